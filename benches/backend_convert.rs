@@ -0,0 +1,56 @@
+//! Cost of moving a store onto another backend:
+//! `cargo bench --bench backend_convert`.
+//!
+//! `builder` converts a [`NaiveBTree`] with [`Quadrille::clone_into_backend`],
+//! which fills the target in place; `insert` copies the entries across
+//! through one committed insert per entry. As a [`SortedVecStore`] copies
+//! itself on every insert, that comparison is at 2k entries; the builder is
+//! also measured at 100k.
+//!
+//! `builder` is linear in the entries, while each of `insert`'s commits
+//! copies everything so far, so `insert` is quadratic and should trail far
+//! behind; `builder` at 100k shows the conversion staying linear.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use quadrille::stores::{NaiveBTree, SortedVecStore};
+use quadrille::Quadrille;
+
+fn source(n: u32) -> Quadrille<NaiveBTree> {
+    let kv = Quadrille::new();
+    let mut tx = kv.transaction();
+    tx.extend((0..n).map(|i| (i.to_be_bytes().to_vec(), vec![0; 16])));
+    tx.commit().unwrap();
+    kv
+}
+
+fn inserted(kv: &Quadrille<NaiveBTree>) -> Quadrille<SortedVecStore> {
+    let target = Quadrille::new();
+    for (key, val) in &kv.snapshot() {
+        let mut tx = target.transaction();
+        tx.insert(key, val).unwrap();
+        tx.commit().unwrap();
+    }
+    target
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("backend_convert");
+    group.sample_size(10);
+    for n in [2_000, 100_000] {
+        let kv = source(n);
+        group.bench_with_input(BenchmarkId::new("builder", n), &kv, |b, kv| {
+            b.iter(|| black_box(kv.clone_into_backend::<SortedVecStore>()))
+        });
+        if n <= 2_000 {
+            group.bench_with_input(BenchmarkId::new("insert", n), &kv, |b, kv| {
+                b.iter(|| black_box(inserted(kv)))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,72 @@
+//! Cost of building a store from scratch: `cargo bench --bench bulk_build`.
+//!
+//! `builder` pushes every entry through [`KVStore::builder`], which writes
+//! in place; `insert` folds them through [`KVStore::insert`], which leaves
+//! each previous version intact. A [`NaiveBTree`] copies its whole map on
+//! every `insert`, so that comparison is at 2k entries rather than 100k; a
+//! [`Treap`] only copies a path, and has no in-place builder.
+//!
+//! On a [`NaiveBTree`], `builder` should beat `insert` by orders of
+//! magnitude, the copies growing with the entries so far. On a [`Treap`]
+//! the two paths do the same work and should match.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use quadrille::stores::{NaiveBTree, Treap};
+use quadrille::KVStore;
+
+fn entries(n: u32) -> Vec<(Vec<u8>, Vec<u8>)> {
+    // a fixed permutation, so neither path gets keys in order
+    (0..n)
+        .map(|i| {
+            ((u64::from(i) * 7919 % u64::from(n)) as u32)
+                .to_be_bytes()
+                .to_vec()
+        })
+        .map(|k| (k.clone(), k))
+        .collect()
+}
+
+fn built<T: KVStore>(entries: &[(Vec<u8>, Vec<u8>)]) -> T {
+    let mut builder = T::builder();
+    builder.extend(entries.iter().cloned());
+    builder.finish()
+}
+
+fn inserted<T: KVStore>(entries: &[(Vec<u8>, Vec<u8>)]) -> T {
+    entries
+        .iter()
+        .cloned()
+        .fold(T::default(), |store, (k, v)| store.insert(k, v).0)
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bulk_build");
+    group.sample_size(10);
+    for n in [2_000, 100_000] {
+        let entries = entries(n);
+        group.bench_with_input(
+            BenchmarkId::new("naive_btree/builder", n),
+            &entries,
+            |b, e| b.iter(|| black_box(built::<NaiveBTree>(e))),
+        );
+        if n <= 2_000 {
+            group.bench_with_input(
+                BenchmarkId::new("naive_btree/insert", n),
+                &entries,
+                |b, e| b.iter(|| black_box(inserted::<NaiveBTree>(e))),
+            );
+        }
+        group.bench_with_input(BenchmarkId::new("treap/builder", n), &entries, |b, e| {
+            b.iter(|| black_box(built::<Treap>(e)))
+        });
+        group.bench_with_input(BenchmarkId::new("treap/insert", n), &entries, |b, e| {
+            b.iter(|| black_box(inserted::<Treap>(e)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+//! Tiny transactions committed immediately or through a [`Coalescer`]:
+//! `cargo bench --features coalesce --bench coalesce`.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use quadrille::stores::NaiveBTree;
+use quadrille::{Coalescer, Quadrille};
+
+const THREADS: u64 = 4;
+const TRANSACTIONS: u64 = 10_000;
+
+fn immediate(kv: &Quadrille<NaiveBTree>, key: Vec<u8>) {
+    loop {
+        let mut tx = kv.transaction();
+        tx.insert(key.clone(), vec![1]).unwrap();
+        if tx.commit().is_ok() {
+            return;
+        }
+    }
+}
+
+fn coalesced(kv: &Quadrille<NaiveBTree>, coalescer: &Coalescer<NaiveBTree>, key: Vec<u8>) {
+    let mut tx = kv.transaction();
+    tx.insert(key, vec![1]).unwrap();
+    coalescer.submit(tx).wait().unwrap();
+}
+
+/// Commits `TRANSACTIONS` single-key transactions across `THREADS` threads.
+fn run(coalesce: bool) -> Duration {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let coalescer = kv.coalescer();
+    let start = Instant::now();
+    thread::scope(|s| {
+        for t in 0..THREADS {
+            let (kv, coalescer) = (&kv, &coalescer);
+            s.spawn(move || {
+                for i in 0..TRANSACTIONS / THREADS {
+                    let key = ((t << 32) | (i % 64)).to_be_bytes().to_vec();
+                    match coalesce {
+                        true => coalesced(kv, coalescer, key),
+                        false => immediate(kv, key),
+                    }
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("coalesce");
+    group.sample_size(10);
+    group.bench_function("immediate", |b| {
+        b.iter_custom(|iters| (0..iters).map(|_| run(false)).sum())
+    });
+    group.bench_function("coalesced", |b| {
+        b.iter_custom(|iters| (0..iters).map(|_| run(true)).sum())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
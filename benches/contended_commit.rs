@@ -0,0 +1,123 @@
+//! Commit throughput under contention: `cargo bench --bench contended_commit`.
+//!
+//! Each thread repeatedly runs a read-modify-write transaction on one key.
+//! With probability `overlap` the key comes from a small hot set shared by
+//! every thread, otherwise from keys only that thread touches. Every commit
+//! replaces the single root, so even at zero overlap concurrent commits
+//! conflict; those are merged by [`LastWriteWins`] and retried cheaply. As
+//! overlap grows, more conflicts hit a key the transaction read, which fails
+//! the commit and reruns the whole transaction, so throughput falls while
+//! the p99 latency climbs.
+//!
+//! The `contended_commit` group reports throughput in transactions per
+//! second; in `contended_commit_p99` the time per iteration is the p99
+//! latency of one transaction.
+
+use std::hint::black_box;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use quadrille::stores::NaiveBTree;
+use quadrille::{LastWriteWins, Quadrille};
+
+const THREADS: usize = 4;
+const HOT_KEYS: u64 = 4;
+
+struct Report {
+    elapsed: Duration,
+    latencies: Vec<Duration>,
+}
+
+impl Report {
+    fn p99(&self) -> Duration {
+        let mut sorted = self.latencies.clone();
+        sorted.sort_unstable();
+        sorted[(sorted.len() * 99 / 100).min(sorted.len() - 1)]
+    }
+}
+
+/// Runs `per_thread` transactions on each of `threads` threads, each picking
+/// a shared hot key with probability `overlap`.
+fn contended_commit(threads: usize, overlap: f64, per_thread: u64) -> Report {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(LastWriteWins)
+        .build();
+    let start = Instant::now();
+    let latencies = thread::scope(|s| {
+        let workers: Vec<_> = (0..threads)
+            .map(|t| {
+                let kv = kv.clone();
+                s.spawn(move || {
+                    let mut rng = 0x9E37_79B9_7F4A_7C15 ^ (t as u64 + 1);
+                    (0..per_thread)
+                        .map(|i| {
+                            let r = xorshift(&mut rng);
+                            let key = if (r % 1000) as f64 / 1000.0 < overlap {
+                                (r >> 32) % HOT_KEYS
+                            } else {
+                                HOT_KEYS + (i % HOT_KEYS) * threads as u64 + t as u64
+                            };
+                            let begin = Instant::now();
+                            increment(&kv, &key.to_be_bytes());
+                            begin.elapsed()
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect()
+    });
+    Report {
+        elapsed: start.elapsed(),
+        latencies,
+    }
+}
+
+fn increment(kv: &Quadrille<NaiveBTree>, key: &[u8]) {
+    loop {
+        let mut tx = kv.transaction();
+        let n = tx.get(key).map_or(0, |v| v[0]);
+        tx.insert(key.to_vec(), vec![n.wrapping_add(1)]).unwrap();
+        if tx.commit().is_ok() {
+            return;
+        }
+    }
+}
+
+fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+fn bench(c: &mut Criterion) {
+    const OVERLAPS: [f64; 4] = [0.0, 0.1, 0.5, 1.0];
+    let mut group = c.benchmark_group("contended_commit");
+    // each iteration is one transaction on every thread
+    group.throughput(Throughput::Elements(THREADS as u64));
+    for overlap in OVERLAPS {
+        group.bench_with_input(BenchmarkId::from_parameter(overlap), &overlap, |b, &o| {
+            b.iter_custom(|iters| black_box(contended_commit(THREADS, o, iters)).elapsed)
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("contended_commit_p99");
+    for overlap in OVERLAPS {
+        group.bench_with_input(BenchmarkId::from_parameter(overlap), &overlap, |b, &o| {
+            b.iter_custom(|iters| {
+                let report = black_box(contended_commit(THREADS, o, iters));
+                report.p99() * iters as u32
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,54 @@
+//! Cost of a transaction that mostly removes keys:
+//! `cargo bench --bench delete_heavy`.
+//!
+//! A transaction's first write copies the state it began on; after that the
+//! copy is its own, and `in_place` writes to it directly. `copying` goes
+//! through [`KVStore::remove`] for every key, as transactions used to, so
+//! each remove from a [`NaiveBTree`] copies the whole map.
+//!
+//! Both remove every other key of 2k. `in_place` copies the map once, and
+//! `copying` once per remove, so `copying` should be slower by roughly the
+//! thousand removes.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use quadrille::stores::NaiveBTree;
+use quadrille::{KVStore, Quadrille};
+
+const N: u32 = 2_000;
+
+fn base() -> NaiveBTree {
+    let mut builder = NaiveBTree::builder();
+    builder.extend((0..N).map(|i| (i.to_be_bytes().to_vec(), vec![0; 16])));
+    builder.finish()
+}
+
+fn bench(c: &mut Criterion) {
+    let kv = Quadrille::builder().initial(base()).build();
+    let mut group = c.benchmark_group("delete_heavy");
+    group.sample_size(10);
+    group.bench_function("in_place", |b| {
+        b.iter(|| {
+            let mut tx = kv.transaction();
+            for i in (0..N).step_by(2) {
+                tx.remove(&i.to_be_bytes()).unwrap();
+            }
+            black_box(tx);
+        })
+    });
+    let base = base();
+    group.bench_function("copying", |b| {
+        b.iter(|| {
+            let mut state = base.retain(|_, _| true).0;
+            for i in (0..N).step_by(2) {
+                state = state.remove(&i.to_be_bytes()).0;
+            }
+            black_box(state);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
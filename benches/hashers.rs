@@ -0,0 +1,75 @@
+//! `NaiveHashMap` with its default hasher against a fast one:
+//! `cargo bench --bench hashers`.
+//!
+//! `insert` builds a store of `n` entries in one `insert_many`, `get` reads
+//! each of them back.
+//!
+//! With 12-byte keys the hasher barely matters: FNV-1a is within about 20%
+//! of `RandomState` either way, as copying entries and cache misses
+//! dominate. Longer keys or cheaper values favour the faster hasher more.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use quadrille::stores::NaiveHashMap;
+use quadrille::KVStore;
+
+/// 64-bit FNV-1a: fast on short keys, but trivially collided by anyone
+/// choosing them.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+type Fast = BuildHasherDefault<Fnv>;
+
+fn entries(n: u32) -> Vec<(Vec<u8>, Vec<u8>)> {
+    (0..n)
+        .map(|i| (format!("key-{i:08}").into_bytes(), vec![0; 16]))
+        .collect()
+}
+
+fn bench_hasher<S: BuildHasher + Default + Clone>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group(format!("hashers/{name}"));
+    for n in [1_000, 100_000] {
+        let entries = entries(n);
+        group.bench_with_input(BenchmarkId::new("insert", n), &entries, |b, entries| {
+            b.iter(|| NaiveHashMap::<S>::with_capacity(0).insert_many(entries.clone()))
+        });
+        let store = NaiveHashMap::<S>::default().insert_many(entries.clone()).0;
+        group.bench_with_input(BenchmarkId::new("get", n), &entries, |b, entries| {
+            b.iter(|| {
+                entries
+                    .iter()
+                    .filter_map(|(k, _)| store.get(black_box(k)))
+                    .count()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench(c: &mut Criterion) {
+    bench_hasher::<RandomState>(c, "RandomState");
+    bench_hasher::<Fast>(c, "Fnv");
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
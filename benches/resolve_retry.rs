@@ -0,0 +1,79 @@
+//! Cost of resolving a large transaction's conflict:
+//! `cargo bench --bench resolve_retry`.
+//!
+//! A transaction writes `WRITES` keys of a store holding `ENTRIES`, then a
+//! rival commits one unrelated key first, so the transaction's commit has to
+//! resolve once. `diff` resolves by diffing the whole transaction state
+//! against its base, as [`ConflictResolver::resolve`] does; `changes` applies
+//! just the transaction's change set, as [`LastWriteWins`] does on commit.
+//!
+//! Both run on a 100k-entry [`Treap`] at 10 and 1000 writes. `diff` walks
+//! every entry whatever the size of the transaction, while `changes` grows
+//! with the writes alone, so the gap should be widest at 10 writes.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use quadrille::stores::Treap;
+use quadrille::{ConflictResolver, LastWriteWins, Quadrille, QuadrilleError};
+
+const ENTRIES: u32 = 100_000;
+
+/// [`LastWriteWins`] without its change-set shortcut.
+struct Diff;
+
+impl ConflictResolver<Treap> for Diff {
+    fn resolve(&self, base: &Treap, theirs: &Treap, ours: &Treap) -> Result<Treap, QuadrilleError> {
+        LastWriteWins.resolve(base, theirs, ours)
+    }
+}
+
+/// `round` varies the written values, so no commit leaves the store as it
+/// found it.
+fn setup(kv: &Quadrille<Treap>, writes: u32, round: u64) -> quadrille::Transation<Treap> {
+    let round = round.to_be_bytes().to_vec();
+    let mut tx = kv.transaction();
+    // spread the writes over the key space
+    let step = ENTRIES / writes;
+    for i in 0..writes {
+        tx.insert((i * step).to_be_bytes().to_vec(), round.clone())
+            .unwrap();
+    }
+    let mut rival = kv.transaction();
+    rival.insert(b"rival".to_vec(), round).unwrap();
+    rival.commit().unwrap();
+    tx
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("resolve_retry");
+    group.sample_size(20);
+    for writes in [10, 1_000] {
+        for (name, changes) in [("diff", false), ("changes", true)] {
+            let builder = Quadrille::<Treap>::builder();
+            let kv = if changes {
+                builder.resolver(LastWriteWins).build()
+            } else {
+                builder.resolver(Diff).build()
+            };
+            let mut tx = kv.transaction();
+            tx.extend((0..ENTRIES).map(|i| (i.to_be_bytes().to_vec(), vec![0])));
+            tx.commit().unwrap();
+            let mut round = 0;
+            group.bench_with_input(BenchmarkId::new(name, writes), &writes, |b, &writes| {
+                b.iter_batched(
+                    || {
+                        round += 1;
+                        setup(&kv, writes, round)
+                    },
+                    |tx| black_box(tx.commit().unwrap()),
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
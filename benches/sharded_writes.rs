@@ -0,0 +1,49 @@
+//! Write throughput against shard count: `cargo bench --bench sharded_writes`.
+//!
+//! Every thread writes its own keys, so with enough shards most commits land
+//! on a root no other thread is publishing to and never conflict.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use quadrille::stores::NaiveBTree;
+use quadrille::ShardedQuadrille;
+
+const THREADS: u64 = 4;
+
+fn writes(shards: usize, per_thread: u64) -> Duration {
+    let kv = ShardedQuadrille::<NaiveBTree>::new(shards);
+    let start = Instant::now();
+    thread::scope(|s| {
+        for t in 0..THREADS {
+            let kv = kv.clone();
+            s.spawn(move || {
+                for i in 0..per_thread {
+                    let key = (t * 16 + i % 16).to_be_bytes().to_vec();
+                    loop {
+                        let mut tx = kv.transaction();
+                        tx.insert(key.clone(), i.to_be_bytes().to_vec()).unwrap();
+                        if tx.commit().is_ok() {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sharded_writes");
+    for shards in [1, 2, 4, 8, 16] {
+        group.bench_with_input(BenchmarkId::from_parameter(shards), &shards, |b, &n| {
+            b.iter_custom(|iters| writes(n, iters))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
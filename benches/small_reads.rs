@@ -0,0 +1,36 @@
+//! Point reads on small stores: `cargo bench --bench small_reads`.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use quadrille::stores::{NaiveBTree, SortedVecStore};
+use quadrille::KVStore;
+
+fn filled<T: KVStore>(n: u32) -> T {
+    T::default()
+        .insert_many(
+            (0..n)
+                .map(|i| (i.to_be_bytes().to_vec(), vec![0; 16]))
+                .collect(),
+        )
+        .0
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("small_reads");
+    for n in [16, 128, 512] {
+        let keys: Vec<_> = (0..n).map(|i: u32| i.to_be_bytes()).collect();
+        let naive = filled::<NaiveBTree>(n);
+        group.bench_with_input(BenchmarkId::new("NaiveBTree", n), &keys, |b, keys| {
+            b.iter(|| keys.iter().filter_map(|k| naive.get(black_box(k))).count())
+        });
+        let sorted = filled::<SortedVecStore>(n);
+        group.bench_with_input(BenchmarkId::new("SortedVecStore", n), &keys, |b, keys| {
+            b.iter(|| keys.iter().filter_map(|k| sorted.get(black_box(k))).count())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
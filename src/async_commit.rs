@@ -0,0 +1,57 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::{trace, Attempt, KVStore, Quadrille, QuadrilleError, Transation};
+
+impl<T: KVStore> Transation<T> {
+    /// Like [`commit`](Self::commit), but between conflicting attempts the
+    /// task yields to its executor instead of spinning, so a contended
+    /// commit lets other tasks on the thread run.
+    ///
+    /// The future holds nothing but the transaction across its awaits, so
+    /// it is `Send` whenever `T: Send + Sync` and can be spawned on a
+    /// multi-threaded executor. Publishing happens within a single poll, so
+    /// dropping the future before it completes abandons the commit cleanly,
+    /// like dropping the transaction: nothing is published and every state
+    /// it held is released.
+    pub async fn commit_async(mut self) -> Result<Quadrille<T>, QuadrilleError> {
+        let deadline = self
+            .shared
+            .config
+            .commit_timeout
+            .map(|t| self.shared.now() + t);
+        let mut attempt = 0;
+        loop {
+            match self.attempt(&|_: &T| true, &mut attempt, deadline)? {
+                Attempt::Published => {
+                    trace::event!(attempts = attempt + 1, "committed");
+                    break;
+                }
+                Attempt::Unchanged => break,
+                Attempt::Conflict => {
+                    YieldNow(false).await;
+                    self.reconcile(attempt)?;
+                }
+            }
+        }
+        Ok(self.finish())
+    }
+}
+
+/// Pending exactly once, waking itself so the executor polls it again after
+/// running whatever else is ready.
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
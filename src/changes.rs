@@ -0,0 +1,69 @@
+use std::collections::btree_map;
+use std::collections::BTreeMap;
+
+/// A single buffered write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Set(Vec<u8>),
+    Delete,
+}
+
+/// The net writes a transaction has made, by key: later writes to a key
+/// replace earlier ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeSet(BTreeMap<Vec<u8>, Change>);
+
+impl ChangeSet {
+    pub fn get(&self, key: &[u8]) -> Option<&Change> {
+        self.0.get(key)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// Number of distinct keys written.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Bytes of keys and set values written.
+    pub fn bytes(&self) -> usize {
+        self.0
+            .iter()
+            .map(|(k, c)| match c {
+                Change::Set(v) => k.len() + v.len(),
+                Change::Delete => k.len(),
+            })
+            .sum()
+    }
+
+    pub fn iter(&self) -> btree_map::Iter<'_, Vec<u8>, Change> {
+        self.0.iter()
+    }
+
+    pub(crate) fn set(&mut self, key: Vec<u8>, val: Vec<u8>) {
+        self.0.insert(key, Change::Set(val));
+    }
+
+    pub(crate) fn delete(&mut self, key: Vec<u8>) {
+        self.0.insert(key, Change::Delete);
+    }
+
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&[u8], &Change) -> bool) {
+        self.0.retain(|key, change| keep(key, change));
+    }
+}
+
+impl<'a> IntoIterator for &'a ChangeSet {
+    type Item = (&'a Vec<u8>, &'a Change);
+    type IntoIter = btree_map::Iter<'a, Vec<u8>, Change>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
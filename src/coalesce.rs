@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::Instant;
+
+use crate::{Change, ChangeSet, Isolation, KVStore, Quadrille, QuadrilleError, Transation};
+
+/// A transaction's writes, waiting for the flusher.
+struct Pending {
+    changes: ChangeSet,
+    ttls: BTreeMap<Vec<u8>, Option<Instant>>,
+    done: Sender<Result<(), QuadrilleError>>,
+}
+
+/// Funnels transactions to a single background writer, which applies
+/// whatever has queued up since its last commit in one `compare_swap`.
+/// Under many small concurrent commits this trades a little latency for far
+/// fewer conflicts. See [`Quadrille::coalescer`].
+///
+/// Coalesced transactions are applied as blind writes, in submission order:
+/// their reads are not checked against concurrent commits, and where two
+/// write the same key the later submission wins.
+pub struct Coalescer<T: KVStore> {
+    queue: Sender<Pending>,
+    _store: std::marker::PhantomData<T>,
+}
+
+impl<T: KVStore> Clone for Coalescer<T> {
+    fn clone(&self) -> Self {
+        Coalescer {
+            queue: self.queue.clone(),
+            _store: std::marker::PhantomData,
+        }
+    }
+}
+
+/// Resolves once a submitted transaction's batch has been committed.
+pub struct CommitHandle(Receiver<Result<(), QuadrilleError>>);
+
+impl CommitHandle {
+    /// Blocks until the batch lands, returning its commit result, or
+    /// [`QuadrilleError::Closed`] if the writer died first.
+    pub fn wait(self) -> Result<(), QuadrilleError> {
+        self.0.recv().unwrap_or(Err(QuadrilleError::Closed))
+    }
+}
+
+impl<T: KVStore + Send + Sync + 'static> Coalescer<T> {
+    pub(crate) fn new(kv: Quadrille<T>) -> Coalescer<T> {
+        let (queue, pending) = channel();
+        thread::spawn(move || flush(kv, pending));
+        Coalescer {
+            queue,
+            _store: std::marker::PhantomData,
+        }
+    }
+
+    /// Queues `tx`'s writes for the next batch.
+    pub fn submit(&self, tx: Transation<T>) -> CommitHandle {
+        let (done, handle) = channel();
+        let pending = Pending {
+            changes: tx.changes,
+            ttls: tx.ttls,
+            done,
+        };
+        if let Err(err) = self.queue.send(pending) {
+            // keeps `handle` from waiting forever on a dead flusher
+            drop(err.0.done);
+        }
+        CommitHandle(handle)
+    }
+}
+
+/// Runs until every [`Coalescer`] for `kv` is dropped.
+fn flush<T: KVStore>(kv: Quadrille<T>, pending: Receiver<Pending>) {
+    while let Ok(first) = pending.recv() {
+        let mut batch = vec![first];
+        batch.extend(pending.try_iter());
+        let result = apply(&kv, &batch).and_then(Transation::commit);
+        match result {
+            Ok(_) => {
+                for p in batch {
+                    let _ = p.done.send(Ok(()));
+                }
+            }
+            // isolate whichever transaction spoiled the batch
+            Err(_) => {
+                for p in batch {
+                    let result = apply(&kv, std::slice::from_ref(&p)).and_then(Transation::commit);
+                    let _ = p.done.send(result.map(drop));
+                }
+            }
+        }
+    }
+}
+
+fn apply<T: KVStore>(
+    kv: &Quadrille<T>,
+    batch: &[Pending],
+) -> Result<Transation<T>, QuadrilleError> {
+    // blind writes: a concurrent commit to the same keys is written over
+    let mut tx = kv.transaction_with_isolation(Isolation::ReadCommitted);
+    for p in batch {
+        for (key, change) in &p.changes {
+            match change {
+                Change::Set(val) => tx.insert(key.clone(), val.clone())?,
+                Change::Delete => tx.remove(key)?,
+            };
+        }
+        tx.ttls.extend(p.ttls.iter().map(|(k, d)| (k.clone(), *d)));
+    }
+    Ok(tx)
+}
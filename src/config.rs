@@ -0,0 +1,307 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::validate::Check;
+use crate::{
+    AtomicRoot, ChangeSet, Clock, ConflictResolver, KVStore, Quadrille, QuadrilleError, Shared,
+    SystemClock, Validate,
+};
+
+/// Fetches a value from outside the store on a transaction miss.
+pub type Loader = Arc<dyn Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync>;
+
+/// Checks the state a commit would publish, along with the changes that
+/// produced it, and may veto the commit by returning an error.
+pub type Validator<T> = Arc<dyn Fn(&T, &ChangeSet) -> Result<(), QuadrilleError> + Send + Sync>;
+
+/// Merges concurrent writes to a commutative key, given its value in the
+/// transaction's basis, in the concurrently committed state, and in the
+/// transaction, each `None` if absent. Returns the value to commit, `None`
+/// deleting the key.
+pub type Combiner =
+    Arc<dyn Fn(Option<&[u8]>, Option<&[u8]>, Option<&[u8]>) -> Option<Vec<u8>> + Send + Sync>;
+
+/// Decides whether two values written to the same key are the same, so
+/// that concurrent writes of them do not conflict.
+pub type ValueEq = Arc<dyn Fn(&[u8], &[u8]) -> bool + Send + Sync>;
+
+/// A named check on the whole state, run on commits that wrote a key under
+/// one of `keys`, see [`QuadrilleBuilder::invariant`].
+pub(crate) struct Invariant<T> {
+    pub(crate) name: String,
+    pub(crate) keys: Vec<Vec<u8>>,
+    pub(crate) check: Arc<dyn Fn(&T) -> bool + Send + Sync>,
+}
+
+impl<T> Clone for Invariant<T> {
+    fn clone(&self) -> Self {
+        Invariant {
+            name: self.name.clone(),
+            keys: self.keys.clone(),
+            check: self.check.clone(),
+        }
+    }
+}
+
+/// Store-wide settings shared by every handle and transaction of a `Quadrille`.
+pub(crate) struct Config<T> {
+    pub(crate) loader: Option<Loader>,
+    pub(crate) validator: Option<Validator<T>>,
+    pub(crate) resolver: Option<Arc<dyn ConflictResolver<T>>>,
+    pub(crate) commit_timeout: Option<Duration>,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) record_sizes: bool,
+    pub(crate) retain_versions: usize,
+    pub(crate) key_check: Option<Check>,
+    pub(crate) value_check: Option<Check>,
+    /// Key prefixes whose concurrent writes are merged instead of conflicting.
+    pub(crate) commutative: Vec<(Vec<u8>, Combiner)>,
+    pub(crate) invariants: Vec<Invariant<T>>,
+    pub(crate) max_entries: Option<usize>,
+    /// Byte equality if unset.
+    pub(crate) value_eq: Option<ValueEq>,
+    /// Conflicts after which a commit takes a turn, see
+    /// [`QuadrilleBuilder::fair_commits`].
+    pub(crate) fair_after: Option<u32>,
+}
+
+impl<T> Clone for Config<T> {
+    fn clone(&self) -> Self {
+        Config {
+            loader: self.loader.clone(),
+            validator: self.validator.clone(),
+            resolver: self.resolver.clone(),
+            commit_timeout: self.commit_timeout,
+            clock: self.clock.clone(),
+            record_sizes: self.record_sizes,
+            retain_versions: self.retain_versions,
+            key_check: self.key_check,
+            value_check: self.value_check,
+            commutative: self.commutative.clone(),
+            invariants: self.invariants.clone(),
+            max_entries: self.max_entries,
+            value_eq: self.value_eq.clone(),
+            fair_after: self.fair_after,
+        }
+    }
+}
+
+impl<T> Default for Config<T> {
+    fn default() -> Self {
+        Config {
+            loader: None,
+            validator: None,
+            resolver: None,
+            commit_timeout: None,
+            clock: Arc::new(SystemClock),
+            record_sizes: false,
+            retain_versions: 0,
+            key_check: None,
+            value_check: None,
+            commutative: Vec::new(),
+            invariants: Vec::new(),
+            max_entries: None,
+            value_eq: None,
+            fair_after: None,
+        }
+    }
+}
+
+/// Configures a [`Quadrille`] before construction, see [`Quadrille::builder`].
+pub struct QuadrilleBuilder<T: KVStore> {
+    config: Config<T>,
+    capacity: usize,
+    initial: Option<T>,
+}
+
+impl<T: KVStore> QuadrilleBuilder<T> {
+    pub(crate) fn new() -> QuadrilleBuilder<T> {
+        QuadrilleBuilder {
+            config: Config::default(),
+            capacity: 0,
+            initial: None,
+        }
+    }
+
+    /// Makes the store a read-through cache: [`Transation::get_or_load`]
+    /// calls `loader` for keys the store does not hold.
+    ///
+    /// [`Transation::get_or_load`]: crate::Transation::get_or_load
+    pub fn loader(
+        mut self,
+        loader: impl Fn(&[u8]) -> Option<Vec<u8>> + Send + Sync + 'static,
+    ) -> Self {
+        self.config.loader = Some(Arc::new(loader));
+        self
+    }
+
+    /// Runs `validator` on every commit, on the final state after any
+    /// conflict resolution. An error vetoes the commit and is returned from
+    /// it, leaving the store unchanged.
+    pub fn validator(
+        mut self,
+        validator: impl Fn(&T, &ChangeSet) -> Result<(), QuadrilleError> + Send + Sync + 'static,
+    ) -> Self {
+        self.config.validator = Some(Arc::new(validator));
+        self
+    }
+
+    /// Resolves commit conflicts with `resolver` instead of the store's own
+    /// [`KVStore::resolve`], e.g. [`LastWriteWins`](crate::LastWriteWins) or
+    /// [`FailFast`](crate::FailFast).
+    pub fn resolver(mut self, resolver: impl ConflictResolver<T> + 'static) -> Self {
+        self.config.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Checks every key written with `K`'s [`Validate`] impl, failing the
+    /// write with [`QuadrilleError::InvalidData`] if it is rejected.
+    pub fn validate_keys<K: Validate>(mut self) -> Self {
+        self.config.key_check = Some(K::validate);
+        self
+    }
+
+    /// Checks every value written with `V`'s [`Validate`] impl, failing the
+    /// write with [`QuadrilleError::InvalidData`] if it is rejected.
+    pub fn validate_values<V: Validate>(mut self) -> Self {
+        self.config.value_check = Some(V::validate);
+        self
+    }
+
+    /// Compares values with `eq` rather than byte for byte when deciding
+    /// whether a concurrent commit wrote the same value to a key as a
+    /// conflicting transaction did, e.g. to treat differently formatted but
+    /// equivalent documents as the same. Such writes agree, so they do not
+    /// count as a conflict; which of the equal values is kept is
+    /// unspecified.
+    pub fn value_eq(mut self, eq: impl Fn(&[u8], &[u8]) -> bool + Send + Sync + 'static) -> Self {
+        self.config.value_eq = Some(Arc::new(eq));
+        self
+    }
+
+    /// Marks keys starting with `prefix` as commutative, e.g. counters or
+    /// append-only logs. Concurrent writes to them never fail a commit:
+    /// `combiner` merges them (see [`Combiner`]), and reading them does not
+    /// count towards read conflicts. Transactions that also wrote other keys
+    /// still go through conflict resolution for those.
+    pub fn commutative(
+        mut self,
+        prefix: impl Into<Vec<u8>>,
+        combiner: impl Fn(Option<&[u8]>, Option<&[u8]>, Option<&[u8]>) -> Option<Vec<u8>>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.config
+            .commutative
+            .push((prefix.into(), Arc::new(combiner)));
+        self
+    }
+
+    /// Registers an invariant over the key group `keys`, a list of key
+    /// prefixes: every commit that wrote a key in the group runs `check` on
+    /// the state it would publish, after any conflict resolution, and fails
+    /// with [`QuadrilleError::InvariantViolated`] if it returns false.
+    /// Commits touching none of the group skip the check.
+    pub fn invariant(
+        mut self,
+        name: impl Into<String>,
+        keys: impl IntoIterator<Item = impl Into<Vec<u8>>>,
+        check: impl Fn(&T) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config.invariants.push(Invariant {
+            name: name.into(),
+            keys: keys.into_iter().map(Into::into).collect(),
+            check: Arc::new(check),
+        });
+        self
+    }
+
+    /// Default for how long [`Transation::commit`] keeps retrying conflicts
+    /// before failing with [`QuadrilleError::Timeout`]. Whichever of this and
+    /// the transaction's retry limit runs out first ends the commit.
+    ///
+    /// [`Transation::commit`]: crate::Transation::commit
+    /// [`QuadrilleError::Timeout`]: crate::QuadrilleError::Timeout
+    pub fn commit_timeout(mut self, timeout: Duration) -> Self {
+        self.config.commit_timeout = Some(timeout);
+        self
+    }
+
+    /// Replaces the clock used for commit timeouts and TTL expiry, e.g. with
+    /// a [`TestClock`](crate::TestClock).
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.config.clock = Arc::new(clock);
+        self
+    }
+
+    /// Records the number of keys and bytes written by each committed
+    /// transaction, see [`Quadrille::size_histogram`].
+    pub fn record_sizes(mut self) -> Self {
+        self.config.record_sizes = true;
+        self
+    }
+
+    /// Keeps the last `versions` committed roots, including the current
+    /// one, for [`Quadrille::snapshot_at_version`]. Older roots are released
+    /// as they fall out of the window; with structural sharing that mostly
+    /// frees only what later commits replaced.
+    ///
+    /// Commits then take a short lock around publishing the root, so that
+    /// versions are recorded in commit order. Reads stay lock-free.
+    pub fn retain_versions(mut self, versions: usize) -> Self {
+        self.config.retain_versions = versions;
+        self
+    }
+
+    /// Bounds the store to `max` entries. Once it is full, writes that would
+    /// add a new key fail with [`QuadrilleError::StoreFull`] rather than
+    /// evicting anything; replacing or removing existing keys still works.
+    /// Commits check the bound again on the state they would publish, so
+    /// concurrent inserts cannot overshoot it together.
+    pub fn max_entries(mut self, max: usize) -> Self {
+        self.config.max_entries = Some(max);
+        self
+    }
+
+    /// Keeps commits that repeatedly lose the race for the root from
+    /// starving. A blocking commit that has conflicted `after_conflicts`
+    /// times waits its turn, in the order such commits got there, and then
+    /// retries while every other blocking commit holds off, so it publishes
+    /// within a few more attempts. Off by default, since a commit that is
+    /// slow between attempts then holds everyone else up.
+    ///
+    /// [`Transation::commit_async`](crate::Transation::commit_async) and
+    /// [`Transation::try_commit`](crate::Transation::try_commit) neither
+    /// take nor wait for turns.
+    pub fn fair_commits(mut self, after_conflicts: u32) -> Self {
+        self.config.fair_after = Some(after_conflicts);
+        self
+    }
+
+    /// Builds the initial state with [`KVStore::with_capacity`], to save
+    /// reallocating while bulk loading about `capacity` entries. Backends
+    /// that cannot preallocate ignore it.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Starts the store from `state` instead of an empty one, e.g. a
+    /// [`Logged`](crate::stores::Logged) store carrying its sink. Overrides
+    /// [`capacity`](Self::capacity).
+    pub fn initial(mut self, state: T) -> Self {
+        self.initial = Some(state);
+        self
+    }
+
+    pub fn build(self) -> Quadrille<T> {
+        let capacity = self.capacity;
+        let state = self.initial.unwrap_or_else(|| T::with_capacity(capacity));
+        let root = AtomicRoot::new(state);
+        Quadrille {
+            shared: Arc::new(Shared::new(self.config, &root)),
+            inner: Arc::new(root),
+        }
+    }
+}
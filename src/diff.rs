@@ -0,0 +1,103 @@
+use std::cmp::Ordering::{Equal, Greater, Less};
+use std::iter::Peekable;
+use std::sync::Arc;
+
+use crate::{Change, EntryIter, KVStore, Quadrille, Snapshot, Transation};
+
+impl<T: KVStore> Quadrille<T> {
+    /// The changes that turn `base` into the current state, one at a time in
+    /// key order: a [`Change::Set`] for every key added or given a new
+    /// value, and a [`Change::Delete`] for every key removed.
+    ///
+    /// Both states are walked side by side as the iterator is advanced, so
+    /// memory stays bounded however large the diff. The iterator pins both
+    /// states, and commits made while it is alive do not affect it.
+    pub fn diff_stream(&self, base: &Snapshot<T>) -> impl Iterator<Item = (Vec<u8>, Change)> {
+        DiffStream::new(base.root.clone(), self.inner.get())
+    }
+}
+
+impl<T: KVStore> Transation<T> {
+    /// What committing would change relative to the state this transaction
+    /// began from, in key order, in the same form as
+    /// [`Quadrille::diff_stream`].
+    ///
+    /// Unlike the buffered writes, this compares the states themselves: a
+    /// key set back to the value it started with is not listed.
+    pub fn pending_changes(&self) -> Vec<(Vec<u8>, Change)> {
+        DiffStream::new(self.basis.clone(), self.current.clone()).collect()
+    }
+}
+
+/// Merge-joins the entries of two states of the same store type.
+pub(crate) struct DiffStream<T> {
+    // These borrow from the states below, which keep them valid: declared
+    // first, they are dropped first.
+    old_entries: Peekable<EntryIter<'static>>,
+    new_entries: Peekable<EntryIter<'static>>,
+    _old: Arc<T>,
+    _new: Arc<T>,
+}
+
+impl<T: KVStore> DiffStream<T> {
+    pub(crate) fn new(old: Arc<T>, new: Arc<T>) -> DiffStream<T> {
+        let (old_entries, new_entries) = if Arc::ptr_eq(&old, &new) {
+            (empty(), empty())
+        } else {
+            // SAFETY: each iterator only borrows the state behind its `Arc`,
+            // which lives, unmoved, for as long as the struct holding both.
+            // Entries are copied out before being returned, so nothing
+            // borrowed escapes with the made-up lifetime.
+            unsafe {
+                (
+                    std::mem::transmute::<EntryIter<'_>, EntryIter<'static>>(old.iter_ref()),
+                    std::mem::transmute::<EntryIter<'_>, EntryIter<'static>>(new.iter_ref()),
+                )
+            }
+        };
+        DiffStream {
+            old_entries: old_entries.peekable(),
+            new_entries: new_entries.peekable(),
+            _old: old,
+            _new: new,
+        }
+    }
+}
+
+fn empty() -> EntryIter<'static> {
+    Box::new(std::iter::empty())
+}
+
+impl<T: KVStore> Iterator for DiffStream<T> {
+    type Item = (Vec<u8>, Change);
+
+    fn next(&mut self) -> Option<(Vec<u8>, Change)> {
+        loop {
+            let old = self.old_entries.peek().copied();
+            let new = self.new_entries.peek().copied();
+            let order = match (old, new) {
+                (None, None) => return None,
+                (Some(_), None) => Less,
+                (None, Some(_)) => Greater,
+                (Some((old_key, _)), Some((new_key, _))) => T::compare_keys(old_key, new_key),
+            };
+            match order {
+                Less => {
+                    let (key, _) = self.old_entries.next()?;
+                    return Some((key.to_vec(), Change::Delete));
+                }
+                Greater => {
+                    let (key, val) = self.new_entries.next()?;
+                    return Some((key.to_vec(), Change::Set(val.to_vec())));
+                }
+                Equal => {
+                    let (_, old_val) = self.old_entries.next()?;
+                    let (key, val) = self.new_entries.next()?;
+                    if old_val != val {
+                        return Some((key.to_vec(), Change::Set(val.to_vec())));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use std::sync::{Condvar, Mutex};
+
+/// Commit turns for transactions that keep losing the race for the root,
+/// see [`QuadrilleBuilder::fair_commits`].
+///
+/// A starving commit takes a ticket and waits for it to be served, then
+/// retries with the root to itself: every other commit waits for the
+/// starving ones to finish before its next attempt. Turns cover the whole
+/// root rather than single keys, since any publish moves the root and so
+/// beats whatever is retrying, whichever keys it wrote.
+///
+/// A thread holding a turn neither waits for nor takes another, on any
+/// store: a commit made from inside a starving commit's predicate would
+/// otherwise wait forever for the turn its own thread holds.
+///
+/// [`QuadrilleBuilder::fair_commits`]: crate::QuadrilleBuilder::fair_commits
+#[derive(Default)]
+pub(crate) struct Turnstile {
+    tickets: Mutex<Tickets>,
+    served: Condvar,
+    /// Tickets taken and not yet done, so commits can skip the lock while
+    /// nothing is starving.
+    starving: AtomicUsize,
+}
+
+thread_local! {
+    /// Turns this thread holds.
+    static HELD: Cell<usize> = const { Cell::new(0) };
+}
+
+#[derive(Default)]
+struct Tickets {
+    /// The next ticket to hand out.
+    next: u64,
+    /// The ticket whose turn it is; tickets before it are done.
+    serving: u64,
+}
+
+/// A starving commit's turn, held until it commits or fails.
+pub(crate) struct Turn<'a> {
+    turnstile: &'a Turnstile,
+}
+
+impl Turnstile {
+    /// Takes a ticket and blocks until its turn comes up, or returns `None`
+    /// at once if this thread already holds a turn.
+    pub(crate) fn enter(&self) -> Option<Turn<'_>> {
+        if HELD.get() > 0 {
+            return None;
+        }
+        self.starving.fetch_add(1, SeqCst);
+        let mut tickets = self.tickets.lock().unwrap_or_else(|e| e.into_inner());
+        let ticket = tickets.next;
+        tickets.next += 1;
+        while tickets.serving != ticket {
+            tickets = self.served.wait(tickets).unwrap_or_else(|e| e.into_inner());
+        }
+        HELD.set(HELD.get() + 1);
+        Some(Turn { turnstile: self })
+    }
+
+    /// Blocks while any starving commit is waiting for or taking its turn,
+    /// unless this thread holds a turn.
+    pub(crate) fn yield_to_starving(&self) {
+        // a commit that starts starving just after this check merely misses
+        // one attempt's wait
+        if self.starving.load(SeqCst) == 0 || HELD.get() > 0 {
+            return;
+        }
+        let mut tickets = self.tickets.lock().unwrap_or_else(|e| e.into_inner());
+        while tickets.serving != tickets.next {
+            tickets = self.served.wait(tickets).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        let turnstile = self.turnstile;
+        turnstile
+            .tickets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .serving += 1;
+        turnstile.starving.fetch_sub(1, SeqCst);
+        HELD.set(HELD.get() - 1);
+        turnstile.served.notify_all();
+    }
+}
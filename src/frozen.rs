@@ -0,0 +1,69 @@
+use std::ops::ControlFlow;
+
+use crate::{KVStore, Quadrille, Snapshot};
+
+/// A read-only handle to a [`Quadrille`], see [`Quadrille::freeze`]. It
+/// reads the same root as the handle it came from, so it sees every commit
+/// made through that or any other writable handle, but offers no way to
+/// open a transaction or otherwise change the store.
+///
+/// ```compile_fail
+/// use quadrille::{stores::NaiveBTree, Quadrille};
+///
+/// let frozen = Quadrille::<NaiveBTree>::new().freeze();
+/// frozen.transaction();
+/// ```
+pub struct FrozenQuadrille<T: KVStore> {
+    kv: Quadrille<T>,
+}
+
+impl<T: KVStore> Clone for FrozenQuadrille<T> {
+    fn clone(&self) -> Self {
+        FrozenQuadrille {
+            kv: self.kv.clone(),
+        }
+    }
+}
+
+impl<T: KVStore> Quadrille<T> {
+    /// A handle that can read this store but not write to it, for handing
+    /// to code that must not.
+    pub fn freeze(&self) -> FrozenQuadrille<T> {
+        FrozenQuadrille { kv: self.clone() }
+    }
+}
+
+impl<T: KVStore> FrozenQuadrille<T> {
+    /// See [`Quadrille::get`].
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.kv.get(key)
+    }
+
+    /// See [`Quadrille::multi_get`].
+    pub fn multi_get(&self, keys: &[&[u8]]) -> Vec<Option<Vec<u8>>> {
+        self.kv.multi_get(keys)
+    }
+
+    pub fn len(&self) -> usize {
+        self.kv.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kv.is_empty()
+    }
+
+    /// See [`Quadrille::snapshot`]. Snapshots are read-only already.
+    pub fn snapshot(&self) -> Snapshot<T> {
+        self.kv.snapshot()
+    }
+
+    /// See [`Quadrille::for_each`].
+    pub fn for_each(&self, f: impl FnMut(&[u8], &[u8]) -> ControlFlow<()>) -> ControlFlow<()> {
+        self.kv.for_each(f)
+    }
+
+    /// See [`Quadrille::version`].
+    pub fn version(&self) -> u64 {
+        self.kv.version()
+    }
+}
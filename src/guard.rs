@@ -0,0 +1,85 @@
+use std::ops::{Deref, DerefMut};
+use std::thread;
+
+use crate::{KVStore, Quadrille, QuadrilleError, Transation};
+
+type ErrorHandler = Box<dyn FnOnce(QuadrilleError)>;
+
+/// A transaction that commits when dropped, unless the thread is unwinding
+/// from a panic, in which case its writes are discarded. Create one with
+/// [`Transation::commit_on_drop`]; it derefs to the transaction for reads
+/// and writes.
+///
+/// A drop cannot return an error, so a failed commit in the drop path is
+/// passed to the handler set with [`on_error`](Self::on_error), or otherwise
+/// only reported through `tracing`. Call [`commit`](Self::commit) to commit
+/// early and get the result instead.
+pub struct CommitGuard<T: KVStore> {
+    tx: Option<Transation<T>>,
+    on_error: Option<ErrorHandler>,
+}
+
+impl<T: KVStore> CommitGuard<T> {
+    /// Calls `handler` with the error if the commit on drop fails.
+    pub fn on_error(mut self, handler: impl FnOnce(QuadrilleError) + 'static) -> Self {
+        self.on_error = Some(Box::new(handler));
+        self
+    }
+
+    /// Commits now, returning the result rather than passing it to the
+    /// error handler.
+    pub fn commit(mut self) -> Result<Quadrille<T>, QuadrilleError> {
+        self.tx
+            .take()
+            .expect("guard holds its transaction")
+            .commit()
+    }
+
+    /// Discards the transaction's writes.
+    pub fn rollback(mut self) {
+        self.tx = None;
+    }
+}
+
+impl<T: KVStore> Deref for CommitGuard<T> {
+    type Target = Transation<T>;
+
+    fn deref(&self) -> &Transation<T> {
+        self.tx.as_ref().expect("guard holds its transaction")
+    }
+}
+
+impl<T: KVStore> DerefMut for CommitGuard<T> {
+    fn deref_mut(&mut self) -> &mut Transation<T> {
+        self.tx.as_mut().expect("guard holds its transaction")
+    }
+}
+
+impl<T: KVStore> Drop for CommitGuard<T> {
+    fn drop(&mut self) {
+        let Some(tx) = self.tx.take() else {
+            return;
+        };
+        if thread::panicking() {
+            crate::trace::event!("rolled back on panic");
+            return;
+        }
+        if let Err(err) = tx.commit() {
+            crate::trace::event!(error = ?err, "commit on drop failed");
+            if let Some(handler) = self.on_error.take() {
+                handler(err);
+            }
+        }
+    }
+}
+
+impl<T: KVStore> Transation<T> {
+    /// Wraps the transaction in a [`CommitGuard`], which commits it at the
+    /// end of its scope and rolls it back if that scope panics.
+    pub fn commit_on_drop(self) -> CommitGuard<T> {
+        CommitGuard {
+            tx: Some(self),
+            on_error: None,
+        }
+    }
+}
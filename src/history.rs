@@ -0,0 +1,67 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// The most recently committed roots, oldest first, tagged with the version
+/// each was committed as.
+pub(crate) struct History<T> {
+    retain: usize,
+    roots: Mutex<VecDeque<(u64, Arc<T>)>>,
+}
+
+impl<T> History<T> {
+    pub(crate) fn new(retain: usize) -> History<T> {
+        History {
+            retain,
+            roots: Mutex::new(VecDeque::with_capacity(retain + 1)),
+        }
+    }
+
+    /// Locks the ring buffer. Publishers hold the guard across the root swap
+    /// so versions enter the buffer in the order they were committed.
+    ///
+    /// A commit that panicked while holding the lock cannot have left the
+    /// buffer half-updated, so a poisoned lock is simply taken over rather
+    /// than failing every later commit.
+    pub(crate) fn lock(&self) -> HistoryGuard<'_, T> {
+        HistoryGuard {
+            retain: self.retain,
+            roots: self.roots.lock().unwrap_or_else(|e| e.into_inner()),
+        }
+    }
+
+    pub(crate) fn get(&self, version: u64) -> Option<Arc<T>> {
+        let roots = self.roots.lock().unwrap_or_else(|e| e.into_inner());
+        let oldest = roots.front()?.0;
+        let (v, root) = roots.get(version.checked_sub(oldest)? as usize)?;
+        debug_assert_eq!(*v, version);
+        Some(root.clone())
+    }
+}
+
+impl<T> History<T> {
+    /// Drops all but the newest `keep` roots, returning how many went. The
+    /// window stays as configured: later commits fill it up again.
+    pub(crate) fn compact(&self, keep: usize) -> usize {
+        let mut roots = self.roots.lock().unwrap_or_else(|e| e.into_inner());
+        let excess = roots.len().saturating_sub(keep);
+        let dropped: Vec<_> = roots.drain(..excess).collect();
+        // freeing old versions can take a while, so not under the lock
+        drop(roots);
+        dropped.len()
+    }
+}
+
+pub(crate) struct HistoryGuard<'a, T> {
+    retain: usize,
+    roots: std::sync::MutexGuard<'a, VecDeque<(u64, Arc<T>)>>,
+}
+
+impl<T> HistoryGuard<'_, T> {
+    /// Records `root` as `version`, evicting whatever falls out of the window.
+    pub(crate) fn push(&mut self, version: u64, root: Arc<T>) {
+        self.roots.push_back((version, root));
+        while self.roots.len() > self.retain {
+            self.roots.pop_front();
+        }
+    }
+}
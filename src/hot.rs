@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, RwLock};
+
+/// Number of separately locked parts of the cache.
+const SHARDS: usize = 16;
+/// Keys each part holds before making room for more.
+const SHARD_CAPACITY: usize = 64;
+
+/// Values read through [`Quadrille::hot_get`], each with the version it was
+/// read at. Keys are hashed onto shards behind their own locks, so hits on
+/// different keys rarely wait on each other, and each shard is bounded.
+///
+/// [`Quadrille::hot_get`]: crate::Quadrille::hot_get
+pub(crate) struct HotCache {
+    shards: Box<[Shard]>,
+}
+
+/// Cached results and the versions they were read at.
+type Shard = RwLock<HashMap<Vec<u8>, (u64, Option<Arc<[u8]>>)>>;
+
+impl Default for HotCache {
+    fn default() -> Self {
+        HotCache {
+            shards: (0..SHARDS).map(|_| Default::default()).collect(),
+        }
+    }
+}
+
+impl HotCache {
+    fn shard(&self, key: &[u8]) -> &Shard {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[(hasher.finish() % SHARDS as u64) as usize]
+    }
+
+    /// What `key` held when last read at `version`, or `None` if it has not
+    /// been; an absent key is cached as `Some(None)`.
+    pub(crate) fn get(&self, key: &[u8], version: u64) -> Option<Option<Arc<[u8]>>> {
+        let shard = self.shard(key).read().unwrap_or_else(|e| e.into_inner());
+        let (read_at, val) = shard.get(key)?;
+        (*read_at == version).then(|| val.clone())
+    }
+
+    /// Caches `val` as what `key` held at `version`. A full shard first
+    /// drops keys read at older versions, which can never hit again, and
+    /// failing that an arbitrary key.
+    pub(crate) fn insert(&self, key: &[u8], version: u64, val: Option<Arc<[u8]>>) {
+        let mut shard = self.shard(key).write().unwrap_or_else(|e| e.into_inner());
+        if shard.len() >= SHARD_CAPACITY && !shard.contains_key(key) {
+            shard.retain(|_, (read_at, _)| *read_at == version);
+            if shard.len() >= SHARD_CAPACITY {
+                let victim = shard.keys().next().cloned();
+                if let Some(victim) = victim {
+                    shard.remove(&victim);
+                }
+            }
+        }
+        shard.insert(key.to_vec(), (version, val));
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        let len = |shard: &Shard| shard.read().unwrap().len();
+        self.shards.iter().map(len).sum()
+    }
+}
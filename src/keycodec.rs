@@ -0,0 +1,141 @@
+//! Order-preserving encodings of common key types, so that the byte order
+//! stores sort keys by matches the order of the values they encode and range
+//! scans over encoded keys are meaningful.
+//!
+//! Integers are fixed-width big-endian, with the sign bit of an `i64`
+//! flipped so negatives sort first. Strings are escaped and terminated, so
+//! they can be followed by further parts in a tuple: each `0x00` byte is
+//! written as `0x00 0xFF`, and the string ends with `0x00 0x00`. Tuples are
+//! the concatenation of their parts.
+
+/// A type with an order-preserving, self-delimiting key encoding.
+pub trait KeyPart: Sized {
+    /// Appends the encoding of `self` to `out`.
+    fn encode_into(&self, out: &mut Vec<u8>);
+    /// Decodes one value from the front of `input`, advancing past it.
+    fn decode_from(input: &mut &[u8]) -> Option<Self>;
+}
+
+/// Encodes `key` as a store key.
+pub fn encode<K: KeyPart>(key: &K) -> Vec<u8> {
+    let mut out = Vec::new();
+    key.encode_into(&mut out);
+    out
+}
+
+/// Decodes a key written by [`encode`], failing on malformed or trailing
+/// bytes.
+pub fn decode<K: KeyPart>(mut bytes: &[u8]) -> Option<K> {
+    let key = K::decode_from(&mut bytes)?;
+    bytes.is_empty().then_some(key)
+}
+
+pub fn encode_u64(x: u64) -> Vec<u8> {
+    encode(&x)
+}
+
+pub fn decode_u64(bytes: &[u8]) -> Option<u64> {
+    decode(bytes)
+}
+
+pub fn encode_i64(x: i64) -> Vec<u8> {
+    encode(&x)
+}
+
+pub fn decode_i64(bytes: &[u8]) -> Option<i64> {
+    decode(bytes)
+}
+
+pub fn encode_str(s: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(s.len() + 2);
+    encode_str_into(s, &mut out);
+    out
+}
+
+pub fn decode_str(bytes: &[u8]) -> Option<String> {
+    decode(bytes)
+}
+
+fn encode_str_into(s: &str, out: &mut Vec<u8>) {
+    for &b in s.as_bytes() {
+        out.push(b);
+        if b == 0 {
+            out.push(0xFF);
+        }
+    }
+    out.extend_from_slice(&[0, 0]);
+}
+
+fn take<const N: usize>(input: &mut &[u8]) -> Option<[u8; N]> {
+    let (head, rest) = input.split_first_chunk()?;
+    *input = rest;
+    Some(*head)
+}
+
+impl KeyPart for u64 {
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+
+    fn decode_from(input: &mut &[u8]) -> Option<Self> {
+        take(input).map(u64::from_be_bytes)
+    }
+}
+
+impl KeyPart for i64 {
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        ((*self as u64) ^ (1 << 63)).encode_into(out);
+    }
+
+    fn decode_from(input: &mut &[u8]) -> Option<Self> {
+        u64::decode_from(input).map(|x| (x ^ (1 << 63)) as i64)
+    }
+}
+
+impl KeyPart for String {
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        encode_str_into(self, out);
+    }
+
+    fn decode_from(input: &mut &[u8]) -> Option<Self> {
+        let mut bytes = Vec::new();
+        loop {
+            match take(input)? {
+                [0] => match take(input)? {
+                    [0] => break,
+                    [0xFF] => bytes.push(0),
+                    _ => return None,
+                },
+                [b] => bytes.push(b),
+            }
+        }
+        String::from_utf8(bytes).ok()
+    }
+}
+
+impl<A: KeyPart, B: KeyPart> KeyPart for (A, B) {
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        self.0.encode_into(out);
+        self.1.encode_into(out);
+    }
+
+    fn decode_from(input: &mut &[u8]) -> Option<Self> {
+        Some((A::decode_from(input)?, B::decode_from(input)?))
+    }
+}
+
+impl<A: KeyPart, B: KeyPart, C: KeyPart> KeyPart for (A, B, C) {
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        self.0.encode_into(out);
+        self.1.encode_into(out);
+        self.2.encode_into(out);
+    }
+
+    fn decode_from(input: &mut &[u8]) -> Option<Self> {
+        Some((
+            A::decode_from(input)?,
+            B::decode_from(input)?,
+            C::decode_from(input)?,
+        ))
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{KVStore, QuadrilleError, Transation};
+
+/// Bytes per chunk of a value written with [`Transation::insert_large`].
+pub const LARGE_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Keys starting with this are reserved for the chunks of large values:
+/// the prefix, the big-endian `u32` length of the value's key, the key, and
+/// the big-endian chunk index. Length-prefixing the key keeps one value's
+/// chunks apart from another's, and sorts them together and in order.
+pub const LARGE_CHUNK_PREFIX: &[u8] = b"\xffquadrille:large\x00";
+
+/// Marks a manifest, ahead of its big-endian `u32` chunk count, so plain
+/// values are not mistaken for one.
+const MANIFEST_TAG: &[u8] = b"\xffQLV";
+
+/// The prefix every chunk of the value at `key` starts with.
+fn chunk_prefix(key: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(LARGE_CHUNK_PREFIX.len() + 4 + key.len() + 4);
+    out.extend_from_slice(LARGE_CHUNK_PREFIX);
+    out.extend_from_slice(&(key.len() as u32).to_be_bytes());
+    out.extend_from_slice(key);
+    out
+}
+
+fn chunk_key(key: &[u8], index: u32) -> Vec<u8> {
+    let mut out = chunk_prefix(key);
+    out.extend_from_slice(&index.to_be_bytes());
+    out
+}
+
+fn manifest(count: u32) -> Vec<u8> {
+    [MANIFEST_TAG, &count.to_be_bytes()].concat()
+}
+
+fn chunk_count(manifest: &[u8]) -> Result<u32, QuadrilleError> {
+    manifest
+        .strip_prefix(MANIFEST_TAG)
+        .and_then(|count| count.try_into().ok())
+        .map(u32::from_be_bytes)
+        .ok_or_else(|| QuadrilleError::InvalidData("not a large value manifest".into()))
+}
+
+impl<T: KVStore> Transation<T> {
+    /// Stores `val` split into [`LARGE_CHUNK_SIZE`] chunks under
+    /// [`LARGE_CHUNK_PREFIX`], with a manifest at `key` itself recording how
+    /// many there are. Chunks left over from a longer value previously stored
+    /// at `key` are removed. All of it commits, or fails, with the
+    /// transaction. Fails with [`QuadrilleError::InvalidData`], writing
+    /// nothing, if `key` already holds a plain value.
+    ///
+    /// Read it back with [`get_large`](Self::get_large); a plain
+    /// [`get`](Self::get) of `key` returns only the manifest.
+    pub fn insert_large(&mut self, key: Vec<u8>, val: &[u8]) -> Result<(), QuadrilleError> {
+        if let Some(old) = self.get(&key) {
+            chunk_count(&old)?;
+        }
+        let count = u32::try_from(val.len().div_ceil(LARGE_CHUNK_SIZE))
+            .map_err(|_| QuadrilleError::InvalidData("large value has too many chunks".into()))?;
+        // the chunks actually stored, whatever the old manifest claims
+        let prefix = chunk_prefix(&key);
+        let stale: Vec<_> = self
+            .scan_prefix(&prefix)
+            .into_iter()
+            .map(|(k, _)| k)
+            .filter(|k| k[prefix.len()..] >= count.to_be_bytes()[..])
+            .collect();
+        let mut entries: Vec<_> = val
+            .chunks(LARGE_CHUNK_SIZE)
+            .zip(0..)
+            .map(|(chunk, i)| (chunk_key(&key, i), chunk.to_vec()))
+            .collect();
+        entries.push((key, manifest(count)));
+        self.insert_many(entries)?;
+        for chunk in stale {
+            self.remove(&chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Reassembles a value stored with [`insert_large`](Self::insert_large).
+    /// Fails with [`QuadrilleError::InvalidData`] if `key` holds something
+    /// other than a manifest, or a chunk it lists is missing.
+    pub fn get_large(&self, key: &[u8]) -> Result<Option<Vec<u8>>, QuadrilleError> {
+        let Some(manifest) = self.get(key) else {
+            return Ok(None);
+        };
+        let mut out = Vec::new();
+        for i in 0..chunk_count(&manifest)? {
+            let chunk = self
+                .get(&chunk_key(key, i))
+                .ok_or_else(|| QuadrilleError::InvalidData("large value chunk missing".into()))?;
+            out.extend_from_slice(&chunk);
+        }
+        Ok(Some(out))
+    }
+}
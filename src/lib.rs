@@ -1,54 +1,634 @@
-use std::mem::ManuallyDrop;
-use std::ops::Deref;
-use std::sync::Arc;
-use std::sync::atomic::AtomicPtr;
-use std::sync::atomic::Ordering::Relaxed;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ops::{Bound, ControlFlow};
+use std::sync::atomic::Ordering::{AcqRel, Acquire, SeqCst};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::time::{Duration, Instant};
 
-mod stores;
+pub use changes::{Change, ChangeSet};
+pub use clock::{Clock, SystemClock, TestClock};
+#[cfg(feature = "coalesce")]
+pub use coalesce::{Coalescer, CommitHandle};
+use config::Config;
+pub use config::{Combiner, Loader, QuadrilleBuilder, Validator, ValueEq};
+use fairness::Turnstile;
+pub use frozen::FrozenQuadrille;
+pub use guard::CommitGuard;
+use history::History;
+use hot::HotCache;
+pub use large::{LARGE_CHUNK_PREFIX, LARGE_CHUNK_SIZE};
+pub use locks::KeyGuard;
+use locks::KeyLocks;
+use merkle::MerkleSource;
+pub use namespace::{Namespace, NamespaceTransation};
+pub use resolver::{ConflictResolver, FailFast, LastWriteWins};
+pub use sharded::{ShardedQuadrille, ShardedTransation};
+pub use snapshot::Snapshot;
+use stats::{AtomicCommitMetrics, AtomicSizeHistogram};
+pub use stats::{CommitMetrics, ConflictInfo, MemoryReport, SizeHistogram, SIZE_BUCKETS};
+pub use store_builder::StoreBuilder;
+pub use validate::Validate;
+
+mod async_commit;
+mod changes;
+mod clock;
+#[cfg(feature = "coalesce")]
+mod coalesce;
+mod config;
+mod diff;
+mod fairness;
+mod frozen;
+mod guard;
+mod history;
+mod hot;
+pub mod keycodec;
+mod large;
+mod locks;
+pub mod merkle;
+mod namespace;
+mod resolver;
+mod sharded;
+mod snapshot;
+mod stats;
+mod store_builder;
+pub mod stores;
 #[cfg(test)]
 mod test;
+mod trace;
+mod validate;
+mod versioned;
+pub mod wal;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum QuadrilleError {
     KeyConflict,
+    /// The transaction's [`Priority`] allowed no further commit attempts.
+    RetryLimit,
+    /// The predicate given to [`Transation::commit_if`] rejected the state.
+    PreconditionFailed,
+    /// [`Transation::insert_new`] found the key already present.
+    KeyExists(Vec<u8>),
+    /// Commit was still conflicting when its timeout elapsed.
+    Timeout,
+    /// A commit was vetoed, e.g. by a [`Validator`].
+    Rejected(String),
+    /// A key or value failed its [`Validate`] check.
+    InvalidData(String),
+    /// Conflict resolution produced a state missing some of the
+    /// transaction's writes. Only checked in debug builds.
+    ResolveLostWrites {
+        keys: Vec<Vec<u8>>,
+    },
+    /// A transaction from [`Quadrille::transaction_for`] wrote a key it had
+    /// not declared.
+    Undeclared(Vec<u8>),
+    /// The invariant registered as `name` with
+    /// [`QuadrilleBuilder::invariant`] rejected the state a commit would
+    /// have published.
+    InvariantViolated {
+        name: String,
+    },
+    /// A resolved state failed [`KVStore::validate_invariants`], meaning the
+    /// backend has a bug. Only checked in debug builds.
+    BrokenStore(String),
+    /// A write would have added a key to a store already holding its
+    /// [`QuadrilleBuilder::max_entries`].
+    StoreFull,
+    /// [`Transation::insert_versioned`] expected the key at a version it is
+    /// no longer, or not yet, at; 0 means absent.
+    VersionMismatch {
+        expected: u64,
+        found: u64,
+    },
+    /// The [`wal`] record starting `offset` bytes into the log failed its
+    /// checksum or could not be decoded.
+    CorruptLog {
+        offset: usize,
+    },
+    /// The coalescer's writer stopped before committing the transaction.
+    Closed,
 }
 pub trait KVStore: Default {
+    /// An empty store with room for about `capacity` entries, for backends
+    /// that can preallocate. Others ignore the hint.
+    fn with_capacity(capacity: usize) -> Self {
+        let _ = capacity;
+        Self::default()
+    }
+    /// A copy with room for about `additional` more entries, or `None` for
+    /// backends that cannot preallocate.
+    fn reserve(&self, additional: usize) -> Option<Self> {
+        let _ = additional;
+        None
+    }
     fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
     fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool);
+    fn remove(&self, key: &[u8]) -> (Self, bool);
+    /// Inserts every entry in one new version, returning how many replaced an
+    /// existing value. Callers never pass an empty `entries`.
+    fn insert_many(&self, entries: Vec<(Vec<u8>, Vec<u8>)>) -> (Self, usize) {
+        let mut iter = entries.into_iter();
+        let (key, val) = iter.next().expect("insert_many needs at least one entry");
+        let (mut out, found) = self.insert(key, val);
+        let mut replaced = usize::from(found);
+        for (key, val) in iter {
+            let (new, found) = out.insert(key, val);
+            out = new;
+            replaced += usize::from(found);
+        }
+        (out, replaced)
+    }
+    /// An empty store to fill with [`StoreBuilder::push`], for bulk loads.
+    fn builder() -> StoreBuilder<Self> {
+        StoreBuilder::new(Self::default())
+    }
+    /// An empty version keeping whatever this one carries besides its
+    /// entries, such as a [`Logged`](stores::Logged) store's sink. Backends
+    /// holding nothing else return their default.
+    fn cleared(&self) -> Self {
+        Self::default()
+    }
+    /// Writes `val` at `key` into this version itself, returning whether it
+    /// replaced a value, for [`StoreBuilder`] and for transactions whose
+    /// working state nothing else shares. Backends that can mutate in place
+    /// should, rather than copying as the default [`insert`](Self::insert)
+    /// does.
+    fn insert_in_place(&mut self, key: Vec<u8>, val: Vec<u8>) -> bool {
+        let (new, found) = self.insert(key, val);
+        *self = new;
+        found
+    }
+    /// Like [`insert_in_place`](Self::insert_in_place), for
+    /// [`remove`](Self::remove).
+    fn remove_in_place(&mut self, key: &[u8]) -> bool {
+        let (new, found) = self.remove(key);
+        *self = new;
+        found
+    }
+    /// Removes every key starting with `prefix`, returning how many there were.
+    fn remove_prefix(&self, prefix: &[u8]) -> (Self, usize) {
+        let keys = self.scan_prefix(prefix);
+        let mut iter = keys.iter().map(|(k, _)| k.as_slice());
+        // when nothing matches, `prefix` itself is absent and this is a copy
+        let (mut out, _) = self.remove(iter.next().unwrap_or(prefix));
+        for key in iter {
+            out = out.remove(key).0;
+        }
+        (out, keys.len())
+    }
+    /// Keeps only the entries for which `pred` holds, returning how many were
+    /// removed.
+    fn retain(&self, pred: impl Fn(&[u8], &[u8]) -> bool) -> (Self, usize) {
+        let mut removed = 0;
+        let out = self.fold(self.cleared(), |out, key, val| {
+            if pred(key, val) {
+                out.insert(key.to_vec(), val.to_vec()).0
+            } else {
+                removed += 1;
+                out
+            }
+        });
+        (out, removed)
+    }
+    /// Number of entries.
+    fn len(&self) -> usize {
+        self.fold(0, |n, _, _| n + 1)
+    }
+    /// Whether there are no entries, without counting them.
+    fn is_empty(&self) -> bool {
+        self.iter_ref().next().is_none()
+    }
+    /// Whether `key` is present. An empty value still counts as present.
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.get(key).is_some()
+    }
     fn resolve(basis: Arc<Self>, prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError>;
+    /// Entries with keys between `start` and `end`, in the store's key order.
+    /// Empty or inverted bounds yield no entries.
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)>;
+    /// Every entry in key order, borrowed from the store rather than copied.
+    fn iter_ref(&self) -> EntryIter<'_>;
+    /// The key order [`iter_ref`](Self::iter_ref) and [`range`](Self::range)
+    /// follow. Stores that keep something other than byte order override it.
+    fn compare_keys(a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        a.cmp(b)
+    }
+    /// Keys between `start` and `end`, like [`range`](Self::range) but
+    /// without copying values.
+    fn range_keys(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<Vec<u8>> {
+        self.range(start, end).into_iter().map(|(k, _)| k).collect()
+    }
+    /// Number of entries [`range`](Self::range) would return, ideally
+    /// without copying them.
+    fn range_count(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> usize {
+        self.range(start, end).len()
+    }
+    /// The entry with the largest key not after `key`.
+    fn floor(&self, key: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.range(Bound::Unbounded, Bound::Included(key)).pop()
+    }
+    /// The entry with the smallest key not before `key`.
+    fn ceil(&self, key: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.range(Bound::Included(key), Bound::Unbounded)
+            .into_iter()
+            .next()
+    }
+    /// Entries whose key starts with `prefix`, in the store's key order.
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        match prefix_end(prefix) {
+            Some(end) => self.range(Bound::Included(prefix), Bound::Excluded(&end)),
+            None => self.range(Bound::Included(prefix), Bound::Unbounded),
+        }
+    }
+    /// Folds `f` over every entry in key order.
+    fn fold<A>(&self, init: A, mut f: impl FnMut(A, &[u8], &[u8]) -> A) -> A {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+            .iter()
+            .fold(init, |acc, (k, v)| f(acc, k, v))
+    }
+    /// Calls `f` on every entry in key order until it returns
+    /// [`ControlFlow::Break`], which is then returned.
+    fn try_for_each(&self, mut f: impl FnMut(&[u8], &[u8]) -> ControlFlow<()>) -> ControlFlow<()> {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+            .iter()
+            .try_for_each(|(k, v)| f(k, v))
+    }
+    /// Whether both stores hold the same entries. Persistent stores can
+    /// override this to skip subtrees they share.
+    fn structurally_equal(&self, other: &Self) -> bool {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+            == other.range(Bound::Unbounded, Bound::Unbounded)
+    }
+    /// Root of the Merkle trie over the entries, see [`merkle`]. Equal
+    /// contents give equal roots regardless of the store type.
+    fn merkle_root(&self) -> merkle::Hash {
+        self.merkle_node(&[]).hash()
+    }
+    /// Checks the backend's internal consistency, e.g. that entries kept
+    /// sorted are sorted, describing the first problem found. Debug builds
+    /// check states produced by conflict resolution; see also
+    /// [`stores::fuzz_invariants`]. Stores with nothing to check keep the
+    /// default, which always succeeds.
+    fn validate_invariants(&self) -> Result<(), String> {
+        Ok(())
+    }
+    /// Approximate bytes held by the entries: key and value lengths plus
+    /// [`ENTRY_OVERHEAD`] per entry.
+    fn estimated_size_bytes(&self) -> usize {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+            .iter()
+            .map(|(k, v)| entry_size(k, v))
+            .sum()
+    }
+    /// How much of this version's memory is shared with other live
+    /// versions. Stores without structural sharing report everything, as
+    /// estimated by [`estimated_size_bytes`](Self::estimated_size_bytes), as
+    /// unique.
+    fn memory_report(&self) -> MemoryReport {
+        MemoryReport {
+            unique_bytes: self.estimated_size_bytes(),
+            shared_bytes: 0,
+        }
+    }
+}
+
+/// An owned key and value.
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// Stores that keep keys in their natural byte order and can reach either
+/// end directly.
+pub trait OrderedKVStore: KVStore {
+    /// The entry with the smallest key, if any.
+    fn first(&self) -> Option<(Vec<u8>, Vec<u8>)>;
+    /// The entry with the largest key, if any.
+    fn last(&self) -> Option<(Vec<u8>, Vec<u8>)>;
+    /// Removes the entry with the smallest key, returning it.
+    fn pop_first(&self) -> (Self, Option<Entry>) {
+        let first = self.first();
+        let key = first.as_ref().map_or(&[][..], |(k, _)| k);
+        // an empty store has no `[]` key either, so this is then a copy
+        (self.remove(key).0, first)
+    }
+    /// Removes the entry with the largest key, returning it.
+    fn pop_last(&self) -> (Self, Option<Entry>) {
+        let last = self.last();
+        let key = last.as_ref().map_or(&[][..], |(k, _)| k);
+        (self.remove(key).0, last)
+    }
+}
+
+/// Fixed per-entry cost assumed by [`KVStore::estimated_size_bytes`]: the
+/// key and value `Vec` headers.
+pub const ENTRY_OVERHEAD: usize = 2 * std::mem::size_of::<Vec<u8>>();
+
+pub(crate) fn entry_size(key: &[u8], val: &[u8]) -> usize {
+    key.len() + val.len() + ENTRY_OVERHEAD
 }
 
-#[derive(Clone)]
+/// Smallest key greater than every key starting with `prefix`, or `None` if
+/// there is no such key (the prefix is empty or all `0xFF`).
+pub(crate) fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+/// Whether `start..end` denotes a non-empty interval under `cmp`.
+pub(crate) fn valid_range<K: ?Sized>(
+    start: Bound<&K>,
+    end: Bound<&K>,
+    cmp: impl Fn(&K, &K) -> std::cmp::Ordering,
+) -> bool {
+    use std::cmp::Ordering::*;
+    match (start, end) {
+        (Bound::Unbounded, _) | (_, Bound::Unbounded) => true,
+        (Bound::Included(s), Bound::Included(e)) => cmp(s, e) != Greater,
+        (Bound::Included(s), Bound::Excluded(e))
+        | (Bound::Excluded(s), Bound::Included(e))
+        | (Bound::Excluded(s), Bound::Excluded(e)) => cmp(s, e) == Less,
+    }
+}
+
+/// Borrowed entries of a store, see [`KVStore::iter_ref`].
+pub type EntryIter<'a> = Box<dyn Iterator<Item = (&'a [u8], &'a [u8])> + 'a>;
+
+/// A handle to a store. Cloning a handle shares the store: commits through
+/// either clone are seen by both. Use [`deep_clone`](Self::deep_clone) to
+/// fork an independent store instead.
 pub struct Quadrille<T: KVStore> {
     inner: Arc<AtomicRoot<T>>,
+    shared: Arc<Shared<T>>,
 }
 
-struct UnsafeDrop<T: ?Sized> {
-    inner: ManuallyDrop<T>,
+impl<T: KVStore> Clone for Quadrille<T> {
+    fn clone(&self) -> Self {
+        Quadrille {
+            inner: self.inner.clone(),
+            shared: self.shared.clone(),
+        }
+    }
 }
 
-impl<T> UnsafeDrop<T> {
-    pub fn new(val: T) -> UnsafeDrop<T> {
-        let inner = ManuallyDrop::new(val);
-        UnsafeDrop { inner }
+/// State shared by every handle and transaction of one store, besides the
+/// root itself.
+pub(crate) struct Shared<T> {
+    config: Config<T>,
+    /// The TTLs committed with the current root. Swapping the root takes
+    /// the write lock for as long as it takes to move these on too, and
+    /// [`load`](Self::load) reads both under the read lock, so no one sees
+    /// a root with another version's TTLs.
+    expiries: RwLock<Arc<Expiries>>,
+    /// Set for good by the first commit with a TTL. Until then roots are
+    /// swapped and loaded without taking `expiries`.
+    has_ttls: AtomicBool,
+    sizes: Option<AtomicSizeHistogram>,
+    metrics: AtomicCommitMetrics,
+    /// Number of commits published to the root so far.
+    version: AtomicU64,
+    history: Option<History<T>>,
+    hot: HotCache,
+    /// The root [`Quadrille::get_stale`] reads from, when it was taken, and
+    /// the TTLs committed with it.
+    stale: RwLock<Option<StaleRoot<T>>>,
+    locks: KeyLocks,
+    observer: RwLock<Option<ConflictObserver>>,
+    /// Only with [`QuadrilleBuilder::fair_commits`].
+    turnstile: Option<Turnstile>,
+}
+
+/// The callback set with [`Quadrille::observe_conflicts`].
+type ConflictObserver = Arc<dyn Fn(&ConflictInfo) + Send + Sync>;
+
+/// Expiry deadlines by key, see [`Transation::insert_with_ttl`].
+pub(crate) type Expiries = BTreeMap<Vec<u8>, Instant>;
+
+/// A root cached by [`Quadrille::get_stale`]: when it was taken, the root,
+/// and the TTLs committed with it.
+type StaleRoot<T> = (Instant, Arc<T>, Option<Arc<Expiries>>);
+
+impl<T> Shared<T> {
+    pub(crate) fn new(config: Config<T>, root: &AtomicRoot<T>) -> Shared<T> {
+        let history = (config.retain_versions > 0).then(|| {
+            let history = History::new(config.retain_versions);
+            history.lock().push(0, root.get());
+            history
+        });
+        Shared {
+            sizes: config.record_sizes.then(AtomicSizeHistogram::default),
+            turnstile: config.fair_after.map(|_| Turnstile::default()),
+            config,
+            expiries: RwLock::default(),
+            has_ttls: AtomicBool::new(false),
+            metrics: AtomicCommitMetrics::default(),
+            version: AtomicU64::new(0),
+            history,
+            hot: HotCache::default(),
+            stale: RwLock::new(None),
+            locks: KeyLocks::default(),
+            observer: RwLock::new(None),
+        }
+    }
+
+    /// Installs `new` as the next version if `root` is still `basis`, see
+    /// [`AtomicRoot::compare_swap`], with the TTLs `expire` gives as in
+    /// [`swap_with_expiries`](Self::swap_with_expiries).
+    fn publish(
+        &self,
+        root: &AtomicRoot<T>,
+        basis: Basis<T>,
+        new: Arc<T>,
+        sets_ttls: bool,
+        expire: impl FnOnce(&Expiries) -> Option<Expiries>,
+    ) -> Result<Arc<T>, Arc<T>> {
+        let mut history = self.history.as_ref().map(History::lock);
+        let res =
+            self.swap_with_expiries(sets_ttls, expire, || root.compare_swap(basis, new.clone()));
+        if res.is_ok() {
+            let version = self.version.fetch_add(1, AcqRel) + 1;
+            if let Some(history) = &mut history {
+                history.push(version, new);
+            }
+        }
+        self.metrics.record(res.is_ok());
+        res
+    }
+
+    /// Unconditionally installs `new` as the next version, with no TTLs,
+    /// returning the root it replaced.
+    fn replace(&self, root: &AtomicRoot<T>, new: Arc<T>) -> Arc<T> {
+        let mut history = self.history.as_ref().map(History::lock);
+        let swap = || Ok(root.swap(new.clone()));
+        let old = self.swap_with_expiries(false, |_| Some(Expiries::new()), swap);
+        let version = self.version.fetch_add(1, AcqRel) + 1;
+        if let Some(history) = &mut history {
+            history.push(version, new);
+        }
+        old.unwrap_or_else(|_| unreachable!("swapping never fails"))
+    }
+
+    /// Runs `swap`, which moves the root on unless it fails, and moves the
+    /// TTLs on with it: `expire` is given those of the state being replaced
+    /// and returns those of the new one, or `None` to keep them. It only
+    /// runs once the store has TTLs, which `sets_ttls` says this swap may
+    /// be the first to add.
+    fn swap_with_expiries(
+        &self,
+        sets_ttls: bool,
+        expire: impl FnOnce(&Expiries) -> Option<Expiries>,
+        swap: impl FnOnce() -> Result<Arc<T>, Arc<T>>,
+    ) -> Result<Arc<T>, Arc<T>> {
+        if sets_ttls {
+            self.has_ttls.store(true, SeqCst);
+        }
+        if !self.has_ttls.load(SeqCst) {
+            // any root this replaces was loaded before the flag was set, so
+            // there are no TTLs to keep in step with it
+            return swap();
+        }
+        let mut expiries = self.expiries.write().unwrap_or_else(|e| e.into_inner());
+        let res = swap();
+        if res.is_ok() {
+            if let Some(new) = expire(&expiries) {
+                *expiries = Arc::new(new);
+            }
+        }
+        res
+    }
+
+    /// The current root and the TTLs committed with it, or `None` if the
+    /// store has never had any.
+    fn load(&self, root: &AtomicRoot<T>) -> (Arc<T>, Option<Arc<Expiries>>) {
+        if !self.has_ttls.load(SeqCst) {
+            let state = root.get();
+            // a root published with TTLs is only swapped in after the flag
+            // is set, so if it is still clear `state` has none
+            if !self.has_ttls.load(SeqCst) {
+                return (state, None);
+            }
+        }
+        let expiries = self.expiries.read().unwrap_or_else(|e| e.into_inner());
+        (root.get(), Some(expiries.clone()))
+    }
+
+    /// Starts a new store's TTLs from another's.
+    fn inherit_expiries(&mut self, expiries: Arc<Expiries>) {
+        *self.has_ttls.get_mut() = true;
+        *self.expiries.get_mut().unwrap_or_else(|e| e.into_inner()) = expiries;
+    }
+
+    /// Whether `key` has expired by the store's clock, going by the TTLs
+    /// committed most recently.
+    fn expired_now(&self, key: &[u8]) -> bool {
+        if !self.has_ttls.load(SeqCst) {
+            return false;
+        }
+        let expiries = self.expiries.read().unwrap_or_else(|e| e.into_inner());
+        self.expired(Some(&expiries), key)
+    }
+
+    /// Whether `key` has expired by the store's clock, going by `expiries`.
+    fn expired(&self, expiries: Option<&Expiries>, key: &[u8]) -> bool {
+        let deadline = expiries.and_then(|e| e.get(key));
+        deadline.is_some_and(|d| self.now() >= *d)
     }
 
-    pub unsafe fn drop(self) {
-        let _ = ManuallyDrop::into_inner(self.inner);
+    fn now(&self) -> Instant {
+        self.config.clock.now()
     }
 
-    pub unsafe fn into_inner(self) -> T {
-        ManuallyDrop::into_inner(self.inner)
+    /// The combiner for `key`, if it is under a commutative prefix.
+    fn combiner(&self, key: &[u8]) -> Option<&Combiner> {
+        let commutative = &self.config.commutative;
+        commutative
+            .iter()
+            .find(|(prefix, _)| key.starts_with(prefix))
+            .map(|(_, combiner)| combiner)
     }
+
+    /// Whether `a` and `b` are the same value, as configured with
+    /// [`QuadrilleBuilder::value_eq`].
+    fn value_eq(&self, a: &[u8], b: &[u8]) -> bool {
+        match &self.config.value_eq {
+            Some(eq) => eq(a, b),
+            None => a == b,
+        }
+    }
+
+    /// Runs the configured [`Validate`] checks on an entry about to be written.
+    fn check(&self, key: &[u8], val: &[u8]) -> Result<(), QuadrilleError> {
+        let checks = [(self.config.key_check, key), (self.config.value_check, val)];
+        for (check, bytes) in checks {
+            if let Some(check) = check {
+                check(bytes).map_err(QuadrilleError::InvalidData)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a transaction's commit checks against concurrent commits, see
+/// [`Quadrille::transaction_with_isolation`]. Every level reads from the
+/// consistent state the transaction began on and publishes atomically.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Isolation {
+    /// Nothing is checked: on conflict the transaction's writes are reapplied
+    /// on the latest state, overwriting concurrent writes to the same keys.
+    ReadCommitted,
+    /// Write-write conflicts only. If no key the transaction wrote changed
+    /// concurrently its writes are reapplied on the latest state; otherwise
+    /// the conflict is resolved as usual. Reads are not checked, which
+    /// allows write skew.
+    Snapshot,
+    /// Concurrent changes to any key the transaction read fail the commit
+    /// with [`QuadrilleError::KeyConflict`]; other conflicts are resolved as
+    /// usual. What [`Quadrille::transaction`] uses.
+    #[default]
+    Serializable,
+}
+
+/// How eagerly a transaction competes for the root when its commit conflicts.
+///
+/// Lower priorities back off for longer between attempts and give up sooner,
+/// leaving contended roots to higher-priority work.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
 }
 
-impl<T: ?Sized> Deref for UnsafeDrop<T> {
-    type Target = T;
+impl Priority {
+    /// Conflicting attempts tolerated before commit fails with
+    /// [`QuadrilleError::RetryLimit`], or `None` to retry indefinitely.
+    pub fn retry_limit(self) -> Option<u32> {
+        match self {
+            Priority::Low => Some(8),
+            Priority::Normal | Priority::High => None,
+        }
+    }
 
-    fn deref(&self) -> &Self::Target {
-        &self.inner
+    fn backoff(self, attempt: u32) {
+        let spins = match self {
+            Priority::Low => 256,
+            Priority::Normal => 16,
+            Priority::High => 0,
+        } * attempt.min(16);
+        for _ in 0..spins {
+            std::hint::spin_loop();
+        }
     }
 }
 
+/// Pointer identity of the root a transaction was based on. It does not own a
+/// reference count (the transaction's `basis` `Arc` keeps the root alive), so
+/// dropping one, including during unwinding, never needs to reclaim anything.
 #[derive(Copy)]
 struct Basis<T>(*mut T);
 impl<T> Basis<T> {
@@ -66,126 +646,1689 @@ impl<T> Clone for Basis<T> {
     }
 }
 
+/// How a single commit attempt ended.
+enum Attempt {
+    Published,
+    /// There was nothing to publish.
+    Unchanged,
+    Conflict,
+}
+
 struct AtomicRoot<T> {
     inner: AtomicPtr<T>,
+    /// Threads between loading `inner` and taking their own count on it.
+    readers: AtomicUsize,
+    /// Counts released from the root while readers were mid-load, dropped
+    /// once a later swap or the last reader out sees none.
+    retired: Mutex<Vec<Arc<T>>>,
 }
 
+// Every pointer stored in `inner` came from `Arc::into_raw` and carries one
+// strong count owned by the root. A replaced root's count is only released
+// once no reader can still be about to clone it: readers announce
+// themselves in `readers` before loading the pointer, and a swap that sees
+// any of them retires the count instead of dropping it. `readers` and the
+// pointer use `SeqCst`, so a swap that reads `readers` as zero knows every
+// reader either finished cloning or loads the new pointer.
 impl<T> AtomicRoot<T> {
     pub fn new(val: T) -> AtomicRoot<T> {
-        let arc = Arc::new(val);
+        Self::from_arc(Arc::new(val))
+    }
+    pub fn from_arc(arc: Arc<T>) -> AtomicRoot<T> {
         let inner = AtomicPtr::new(Arc::into_raw(arc) as *mut T);
-        Self { inner }
+        Self {
+            inner,
+            readers: AtomicUsize::new(0),
+            retired: Mutex::default(),
+        }
     }
     pub fn get(&self) -> Arc<T> {
-        let inner = self.get_inner();
-        let out = (*inner).clone();
+        self.readers.fetch_add(1, SeqCst);
+        let ptr = self.inner.load(SeqCst);
+        // SAFETY: `ptr` came from `Arc::into_raw`, and as we are counted in
+        // `readers` the root's count on it is not released until we are done.
+        let arc = unsafe {
+            Arc::increment_strong_count(ptr);
+            Arc::from_raw(ptr)
+        };
+        if self.readers.fetch_sub(1, SeqCst) == 1 {
+            // swaps under constant reads may never see zero themselves
+            self.drain(self.retired.lock().unwrap_or_else(|e| e.into_inner()));
+        }
+        arc
+    }
+
+    pub fn swap(&self, val: Arc<T>) -> Arc<T> {
+        // infallible from here on, so the count can move to the root at once
+        let new_ptr = Arc::into_raw(val);
+        let old_ptr = self.inner.swap(new_ptr as *mut T, SeqCst);
+        // SAFETY: the root's count on the state it just replaced
+        let old = unsafe { Arc::from_raw(old_ptr) };
+        let out = old.clone();
+        self.retire(old);
         out
     }
 
-    fn get_inner(&self) -> UnsafeDrop<Arc<T>> {
-        // SAFTEY: self.inner is only set as the result of Arc::into_raw, and will not be dropped automatically
-        // Dropping must only occur once, and when the Arc pointer is removed from the struct
-        let arc = unsafe { Arc::from_raw(self.inner.load(Relaxed)) };
-        UnsafeDrop::new(arc)
+    /// Releases the root's count on a state it no longer points to, or
+    /// defers it while a reader may still be cloning it.
+    fn retire(&self, old: Arc<T>) {
+        let mut retired = self.retired.lock().unwrap_or_else(|e| e.into_inner());
+        retired.push(old);
+        self.drain(retired);
     }
 
-    pub fn swap(&self, val: Arc<T>) -> Arc<T> {
-        let new_ptr = Arc::into_raw(val);
-        let old_ptr = self.inner.swap(new_ptr as *mut T, Relaxed);
-        unsafe { Arc::from_raw(old_ptr) }
+    /// Drops the retired counts if no reader is mid-load. Everything in
+    /// `retired` was swapped out before it was pushed, so a reader that
+    /// arrives after the check loads a newer pointer.
+    fn drain(&self, mut retired: MutexGuard<'_, Vec<Arc<T>>>) {
+        if self.readers.load(SeqCst) == 0 {
+            let stale = std::mem::take(&mut *retired);
+            drop(retired);
+            drop(stale);
+        }
     }
 
     pub fn basis(&self) -> (Basis<T>, Arc<T>) {
-        let inner = self.get_inner();
-        let cloned = (*inner).clone();
-        let ptr = Arc::into_raw(unsafe { inner.into_inner() }) as *mut T;
-
-        (Basis::new(ptr), cloned)
+        let basis = self.get();
+        (Basis::new(Arc::as_ptr(&basis) as *mut T), basis)
     }
 
+    /// Installs `new` if the root is still `basis`. On success the old state
+    /// is returned, otherwise `new` is handed back.
+    ///
+    /// `new` stays an owned `Arc` until the exchange has succeeded, and only
+    /// then gives its count to the root, so there is no point at which
+    /// returning early or unwinding could leak it or leave the root
+    /// pointing at a state nothing owns.
     pub fn compare_swap(&self, basis: Basis<T>, new: Arc<T>) -> Result<Arc<T>, Arc<T>> {
-        let new_ptr = Arc::into_raw(new) as *mut T;
+        let new_ptr = Arc::as_ptr(&new) as *mut T;
         let old_ptr = basis.unwrap();
         let res = self
             .inner
-            .compare_exchange(old_ptr, new_ptr, Relaxed, Relaxed);
+            .compare_exchange(old_ptr, new_ptr, SeqCst, SeqCst);
         match res {
-            Ok(ptr) => Ok(unsafe { Arc::from_raw(ptr) }),
-            Err(_) => Err(unsafe { Arc::from_raw(new_ptr) }),
+            Ok(ptr) => {
+                // the root now points at `new`, and owns this count on it
+                std::mem::forget(new);
+                // SAFETY: the root's count on the state it just replaced
+                let old = unsafe { Arc::from_raw(ptr) };
+                let out = old.clone();
+                self.retire(old);
+                Ok(out)
+            }
+            Err(_) => Err(new),
         }
     }
 }
 
 impl<T> Drop for AtomicRoot<T> {
     fn drop(&mut self) {
-        let inner = self.get_inner();
-        unsafe {
-            UnsafeDrop::drop(inner);
-        }
+        // SAFETY: `&mut self` rules out concurrent access, and the root's own
+        // count is released exactly once here. Retired counts drop with
+        // the struct.
+        unsafe { drop(Arc::from_raw(*self.inner.get_mut())) }
     }
 }
 
 pub struct Transation<T: KVStore> {
     kv: Arc<AtomicRoot<T>>,
-    basis_marker: Basis<T>,
+    shared: Arc<Shared<T>>,
     basis: Arc<T>,
     current: Arc<T>,
+    priority: Priority,
+    isolation: Isolation,
+    /// The TTLs committed with `basis`, if the store has any.
+    expiries: Option<Arc<Expiries>>,
+    /// Expiry changes to publish on commit; `None` clears a key's TTL.
+    ttls: BTreeMap<Vec<u8>, Option<Instant>>,
+    changes: ChangeSet,
+    /// Keys read so far; a concurrent commit changing any of them fails ours.
+    /// Only allocated on the first read.
+    reads: RefCell<HashSet<Vec<u8>>>,
+    /// The only keys this transaction may write, if it declared them.
+    declared: Option<BTreeSet<Vec<u8>>>,
+    /// Set by [`set_all`](Self::set_all): the state replaces the store's
+    /// outright, so any concurrent commit conflicts.
+    replaced_all: bool,
+    /// The version each key passed to [`insert_versioned`] was expected at,
+    /// checked again against any concurrent commit.
+    ///
+    /// [`insert_versioned`]: Self::insert_versioned
+    versions: BTreeMap<Vec<u8>, u64>,
+    /// `current.len()`, once a bound on the store's size needed it. Single
+    /// inserts and removes keep it up to date, so only other writes make it
+    /// count again.
+    len: Cell<Option<usize>>,
 }
 
 impl<T: KVStore> Transation<T> {
     pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
-        self.current.get(key)
+        self.record_read(key);
+        self.current.get(key).filter(|_| !self.is_expired(key))
     }
 
-    pub fn insert(&mut self, key: Vec<u8>, val: Vec<u8>) -> bool {
-        let (new, found) = self.current.insert(key, val);
-        self.current = new.into();
-        found
+    /// The value at `key` in the state this transaction began from, ignoring
+    /// its own buffered writes, e.g. to compare against what was there
+    /// before. Counts as a read of `key` like [`get`](Self::get). After
+    /// [`try_commit`](Self::try_commit) hands the transaction back, this is
+    /// the state it was rebased onto.
+    pub fn basis_get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.record_read(key);
+        let expired = || {
+            self.committed_deadline(key)
+                .is_some_and(|d| self.shared.now() >= d)
+        };
+        self.basis.get(key).filter(|_| !expired())
+    }
+
+    /// The value at `key`, or `default` if it is absent. Nothing is inserted.
+    pub fn get_with_default(&self, key: &[u8], default: Vec<u8>) -> Vec<u8> {
+        self.get(key).unwrap_or(default)
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.record_read(key);
+        self.current.contains_key(key) && !self.is_expired(key)
+    }
+
+    fn record_read(&self, key: &[u8]) {
+        let mut reads = self.reads.borrow_mut();
+        if !reads.contains(key) {
+            reads.insert(key.to_vec());
+        }
+    }
+
+    /// Forgets every key read so far, so concurrent commits to them no longer
+    /// fail this transaction's commit.
+    ///
+    /// This weakens isolation: the transaction may commit writes that were
+    /// computed from values another transaction has since replaced. Only
+    /// flush once nothing still to be written depends on the earlier reads.
+    pub fn flush_reads(&mut self) {
+        self.reads.get_mut().clear();
+    }
+
+    /// Discards everything buffered and starts over from the latest
+    /// committed state, as if the transaction had just been opened. Its
+    /// priority, isolation level and declared keys are kept, and so is the
+    /// read set's allocation, so a transaction reused in a loop stops
+    /// allocating once it has grown to fit.
+    pub fn reset(&mut self) {
+        self.update_basis();
+        self.set_current(self.basis.clone());
+        self.ttls.clear();
+        self.changes = ChangeSet::default();
+        self.reads.get_mut().clear();
+        self.replaced_all = false;
+        self.versions.clear();
+    }
+
+    /// Buffers `val` at `key`, returning whether it replaced a value. Fails
+    /// with [`QuadrilleError::InvalidData`], buffering nothing, if the key
+    /// or value does not pass the store's [`Validate`] checks, or with
+    /// [`QuadrilleError::StoreFull`] if `key` is new and the store is at its
+    /// [`QuadrilleBuilder::max_entries`].
+    pub fn insert(&mut self, key: Vec<u8>, val: Vec<u8>) -> Result<bool, QuadrilleError> {
+        self.check_declared(&key)?;
+        self.shared.check(&key, &val)?;
+        if let Some(max) = self.shared.config.max_entries {
+            if !self.current.contains_key(&key) && self.current_len() >= max {
+                return Err(QuadrilleError::StoreFull);
+            }
+        }
+        self.forget_ttl(&key);
+        self.changes.set(key.clone(), val.clone());
+        // once the first write has copied the basis, the copy is ours alone
+        let found = match Arc::get_mut(&mut self.current) {
+            Some(current) => current.insert_in_place(key, val),
+            None => {
+                let (new, found) = self.current.insert(key, val);
+                self.current = new.into();
+                found
+            }
+        };
+        self.len
+            .set(self.len.get().map(|n| n + usize::from(!found)));
+        Ok(found)
+    }
+
+    /// Inserts a value that expires `ttl` after now, as read from the
+    /// store's [`Clock`]. Once expired, [`get`](Self::get) and
+    /// [`contains_key`](Self::contains_key) treat the key as absent; the
+    /// entry itself stays in the store, and in scans, until
+    /// [`Quadrille::purge_expired`] removes it or it is overwritten.
+    pub fn insert_with_ttl(
+        &mut self,
+        key: Vec<u8>,
+        val: Vec<u8>,
+        ttl: Duration,
+    ) -> Result<bool, QuadrilleError> {
+        let deadline = self.shared.now() + ttl;
+        let found = self.insert(key.clone(), val)?;
+        self.ttls.insert(key, Some(deadline));
+        Ok(found)
+    }
+
+    fn is_expired(&self, key: &[u8]) -> bool {
+        let deadline = match self.ttls.get(key) {
+            Some(deadline) => *deadline,
+            None => self.committed_deadline(key),
+        };
+        deadline.is_some_and(|d| self.shared.now() >= d)
+    }
+
+    /// The deadline committed for `key`, ignoring this transaction's own
+    /// TTL changes.
+    fn committed_deadline(&self, key: &[u8]) -> Option<Instant> {
+        self.expiries.as_ref()?.get(key).copied()
+    }
+
+    fn forget_ttl(&mut self, key: &[u8]) {
+        if self.ttls.contains_key(key) || self.committed_deadline(key).is_some() {
+            self.ttls.insert(key.to_vec(), None);
+        }
+    }
+
+    /// Grows the working state to hold about `additional` more entries, so
+    /// buffering that many inserts one by one does not reallocate along the
+    /// way. Only backends implementing [`KVStore::reserve`] do anything.
+    pub fn reserve(&mut self, additional: usize) {
+        if let Some(new) = self.current.reserve(additional) {
+            self.set_current(new.into());
+        }
+    }
+
+    /// Buffers all `entries` at once, returning how many replaced an existing
+    /// value. Backends can build the new version in a single pass instead of
+    /// one copy per insert. If any entry is invalid, or the new keys would
+    /// overfill the store, none are buffered.
+    pub fn insert_many(
+        &mut self,
+        entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    ) -> Result<usize, QuadrilleError> {
+        let entries: Vec<_> = entries.into_iter().collect();
+        if entries.is_empty() {
+            return Ok(0);
+        }
+        for (key, val) in &entries {
+            self.check_declared(key)?;
+            self.shared.check(key, val)?;
+        }
+        if let Some(max) = self.shared.config.max_entries {
+            let added: HashSet<_> = entries
+                .iter()
+                .filter(|(key, _)| !self.current.contains_key(key))
+                .map(|(key, _)| key)
+                .collect();
+            if !added.is_empty() && self.current_len() + added.len() > max {
+                return Err(QuadrilleError::StoreFull);
+            }
+        }
+        for (key, val) in &entries {
+            self.forget_ttl(key);
+            self.changes.set(key.clone(), val.clone());
+        }
+        let (new, replaced) = self.current.insert_many(entries);
+        self.set_current(new.into());
+        Ok(replaced)
+    }
+
+    /// Inserts `key` only if it is absent, failing with
+    /// [`QuadrilleError::KeyExists`] otherwise. Unlike [`insert`](Self::insert)
+    /// an existing value is never replaced.
+    pub fn insert_new(&mut self, key: Vec<u8>, val: Vec<u8>) -> Result<(), QuadrilleError> {
+        self.record_read(&key);
+        if self.current.contains_key(&key) {
+            return Err(QuadrilleError::KeyExists(key));
+        }
+        self.insert(key, val)?;
+        Ok(())
+    }
+
+    /// Applies all of `writes` only if every key in `conditions` currently has
+    /// the expected value (`None` meaning absent), returning whether they
+    /// were applied. Nothing is written when any condition fails, or when
+    /// any write is invalid.
+    pub fn check_and_set(
+        &mut self,
+        conditions: &[(Vec<u8>, Option<Vec<u8>>)],
+        writes: Vec<(Vec<u8>, Vec<u8>)>,
+    ) -> Result<bool, QuadrilleError> {
+        if !conditions.iter().all(|(k, v)| self.get(k) == *v) {
+            return Ok(false);
+        }
+        self.insert_many(writes)?;
+        Ok(true)
+    }
+
+    /// Replaces the value at `key` with `new` if `pred` holds on the current
+    /// value, returning the value replaced. Returns `None`, writing nothing,
+    /// if the key is absent or `pred` rejects it. The key counts as read
+    /// either way, so a concurrent change to it fails the commit.
+    pub fn replace_if(
+        &mut self,
+        key: &[u8],
+        pred: impl Fn(&[u8]) -> bool,
+        new: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, QuadrilleError> {
+        let Some(old) = self.get(key).filter(|old| pred(old)) else {
+            return Ok(None);
+        };
+        self.insert(key.to_vec(), new)?;
+        Ok(Some(old))
+    }
+
+    /// Like [`get`](Self::get), but on a miss asks the store's loader (see
+    /// [`QuadrilleBuilder::loader`]) and buffers what it returns as a write,
+    /// so later reads in this transaction hit the buffer and the value is
+    /// committed with the transaction. Loaded values that fail validation
+    /// are treated as missing.
+    pub fn get_or_load(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        if let Some(val) = self.get(key) {
+            return Some(val);
+        }
+        let loader = self.shared.config.loader.as_ref()?;
+        let val = loader(key)?;
+        self.insert(key.to_vec(), val.clone()).ok()?;
+        Some(val)
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Result<bool, QuadrilleError> {
+        self.check_declared(key)?;
+        self.forget_ttl(key);
+        let found = match Arc::get_mut(&mut self.current) {
+            Some(current) => current.remove_in_place(key),
+            None => {
+                let (new, found) = self.current.remove(key);
+                self.current = new.into();
+                found
+            }
+        };
+        self.len.set(self.len.get().map(|n| n - usize::from(found)));
+        if found {
+            self.changes.delete(key.to_vec());
+        }
+        Ok(found)
+    }
+
+    /// Buffers every change in `patch`, in order, e.g. changes captured from
+    /// another replica. If any of them is invalid, none are buffered.
+    pub fn apply_patch(&mut self, patch: &[(Vec<u8>, Change)]) -> Result<(), QuadrilleError> {
+        for (key, change) in patch {
+            self.check_declared(key)?;
+            if let Change::Set(val) = change {
+                self.shared.check(key, val)?;
+            }
+        }
+        for (key, change) in patch {
+            match change {
+                Change::Set(val) => self.insert(key.clone(), val.clone())?,
+                Change::Delete => self.remove(key)?,
+            };
+        }
+        Ok(())
+    }
+
+    /// Moves the value at `from` to `to`, replacing any value there, and
+    /// returns whether `from` existed. Nothing changes if it did not, or if
+    /// `from` and `to` are the same key. A TTL on `from` is not carried over.
+    pub fn rename_key(&mut self, from: &[u8], to: &[u8]) -> Result<bool, QuadrilleError> {
+        let Some(val) = self.get(from) else {
+            return Ok(false);
+        };
+        if from != to {
+            self.check_declared(from)?;
+            self.check_declared(to)?;
+            self.shared.check(to, &val)?;
+            // removing first, the move never finds the store full
+            self.remove(from)?;
+            self.insert(to.to_vec(), val)?;
+        }
+        Ok(true)
+    }
+
+    /// Exchanges the values at `a` and `b`. An absent key swaps like a value,
+    /// so if only one is present its value moves to the other key. Swapping a
+    /// key with itself changes nothing, and so does a swap that fails.
+    pub fn swap_values(&mut self, a: &[u8], b: &[u8]) -> Result<(), QuadrilleError> {
+        if a == b {
+            return Ok(());
+        }
+        let (val_a, val_b) = (self.get(a), self.get(b));
+        let mut writes = [(a, val_b), (b, val_a)];
+        for (key, val) in &writes {
+            self.check_declared(key)?;
+            if let Some(val) = val {
+                self.shared.check(key, val)?;
+            }
+        }
+        // removing first means a value moving to an absent key never finds
+        // the store full: a swap leaves the number of entries as it was
+        writes.sort_by_key(|(_, val)| val.is_some());
+        for (key, val) in writes {
+            match val {
+                Some(val) => self.insert(key.to_vec(), val)?,
+                None => self.remove(key)?,
+            };
+        }
+        Ok(())
+    }
+
+    /// Removes every key starting with `prefix`, returning how many were
+    /// removed. An empty prefix removes everything.
+    pub fn remove_prefix(&mut self, prefix: &[u8]) -> Result<usize, QuadrilleError> {
+        let matched = self.current.scan_prefix(prefix);
+        if matched.is_empty() {
+            return Ok(0);
+        }
+        for (key, _) in &matched {
+            self.check_declared(key)?;
+        }
+        let (new, removed) = self.current.remove_prefix(prefix);
+        self.set_current(new.into());
+        for (key, _) in matched {
+            self.forget_ttl(&key);
+            self.changes.delete(key);
+        }
+        Ok(removed)
+    }
+
+    /// Keeps only the entries for which `pred` holds, like
+    /// [`BTreeMap::retain`], returning how many were removed.
+    pub fn retain(&mut self, pred: impl Fn(&[u8], &[u8]) -> bool) -> Result<usize, QuadrilleError> {
+        let doomed = self.current.fold(Vec::new(), |mut doomed, key, val| {
+            if !pred(key, val) {
+                doomed.push(key.to_vec());
+            }
+            doomed
+        });
+        if doomed.is_empty() {
+            return Ok(0);
+        }
+        for key in &doomed {
+            self.check_declared(key)?;
+        }
+        // removes the keys already found rather than asking `pred` again
+        let (mut new, _) = self.current.remove(&doomed[0]);
+        for key in &doomed[1..] {
+            new.remove_in_place(key);
+        }
+        self.set_current(new.into());
+        let removed = doomed.len();
+        for key in doomed {
+            self.forget_ttl(&key);
+            self.changes.delete(key);
+        }
+        Ok(removed)
+    }
+
+    /// Replaces everything the transaction sees with `entries`, so that
+    /// committing leaves the store holding exactly them; later entries
+    /// replace earlier ones with the same key. Since that overwrites
+    /// whatever anyone else commits meanwhile, any concurrent commit fails
+    /// this one with [`QuadrilleError::KeyConflict`]. If any entry is
+    /// invalid, or there are more than the store's
+    /// [`QuadrilleBuilder::max_entries`], nothing changes.
+    pub fn set_all(
+        &mut self,
+        entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    ) -> Result<(), QuadrilleError> {
+        let entries: Vec<_> = entries.into_iter().collect();
+        for (key, val) in &entries {
+            self.check_declared(key)?;
+            self.shared.check(key, val)?;
+        }
+        let mut builder = StoreBuilder::new(self.current.cleared());
+        builder.extend(entries.iter().cloned());
+        let new = builder.finish();
+        if self
+            .shared
+            .config
+            .max_entries
+            .is_some_and(|max| new.len() > max)
+        {
+            return Err(QuadrilleError::StoreFull);
+        }
+        let doomed: Vec<_> = self
+            .current
+            .iter_ref()
+            .filter(|(key, _)| !new.contains_key(key))
+            .map(|(key, _)| key.to_vec())
+            .collect();
+        for key in &doomed {
+            self.check_declared(key)?;
+        }
+        for key in doomed {
+            self.forget_ttl(&key);
+            self.changes.delete(key);
+        }
+        for (key, val) in entries {
+            self.forget_ttl(&key);
+            self.changes.set(key, val);
+        }
+        self.set_current(Arc::new(new));
+        self.replaced_all = true;
+        Ok(())
+    }
+
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.current.range(start, end)
+    }
+
+    /// Keys in the range, without loading their values.
+    pub fn range_keys(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<Vec<u8>> {
+        self.current.range_keys(start, end)
+    }
+
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.current.scan_prefix(prefix)
+    }
+
+    /// How many entries are in the range, without copying them.
+    pub fn range_count(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> usize {
+        self.current.range_count(start, end)
+    }
+
+    /// A rough, best-effort estimate in `[0, 1]` of how likely committing now
+    /// is to run into a conflicting commit, e.g. to decide whether to commit
+    /// or defer. It scales the store's recent [`conflict_rate`] by the share
+    /// of the store this transaction depends on: its writes, plus its reads
+    /// under [`Isolation::Serializable`].
+    ///
+    /// It assumes concurrent writes are spread evenly over the keys, so it
+    /// knows nothing of which keys are actually hot, and it only sees as
+    /// far back as the last [`Quadrille::stats_reset`].
+    ///
+    /// [`conflict_rate`]: CommitMetrics::conflict_rate
+    pub fn estimate_conflict_probability(&self) -> f64 {
+        let mut footprint = self.changes.len();
+        if self.isolation == Isolation::Serializable {
+            let reads = self.reads.borrow();
+            footprint += reads
+                .iter()
+                .filter(|key| !self.changes.contains_key(key))
+                .count();
+        }
+        if footprint == 0 {
+            return 0.0;
+        }
+        let keys = self.basis.len().max(self.current.len()).max(1);
+        let share = (footprint as f64 / keys as f64).min(1.0);
+        self.shared.metrics.load().conflict_rate() * share
+    }
+
+    /// Publishes the transaction, resolving against concurrent commits.
+    ///
+    /// A panic in `T::resolve` leaves the store untouched: the root is only
+    /// ever changed by a successful `compare_swap`, and everything the
+    /// transaction holds is an owned `Arc` released as it unwinds.
+    pub fn commit(self) -> Result<Quadrille<T>, QuadrilleError> {
+        self.commit_if(|_| true)
+    }
+
+    /// Commits only if `pred` holds on the state that would be published,
+    /// otherwise fails with [`QuadrilleError::PreconditionFailed`] and leaves
+    /// the store unchanged. `pred` is rechecked after every resolve, so it
+    /// always sees the merged state.
+    pub fn commit_if(self, pred: impl Fn(&T) -> bool) -> Result<Quadrille<T>, QuadrilleError> {
+        let timeout = self.shared.config.commit_timeout;
+        self.commit_inner(pred, timeout)
+    }
+
+    /// Commits, failing with [`QuadrilleError::Timeout`] if conflicts are
+    /// still being retried after `timeout`. This overrides the store's
+    /// default from [`QuadrilleBuilder::commit_timeout`].
+    pub fn commit_within(self, timeout: Duration) -> Result<Quadrille<T>, QuadrilleError> {
+        self.commit_inner(|_| true, Some(timeout))
     }
 
-    pub fn commit(mut self) -> Result<Quadrille<T>, QuadrilleError> {
+    fn commit_inner(
+        mut self,
+        pred: impl Fn(&T) -> bool,
+        timeout: Option<Duration>,
+    ) -> Result<Quadrille<T>, QuadrilleError> {
+        let deadline = timeout.map(|t| self.shared.now() + t);
+        let _span = trace::span!("commit", priority = ?self.priority);
+        #[cfg(feature = "tracing")]
+        let start = std::time::Instant::now();
+        let mut attempt = 0;
+        let shared = self.shared.clone();
+        let mut turn = None;
         loop {
-            match self
-                .kv
-                .compare_swap(self.basis_marker.clone(), self.current.clone())
-            {
-                Ok(_) => {
-                    // TODO: drop _ptr
+            if let (Some(turnstile), None) = (&shared.turnstile, &turn) {
+                match shared.config.fair_after {
+                    Some(after) if attempt >= after => {
+                        trace::event!(attempt, "waiting for a turn");
+                        turn = turnstile.enter();
+                    }
+                    _ => turnstile.yield_to_starving(),
+                }
+            }
+            match self.attempt(&pred, &mut attempt, deadline)? {
+                Attempt::Published => {
+                    trace::event!(
+                        attempts = attempt + 1,
+                        elapsed_us = start.elapsed().as_micros() as u64,
+                        "committed"
+                    );
                     break;
                 }
-                Err(_) => {
-                    self.update_basis();
-                    self.current = T::resolve(self.basis.clone(), self.current.clone())?;
+                Attempt::Unchanged => break,
+                Attempt::Conflict => {
+                    self.priority.backoff(attempt);
+                    self.reconcile(attempt)?;
                 }
             }
         }
-        Ok(Quadrille { inner: self.kv })
+        Ok(self.finish())
+    }
+
+    /// Checks the state and tries once to publish it, counting a conflict
+    /// in `attempt` and failing once it exhausts the retry limit or
+    /// `deadline`.
+    fn attempt(
+        &mut self,
+        pred: &impl Fn(&T) -> bool,
+        attempt: &mut u32,
+        deadline: Option<Instant>,
+    ) -> Result<Attempt, QuadrilleError> {
+        if !pred(&self.current) {
+            trace::event!(attempt, "commit precondition failed");
+            return Err(QuadrilleError::PreconditionFailed);
+        }
+        self.validate()?;
+        if self.unchanged() {
+            // nothing to publish, or a concurrent commit already did
+            trace::event!(attempt, "commit is a no-op");
+            return Ok(Attempt::Unchanged);
+        }
+        match self.shared.publish(
+            &self.kv,
+            self.marker(),
+            self.current.clone(),
+            self.sets_ttls(),
+            |e| self.expire(e),
+        ) {
+            Ok(old) => {
+                // releases the root's count on the state we replaced
+                drop(old);
+                Ok(Attempt::Published)
+            }
+            Err(_) => {
+                *attempt += 1;
+                trace::event!(attempt, "commit conflict");
+                if let Some(limit) = self.priority.retry_limit() {
+                    if *attempt > limit {
+                        trace::event!(attempt, "commit retry limit reached");
+                        return Err(QuadrilleError::RetryLimit);
+                    }
+                }
+                if deadline.is_some_and(|d| self.shared.now() >= d) {
+                    trace::event!(attempt, "commit timed out");
+                    return Err(QuadrilleError::Timeout);
+                }
+                Ok(Attempt::Conflict)
+            }
+        }
+    }
+
+    /// Moves onto the latest state after a conflicting `attempt`, merging
+    /// this transaction's changes into it.
+    fn reconcile(&mut self, attempt: u32) -> Result<(), QuadrilleError> {
+        let base = self.basis.clone();
+        self.update_basis();
+        self.report_conflict(&base, attempt);
+        if self.replaced_all {
+            trace::event!(attempt, "store replaced concurrently");
+            return Err(QuadrilleError::KeyConflict);
+        }
+        self.check_versions()?;
+        if self.isolation == Isolation::Serializable && self.read_conflict(&base) {
+            trace::event!(attempt, "read key changed concurrently");
+            return Err(QuadrilleError::KeyConflict);
+        }
+        let declared_untouched = self.declared.as_ref().is_some_and(|declared| {
+            declared.iter().all(|key| {
+                base.get(key) == self.basis.get(key)
+                    || self.changes.get(key).is_some_and(|c| self.agrees(key, c))
+            })
+        });
+        if declared_untouched {
+            // every write is declared, so none can clash
+            trace::event!(attempt, "declared keys unchanged, replaying");
+            self.set_current(self.replay(self.basis.clone()));
+            return Ok(());
+        }
+        let replayable = match self.isolation {
+            Isolation::ReadCommitted => true,
+            Isolation::Snapshot => self.changes.iter().all(|(key, change)| {
+                self.shared.combiner(key).is_some()
+                    || base.get(key) == self.basis.get(key)
+                    || self.agrees(key, change)
+            }),
+            Isolation::Serializable => false,
+        };
+        if replayable {
+            trace::event!(attempt, isolation = ?self.isolation, "replaying writes");
+            let replayed = self.replay(self.basis.clone());
+            let combined = self.combine(&base, replayed);
+            self.set_current(combined);
+            return Ok(());
+        }
+        let commutative = self
+            .changes
+            .iter()
+            .filter(|(key, _)| self.shared.combiner(key).is_some())
+            .count();
+        let agreed: HashSet<&[u8]> = self
+            .changes
+            .iter()
+            .filter(|(key, change)| self.agrees(key, change))
+            .map(|(key, _)| key.as_slice())
+            .collect();
+        if commutative == 0 && agreed.len() == self.changes.len() {
+            // the concurrent commits already made every one of our writes,
+            // but TTLs given to them still have to be published
+            let current = match self.sets_ttls() {
+                true => self.replay(self.basis.clone()),
+                false => self.basis.clone(),
+            };
+            self.set_current(current);
+            return Ok(());
+        }
+        if commutative == self.changes.len() {
+            let combined = self.combine(&base, self.basis.clone());
+            self.set_current(combined);
+            return Ok(());
+        }
+        // writes the concurrent commits already made are not in conflict, so
+        // the resolver is only given the rest
+        let trimmed = (!agreed.is_empty()).then(|| {
+            let mut disputed = self.changes.clone();
+            disputed.retain(|key, _| !agreed.contains(key));
+            (Self::replay_changes(base.clone(), &disputed), disputed)
+        });
+        let (ours, disputed) = match &trimmed {
+            Some((ours, disputed)) => (ours, disputed),
+            None => (&self.current, &self.changes),
+        };
+        let resolved = {
+            let _span = trace::span!("resolve", attempt);
+            match &self.shared.config.resolver {
+                Some(resolver) => resolver
+                    .resolve_changes(&base, &self.basis, ours, disputed)
+                    .map(Arc::new),
+                None => T::resolve(self.basis.clone(), self.current.clone()),
+            }
+        };
+        if let Err(_err) = &resolved {
+            trace::event!(attempt, error = ?_err, "resolve failed");
+        }
+        let resolved = resolved?;
+        if cfg!(debug_assertions) {
+            resolved
+                .validate_invariants()
+                .map_err(QuadrilleError::BrokenStore)?;
+            self.check_writes_kept(&resolved)?;
+        }
+        let combined = self.combine(&base, resolved);
+        self.set_current(combined);
+        Ok(())
+    }
+
+    /// Whether the latest state already has `change` at `key`.
+    fn agrees(&self, key: &[u8], change: &Change) -> bool {
+        match (self.basis.get(key), change) {
+            (Some(theirs), Change::Set(ours)) => self.shared.value_eq(&theirs, ours),
+            (None, Change::Delete) => true,
+            _ => false,
+        }
+    }
+
+    /// Attempts to publish exactly once, without retrying or resolving
+    /// conflicts. If a concurrent commit got in first, the transaction is
+    /// handed back with [`QuadrilleError::KeyConflict`], rebased onto the
+    /// latest state: its writes are replayed on top of it and its reads are
+    /// forgotten, so re-read anything the writes depend on before calling
+    /// this again. If the latest state moved a key past the version
+    /// [`insert_versioned`](Self::insert_versioned) expected, the error is
+    /// [`QuadrilleError::VersionMismatch`] instead, on this and every later
+    /// call. Validator errors also hand the transaction back, unchanged.
+    // handing the transaction back by value is the point of this method
+    #[allow(clippy::result_large_err)]
+    pub fn try_commit(mut self) -> Result<Quadrille<T>, (Self, QuadrilleError)> {
+        if let Err(err) = self.validate().and_then(|()| self.check_versions()) {
+            return Err((self, err));
+        }
+        if !self.unchanged()
+            && self
+                .shared
+                .publish(
+                    &self.kv,
+                    self.marker(),
+                    self.current.clone(),
+                    self.sets_ttls(),
+                    |e| self.expire(e),
+                )
+                .is_err()
+        {
+            trace::event!("single-shot commit conflict");
+            let err = self.rebase().err().unwrap_or(QuadrilleError::KeyConflict);
+            return Err((self, err));
+        }
+        Ok(self.finish())
+    }
+
+    /// Whether the transaction has read or written anything.
+    pub(crate) fn touched(&self) -> bool {
+        !self.changes.is_empty() || !self.reads.borrow().is_empty()
+    }
+
+    /// Checks, without publishing, that a commit would succeed on its first
+    /// attempt: the validator accepts the state and the root is still the
+    /// basis. Only meaningful while nothing else can commit to this root.
+    pub(crate) fn prepare(&self) -> Result<(), QuadrilleError> {
+        self.validate()?;
+        if !Arc::ptr_eq(&self.kv.get(), &self.basis) {
+            return Err(QuadrilleError::KeyConflict);
+        }
+        Ok(())
+    }
+
+    /// Runs the validator, and every invariant whose key group this
+    /// transaction wrote to, on the state it would publish.
+    fn validate(&self) -> Result<(), QuadrilleError> {
+        let config = &self.shared.config;
+        if config
+            .max_entries
+            .is_some_and(|max| self.current_len() > max)
+        {
+            return Err(QuadrilleError::StoreFull);
+        }
+        if let Some(validator) = &config.validator {
+            validator(&self.current, &self.changes)?;
+        }
+        for invariant in &config.invariants {
+            let relevant = self
+                .changes
+                .iter()
+                .any(|(key, _)| invariant.keys.iter().any(|prefix| key.starts_with(prefix)));
+            if relevant && !(invariant.check)(&self.current) {
+                trace::event!(invariant = %invariant.name, "invariant violated");
+                return Err(QuadrilleError::InvariantViolated {
+                    name: invariant.name.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Bookkeeping once the transaction's state has been published.
+    fn finish(self) -> Quadrille<T> {
+        if let Some(sizes) = &self.shared.sizes {
+            sizes.record(self.changes.len(), self.changes.bytes());
+        }
+        Quadrille {
+            inner: self.kv,
+            shared: self.shared,
+        }
+    }
+
+    /// Moves onto the latest state, replaying this transaction's writes, then
+    /// checks the versions [`insert_versioned`](Self::insert_versioned)
+    /// expected against it.
+    fn rebase(&mut self) -> Result<(), QuadrilleError> {
+        self.update_basis();
+        if self.replaced_all {
+            // the state stands as it is; only keys new to the store need
+            // recording as removed
+            let new: Vec<_> = self
+                .basis
+                .iter_ref()
+                .filter(|(key, _)| !self.current.contains_key(key))
+                .map(|(key, _)| key.to_vec())
+                .collect();
+            for key in new {
+                self.changes.delete(key);
+            }
+        } else {
+            self.set_current(self.replay(self.basis.clone()));
+        }
+        self.reads.get_mut().clear();
+        self.check_versions()
+    }
+
+    /// `onto` with this transaction's writes applied.
+    fn replay(&self, onto: Arc<T>) -> Arc<T> {
+        Self::replay_changes(onto, &self.changes)
+    }
+
+    /// `onto` with `changes` applied.
+    fn replay_changes(onto: Arc<T>, changes: &ChangeSet) -> Arc<T> {
+        let mut out = onto;
+        for (key, change) in changes {
+            out = match change {
+                Change::Set(val) => out.insert(key.clone(), val.clone()).0,
+                Change::Delete => out.remove(key).0,
+            }
+            .into();
+        }
+        out
+    }
+
+    fn check_declared(&self, key: &[u8]) -> Result<(), QuadrilleError> {
+        match &self.declared {
+            Some(declared) if !declared.contains(key) => {
+                Err(QuadrilleError::Undeclared(key.to_vec()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Replaces the working state after a write whose effect on the number
+    /// of entries is not tracked.
+    fn set_current(&mut self, new: Arc<T>) {
+        self.current = new;
+        self.len.set(None);
+    }
+
+    fn current_len(&self) -> usize {
+        self.len.get().unwrap_or_else(|| {
+            let len = self.current.len();
+            self.len.set(Some(len));
+            len
+        })
+    }
+
+    /// Whether committing would publish the basis as it is. Only buffered
+    /// writes count: [`reserve`](Self::reserve) alone moves `current` off
+    /// the basis without changing a thing.
+    fn unchanged(&self) -> bool {
+        Arc::ptr_eq(&self.current, &self.basis) || (self.changes.is_empty() && !self.replaced_all)
+    }
+
+    /// Whether publishing gives any key a TTL.
+    fn sets_ttls(&self) -> bool {
+        self.ttls.values().any(Option::is_some)
+    }
+
+    /// The TTLs to publish with this transaction's state, from those
+    /// committed with the state it replaces: every key written or removed
+    /// loses its committed TTL, and keys given one take theirs. `None` if
+    /// that changes nothing.
+    fn expire(&self, committed: &Expiries) -> Option<Expiries> {
+        let stale = |key: &Vec<u8>| committed.contains_key(key);
+        if !self.sets_ttls() && !self.changes.iter().any(|(key, _)| stale(key)) {
+            return None;
+        }
+        let mut out = committed.clone();
+        for (key, _) in &self.changes {
+            out.remove(key);
+        }
+        for (key, deadline) in &self.ttls {
+            if let Some(deadline) = deadline {
+                out.insert(key.clone(), *deadline);
+            }
+        }
+        Some(out)
+    }
+
+    /// Tells the conflict observer, if any, which of our keys changed
+    /// between `base` and the new basis.
+    fn report_conflict(&self, base: &T, attempt: u32) {
+        let observer = self
+            .shared
+            .observer
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let Some(observer) = observer else {
+            return;
+        };
+        let reads = self.reads.borrow();
+        let touched: BTreeSet<_> = self
+            .changes
+            .iter()
+            .map(|(key, _)| key)
+            .chain(&*reads)
+            .collect();
+        let keys = touched
+            .into_iter()
+            .filter(|key| base.get(key) != self.basis.get(key))
+            .cloned()
+            .collect();
+        observer(&ConflictInfo { keys, attempt });
+    }
+
+    /// Whether any key this transaction read differs between `base` and the
+    /// newly loaded basis. Commutative keys never conflict.
+    fn read_conflict(&self, base: &T) -> bool {
+        let reads = self.reads.borrow();
+        reads
+            .iter()
+            .any(|key| self.shared.combiner(key).is_none() && base.get(key) != self.basis.get(key))
+    }
+
+    /// Fails if `resolved` does not hold every write of this transaction,
+    /// other than to commutative keys, which are merged afterwards.
+    fn check_writes_kept(&self, resolved: &T) -> Result<(), QuadrilleError> {
+        let keys: Vec<_> = self
+            .changes
+            .iter()
+            .filter(|(key, _)| self.shared.combiner(key).is_none())
+            .filter(|(key, change)| match change {
+                // an agreeing write may keep the concurrent commit's value
+                Change::Set(val) => !resolved
+                    .get(key)
+                    .is_some_and(|kept| self.shared.value_eq(&kept, val)),
+                Change::Delete => resolved.contains_key(key),
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        if keys.is_empty() {
+            return Ok(());
+        }
+        trace::event!(lost = keys.len(), "resolve lost writes");
+        Err(QuadrilleError::ResolveLostWrites { keys })
+    }
+
+    /// Sets every commutative key this transaction wrote in `into` to its
+    /// combiner's merge of the `base`, concurrently committed, and buffered
+    /// values.
+    fn combine(&mut self, base: &T, into: Arc<T>) -> Arc<T> {
+        let mut out = into;
+        let mut combined = Vec::new();
+        for (key, _) in &self.changes {
+            let Some(combiner) = self.shared.combiner(key) else {
+                continue;
+            };
+            let val = combiner(
+                base.get(key).as_deref(),
+                self.basis.get(key).as_deref(),
+                self.current.get(key).as_deref(),
+            );
+            out = match &val {
+                Some(val) => out.insert(key.clone(), val.clone()).0,
+                None => out.remove(key).0,
+            }
+            .into();
+            combined.push((key.clone(), val));
+        }
+        for (key, val) in combined {
+            match val {
+                Some(val) => self.changes.set(key, val),
+                None => self.changes.delete(key),
+            }
+        }
+        out
     }
 
     fn update_basis(&mut self) {
-        let (m, b) = self.kv.basis();
-        self.basis_marker = m;
-        self.basis = b;
+        (self.basis, self.expiries) = self.shared.load(&self.kv);
+    }
+
+    /// The pointer identity of `basis`, for publishing against it. Derived
+    /// on demand rather than stored, so a transaction holds no raw pointer
+    /// and can move between threads, e.g. across an `await`.
+    fn marker(&self) -> Basis<T> {
+        Basis::new(Arc::as_ptr(&self.basis) as *mut T)
+    }
+}
+
+impl<T: OrderedKVStore> Transation<T> {
+    /// The entry with the smallest key, including this transaction's writes.
+    pub fn first(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        let first = self.current.first();
+        if let Some((key, _)) = &first {
+            self.record_read(key);
+        }
+        first
+    }
+
+    /// The entry with the largest key, including this transaction's writes.
+    pub fn last(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        let last = self.current.last();
+        if let Some((key, _)) = &last {
+            self.record_read(key);
+        }
+        last
+    }
+
+    /// Removes and returns the entry with the smallest key. Transactions
+    /// popping the same entry conflict, as each reads and deletes its key.
+    pub fn pop_first(&mut self) -> Result<Option<Entry>, QuadrilleError> {
+        self.pop_with(T::first, T::pop_first)
+    }
+
+    /// Removes and returns the entry with the largest key, see
+    /// [`pop_first`](Self::pop_first).
+    pub fn pop_last(&mut self) -> Result<Option<Entry>, QuadrilleError> {
+        self.pop_with(T::last, T::pop_last)
+    }
+
+    fn pop_with(
+        &mut self,
+        peek: fn(&T) -> Option<Entry>,
+        pop: fn(&T) -> (T, Option<Entry>),
+    ) -> Result<Option<Entry>, QuadrilleError> {
+        let Some((key, _)) = peek(&self.current) else {
+            return Ok(None);
+        };
+        self.check_declared(&key)?;
+        self.record_read(&key);
+        self.forget_ttl(&key);
+        let (new, popped) = pop(&self.current);
+        self.set_current(new.into());
+        self.changes.delete(key);
+        Ok(popped)
+    }
+}
+
+/// Builds a store with default settings holding `iter`'s entries, in place
+/// through [`KVStore::builder`]. Later entries replace earlier ones with the
+/// same key.
+impl<T: KVStore> FromIterator<(Vec<u8>, Vec<u8>)> for Quadrille<T> {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, Vec<u8>)>>(iter: I) -> Self {
+        let mut builder = T::builder();
+        builder.extend(iter);
+        Quadrille::builder().initial(builder.finish()).build()
+    }
+}
+
+/// Panics if any entry fails validation; use [`Transation::insert_many`] to
+/// handle that instead.
+impl<T: KVStore> Extend<(Vec<u8>, Vec<u8>)> for Transation<T> {
+    fn extend<I: IntoIterator<Item = (Vec<u8>, Vec<u8>)>>(&mut self, iter: I) {
+        if let Err(err) = self.insert_many(iter) {
+            panic!("extended a transaction with invalid data: {err:?}");
+        }
     }
 }
 
 impl<T: KVStore> Quadrille<T> {
     pub fn transaction(&self) -> Transation<T> {
+        self.transaction_with_priority(Priority::default())
+    }
+
+    pub fn transaction_with_priority(&self, priority: Priority) -> Transation<T> {
+        trace::event!(?priority, "transaction opened");
         let tx_root = self.inner.clone();
-        let (basis_marker, basis) = tx_root.basis();
+        let (basis, expiries) = self.shared.load(&tx_root);
         let current = basis.clone();
         Transation {
             kv: tx_root,
-            basis_marker,
+            shared: self.shared.clone(),
             basis,
             current,
+            priority,
+            isolation: Isolation::default(),
+            expiries,
+            ttls: BTreeMap::new(),
+            changes: ChangeSet::default(),
+            reads: RefCell::default(),
+            declared: None,
+            replaced_all: false,
+            versions: BTreeMap::new(),
+            len: Cell::new(None),
         }
     }
 
+    /// Starts a transaction whose commit checks for concurrent changes as
+    /// `level` says, rather than the default [`Isolation::Serializable`].
+    pub fn transaction_with_isolation(&self, level: Isolation) -> Transation<T> {
+        let mut tx = self.transaction();
+        tx.isolation = level;
+        tx
+    }
+
+    /// Starts a transaction with room to record `capacity` distinct reads
+    /// before its read set has to grow, for transactions that read many
+    /// keys. Otherwise the read set is allocated on the first read and grows
+    /// as needed.
+    pub fn transaction_readset_capacity(&self, capacity: usize) -> Transation<T> {
+        let tx = self.transaction();
+        tx.reads.borrow_mut().reserve(capacity);
+        tx
+    }
+
+    /// Starts a transaction that may only write `keys`; other writes fail
+    /// with [`QuadrilleError::Undeclared`]. Knowing the write set up front,
+    /// a conflicting commit that left all of `keys` alone just reapplies the
+    /// writes on the new state instead of resolving, so the commit only
+    /// conflicts on the declared keys, and on changes to keys it read.
+    pub fn transaction_for(&self, keys: &[&[u8]]) -> Transation<T> {
+        let mut tx = self.transaction();
+        tx.declared = Some(keys.iter().map(|k| k.to_vec()).collect());
+        tx
+    }
+
+    /// The latest committed value at `key`, looked up on a single snapshot
+    /// of the root without opening a transaction.
+    /// Keys whose TTL has passed are absent.
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let (root, expiries) = self.shared.load(&self.inner);
+        let expired = || self.shared.expired(expiries.as_deref(), key);
+        root.get(key).filter(|_| !expired())
+    }
+
+    /// The latest committed value at each of `keys`, in order, all read from
+    /// the same state. Unlike calling [`get`](Self::get) once per key, a
+    /// commit landing meanwhile can never show up in some results and not
+    /// others.
+    pub fn multi_get(&self, keys: &[&[u8]]) -> Vec<Option<Vec<u8>>> {
+        let (root, expiries) = self.shared.load(&self.inner);
+        let expired = |key| self.shared.expired(expiries.as_deref(), key);
+        keys.iter()
+            .map(|key| root.get(key).filter(|_| !expired(key)))
+            .collect()
+    }
+
+    /// Number of entries in the current state.
+    pub fn len(&self) -> usize {
+        self.inner.get().len()
+    }
+
+    /// Whether the current state has no entries. Cheaper than
+    /// [`len`](Self::len) for stores that would have to count.
+    pub fn is_empty(&self) -> bool {
+        self.inner.get().is_empty()
+    }
+
+    pub fn snapshot(&self) -> Snapshot<T> {
+        let (root, expiries) = self.shared.load(&self.inner);
+        let mut snapshot = Snapshot::new(root);
+        if let Some(expiries) = expiries {
+            snapshot.expire_with(expiries, self.shared.config.clock.clone());
+        }
+        snapshot
+    }
+
+    /// Starts a transaction that reads from `snapshot` instead of the current
+    /// state. Commit still targets the live root, so if anything has been
+    /// committed since the snapshot was taken the commit goes through the
+    /// normal conflict path.
+    pub fn transaction_from_snapshot(&self, snapshot: &Snapshot<T>) -> Transation<T> {
+        trace::event!("transaction opened from snapshot");
+        let basis = snapshot.root.clone();
+        Transation {
+            kv: self.inner.clone(),
+            shared: self.shared.clone(),
+            current: basis.clone(),
+            basis,
+            priority: Priority::default(),
+            isolation: Isolation::default(),
+            expiries: snapshot.expiries(),
+            ttls: BTreeMap::new(),
+            changes: ChangeSet::default(),
+            reads: RefCell::default(),
+            declared: None,
+            replaced_all: false,
+            versions: BTreeMap::new(),
+            len: Cell::new(None),
+        }
+    }
+
+    /// Single-key compare-and-set, without buffering anything in a
+    /// transaction of the caller's. If `key` currently holds `expected`
+    /// (`None` meaning absent, or expired) it is set to `new` (`None`
+    /// deleting it) and `Ok(true)` is returned; otherwise nothing changes and
+    /// the result is `Ok(false)`.
+    ///
+    /// Each try is a [`transaction_for`](Self::transaction_for) `key`, so
+    /// the write is checked, clears any TTL, and is validated like any other
+    /// commit. Concurrent commits to other keys only cause a retry. The
+    /// store's commit timeout, if any, bounds the retries.
+    pub fn cas(
+        &self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<Vec<u8>>,
+    ) -> Result<bool, QuadrilleError> {
+        let deadline = self
+            .shared
+            .config
+            .commit_timeout
+            .map(|t| self.shared.now() + t);
+        loop {
+            let mut tx = self.transaction_for(&[key]);
+            if tx.get(key).as_deref() != expected {
+                return Ok(false);
+            }
+            match &new {
+                Some(val) => tx.insert(key.to_vec(), val.clone())?,
+                None => tx.remove(key)?,
+            };
+            match tx.commit() {
+                Ok(_) => return Ok(true),
+                // `key` itself changed; check it against `expected` again
+                Err(QuadrilleError::KeyConflict) => {}
+                Err(err) => return Err(err),
+            }
+            if deadline.is_some_and(|d| self.shared.now() >= d) {
+                return Err(QuadrilleError::Timeout);
+            }
+        }
+    }
+
+    /// Combines every entry of `other` into the store in one transaction:
+    /// each key is set to `combine(key, current, incoming)`, where `current`
+    /// is the value committed before the merge (or an earlier entry of
+    /// `other` with the same key). The whole merge becomes visible at once.
+    ///
+    /// `other` is collected up front, and a conflicting commit re-reads the
+    /// latest state and combines again, so `combine` may run more than once
+    /// per entry.
+    pub fn merge_from(
+        &self,
+        other: impl Iterator<Item = (Vec<u8>, Vec<u8>)>,
+        combine: impl Fn(&[u8], Option<&[u8]>, &[u8]) -> Vec<u8>,
+    ) -> Result<(), QuadrilleError> {
+        let entries: Vec<_> = other.collect();
+        loop {
+            let mut tx = self.transaction();
+            for (key, val) in &entries {
+                let merged = combine(key, tx.get(key).as_deref(), val);
+                tx.insert(key.clone(), merged)?;
+            }
+            match tx.commit() {
+                Err(QuadrilleError::KeyConflict) => continue,
+                result => return result.map(drop),
+            }
+        }
+    }
+
+    /// Atomically empties the store, returning everything it held, and
+    /// drops every TTL. The empty state keeps the store's configuration,
+    /// see [`KVStore::cleared`]. Transactions opened before the drain
+    /// conflict when they commit.
+    pub fn drain(&self) -> Vec<(Vec<u8>, Vec<u8>)> {
+        // what a state carries besides its entries is passed from version to
+        // version, so the latest one stands for the store's
+        let empty = self.inner.get().cleared();
+        let old = self.shared.replace(&self.inner, Arc::new(empty));
+        old.range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Atomically moves every entry whose key is at or after `key`, in byte
+    /// order, into a new store, like [`BTreeMap::split_off`], leaving the
+    /// rest here. The new store has the same configuration and takes the
+    /// moved keys' TTLs, starting at version 0.
+    ///
+    /// Both halves are computed from one state and the lower half published
+    /// in its place, retrying from the latest state if a commit lands
+    /// meanwhile. Transactions opened before the split conflict when they
+    /// commit, and are resolved against the lower half as usual.
+    pub fn split_off(&self, key: &[u8]) -> Quadrille<T> {
+        let mut moved = None;
+        let upper = loop {
+            let (marker, basis) = self.inner.basis();
+            let (lower, _) = basis.retain(|k, _| k < key);
+            let (upper, _) = basis.retain(|k, _| k >= key);
+            let split = |expiries: &Expiries| {
+                let mut lower = expiries.clone();
+                moved = Some(lower.split_off(key));
+                Some(lower)
+            };
+            if self
+                .shared
+                .publish(&self.inner, marker, Arc::new(lower), false, split)
+                .is_ok()
+            {
+                break upper;
+            }
+        };
+        let root = AtomicRoot::new(upper);
+        let mut shared = Shared::new(self.shared.config.clone(), &root);
+        if let Some(moved) = moved {
+            shared.inherit_expiries(Arc::new(moved));
+        }
+        Quadrille {
+            inner: Arc::new(root),
+            shared: Arc::new(shared),
+        }
+    }
+
+    /// Removes every entry whose TTL has passed, returning how many were
+    /// removed.
+    pub fn purge_expired(&self) -> Result<usize, QuadrilleError> {
+        let now = self.shared.now();
+        let mut tx = self.transaction();
+        let expired: Vec<_> = tx
+            .expiries
+            .iter()
+            .flat_map(|expiries| expiries.iter())
+            .filter(|(_, d)| now >= **d)
+            .map(|(k, _)| k.clone())
+            .collect();
+        let mut removed = 0;
+        for key in &expired {
+            if tx.is_expired(key) {
+                removed += usize::from(tx.remove(key)?);
+            }
+        }
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Folds `f` over every entry of the current state, in key order, without
+    /// collecting the entries first.
+    pub fn fold<A>(&self, init: A, f: impl FnMut(A, &[u8], &[u8]) -> A) -> A {
+        self.inner.get().fold(init, f)
+    }
+
+    /// Calls `f` on every entry of the current state, in key order, stopping
+    /// early if it returns [`ControlFlow::Break`]. Returns whether it did.
+    pub fn for_each(&self, f: impl FnMut(&[u8], &[u8]) -> ControlFlow<()>) -> ControlFlow<()> {
+        self.inner.get().try_for_each(f)
+    }
+
+    /// Distribution of committed transaction sizes, if enabled with
+    /// [`QuadrilleBuilder::record_sizes`].
+    pub fn size_histogram(&self) -> Option<SizeHistogram> {
+        self.shared.sizes.as_ref().map(AtomicSizeHistogram::load)
+    }
+
+    /// Counts of commit attempts and conflicts so far, across every handle
+    /// and transaction of this store. Useful for measuring contention.
+    pub fn metrics(&self) -> CommitMetrics {
+        self.shared.metrics.load()
+    }
+
+    /// Calls `observer` each time a commit through any handle of this store
+    /// conflicts and is about to retry, with the keys it read or wrote that
+    /// changed underneath it. The callback runs on the committing thread,
+    /// before the conflict is resolved, so it should be quick. Replaces any
+    /// earlier observer.
+    pub fn observe_conflicts(&self, observer: impl Fn(&ConflictInfo) + Send + Sync + 'static) {
+        *self
+            .shared
+            .observer
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(observer));
+    }
+
+    /// Zeroes the [`metrics`](Self::metrics) and, if recorded, the
+    /// [`size_histogram`](Self::size_histogram), e.g. between phases of a
+    /// measurement. Best-effort: commits racing with the reset may be
+    /// counted either side of it, and each counter is zeroed separately.
+    /// The [`version`](Self::version) is not affected.
+    pub fn stats_reset(&self) {
+        self.shared.metrics.reset();
+        if let Some(sizes) = &self.shared.sizes {
+            sizes.reset();
+        }
+    }
+
+    /// Number of commits published so far. Every commit that changes the
+    /// root, and every [`drain`](Self::drain), advances it by one.
+    pub fn version(&self) -> u64 {
+        self.shared.version.load(Acquire)
+    }
+
+    /// The latest committed value at `key`, served from a per-key cache
+    /// while the [`version`](Self::version) is unchanged since it was last
+    /// read, so repeated reads of a hot key skip the store lookup and copy.
+    /// Any commit invalidates every cached key. The cache is split into
+    /// separately locked shards and holds about a thousand keys, dropping
+    /// stale ones first when full, so use it for a small set of hot keys
+    /// rather than general reads.
+    pub fn hot_get(&self, key: &[u8]) -> Option<Arc<[u8]>> {
+        // the version is bumped after the root is swapped, so a value read
+        // after loading it is at least as new as that version
+        let version = self.version();
+        let val = self.shared.hot.get(key, version).unwrap_or_else(|| {
+            let val: Option<Arc<[u8]>> = self.inner.get().get(key).map(Into::into);
+            self.shared.hot.insert(key, version, val.clone());
+            val
+        });
+        // a TTL can pass without any commit
+        val.filter(|_| !self.shared.expired_now(key))
+    }
+
+    /// The value at `key` in a cached snapshot, if that was taken less than
+    /// `max_age` ago by the store's [`Clock`], and otherwise in a fresh
+    /// snapshot that replaces it. The result may miss commits made in the
+    /// last `max_age`. The cached snapshot keeps its state alive until
+    /// replaced.
+    pub fn get_stale(&self, key: &[u8], max_age: Duration) -> Option<Vec<u8>> {
+        let now = self.shared.now();
+        let read = |root: &T, expiries: Option<&Expiries>| {
+            root.get(key)
+                .filter(|_| !self.shared.expired(expiries, key))
+        };
+        let fresh = |cached: &Option<StaleRoot<T>>| {
+            cached
+                .as_ref()
+                .filter(|(taken, ..)| now.saturating_duration_since(*taken) < max_age)
+                .map(|(_, root, expiries)| read(root, expiries.as_deref()))
+        };
+        let stale = &self.shared.stale;
+        if let Some(val) = fresh(&stale.read().unwrap_or_else(|e| e.into_inner())) {
+            return val;
+        }
+        let mut cached = stale.write().unwrap_or_else(|e| e.into_inner());
+        // another reader may have refreshed it meanwhile
+        if let Some(val) = fresh(&cached) {
+            return val;
+        }
+        let (root, expiries) = self.shared.load(&self.inner);
+        let val = read(&root, expiries.as_deref());
+        *cached = Some((now, root, expiries));
+        val
+    }
+
+    /// Read-only view of the state as of `version`, if it is still retained,
+    /// see [`QuadrilleBuilder::retain_versions`]. Versions that have been
+    /// evicted, or not yet committed, give `None`.
+    pub fn snapshot_at_version(&self, version: u64) -> Option<Snapshot<T>> {
+        let root = self.shared.history.as_ref()?.get(version)?;
+        Some(Snapshot::new(root))
+    }
+
+    /// Drops all but the `keep_last` most recent retained versions, freeing
+    /// whatever memory only the older ones held, and returns how many were
+    /// dropped. The current version counts as one of them. Commits after
+    /// this retain versions as usual, up to
+    /// [`QuadrilleBuilder::retain_versions`].
+    pub fn compact_history(&self, keep_last: usize) -> usize {
+        self.shared
+            .history
+            .as_ref()
+            .map_or(0, |history| history.compact(keep_last))
+    }
+
+    /// The net changes from the state as of `version` to the current one, in
+    /// the store's key order as [`diff_stream`](Self::diff_stream) gives
+    /// them, e.g. to bring a replica at `version` up to date. `None` if
+    /// that version is no longer retained, see
+    /// [`snapshot_at_version`](Self::snapshot_at_version).
+    ///
+    /// The two states are diffed, so this costs a pass over both however
+    /// few commits landed in between, and a key written and then restored
+    /// does not appear.
+    pub fn changes_since(&self, version: u64) -> Option<Vec<(Vec<u8>, Change)>> {
+        let old = self.shared.history.as_ref()?.get(version)?;
+        Some(diff::DiffStream::new(old, self.inner.get()).collect())
+    }
+
+    /// Rough size of the current contents, for capacity planning.
+    ///
+    /// This is an approximation: it ignores allocator overhead, spare `Vec`
+    /// capacity, the store's own node structure, and any sharing between
+    /// versions.
+    pub fn estimate_memory(&self) -> usize {
+        self.inner.get().estimated_size_bytes()
+    }
+
+    /// Forks an independent store starting from the current state, with the
+    /// same configuration and TTLs. Unlike [`clone`](Clone::clone), commits
+    /// to the fork and to `self` are not seen by each other.
+    ///
+    /// The fork starts at version 0 with fresh metrics and history. As states
+    /// are never mutated in place, the current root is shared rather than
+    /// copied; the two stores only diverge as each commits.
+    pub fn deep_clone(&self) -> Quadrille<T> {
+        let (state, expiries) = self.shared.load(&self.inner);
+        let root = AtomicRoot::from_arc(state);
+        let mut shared = Shared::new(self.shared.config.clone(), &root);
+        if let Some(expiries) = expiries {
+            shared.inherit_expiries(expiries);
+        }
+        Quadrille {
+            inner: Arc::new(root),
+            shared: Arc::new(shared),
+        }
+    }
+
+    /// Copies the current state into a new store of another backend, with
+    /// default settings. The target is filled in place through
+    /// [`KVStore::builder`], so converting a large store costs one pass
+    /// rather than one versioned insert per entry.
+    ///
+    /// Configuration is typed by backend and does not carry over, and
+    /// neither do TTLs, which are measured by the source's clock.
+    pub fn clone_into_backend<U: KVStore>(&self) -> Quadrille<U> {
+        let root = self.inner.get();
+        let mut builder = U::builder();
+        builder.extend(root.iter_ref().map(|(k, v)| (k.to_vec(), v.to_vec())));
+        Quadrille::builder().initial(builder.finish()).build()
+    }
+
+    /// Breakdown of the current state's memory into bytes only it holds and
+    /// bytes shared with other live versions, such as those kept by
+    /// [`QuadrilleBuilder::retain_versions`]. Useful for tuning retention.
+    pub fn memory_report(&self) -> MemoryReport {
+        self.inner.get().memory_report()
+    }
+
     pub fn new() -> Quadrille<T> {
-        let root = AtomicRoot::new(T::default());
-        let inner = Arc::new(root);
-        Quadrille { inner }
+        Self::builder().build()
+    }
+
+    /// An empty store whose initial state has room for about `capacity`
+    /// entries, see [`QuadrilleBuilder::capacity`].
+    pub fn with_capacity(capacity: usize) -> Quadrille<T> {
+        Self::builder().capacity(capacity).build()
+    }
+
+    pub fn builder() -> QuadrilleBuilder<T> {
+        QuadrilleBuilder::new()
+    }
+}
+
+#[cfg(feature = "coalesce")]
+impl<T: KVStore + Send + Sync + 'static> Quadrille<T> {
+    /// Starts a background writer that commits submitted transactions in
+    /// batches, see [`Coalescer`]. Transactions committed directly are
+    /// unaffected and still commit immediately.
+    pub fn coalescer(&self) -> Coalescer<T> {
+        Coalescer::new(self.clone())
+    }
+}
+
+impl<T: KVStore> Default for Quadrille<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -0,0 +1,119 @@
+use std::collections::BTreeSet;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::{KVStore, Quadrille, QuadrilleError, Shared, Transation};
+
+/// Number of logical locks keys are hashed onto.
+const SLOTS: usize = 64;
+
+/// Logical locks for [`Quadrille::lock_keys`], each covering every key that
+/// hashes to it.
+pub(crate) struct KeyLocks {
+    slots: Box<[(Mutex<bool>, Condvar)]>,
+}
+
+impl Default for KeyLocks {
+    fn default() -> Self {
+        KeyLocks {
+            slots: (0..SLOTS).map(|_| Default::default()).collect(),
+        }
+    }
+}
+
+impl KeyLocks {
+    fn slot(key: &[u8]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % SLOTS as u64) as usize
+    }
+
+    fn acquire(&self, slot: usize) {
+        let (held, freed) = &self.slots[slot];
+        let mut held = held.lock().unwrap_or_else(|e| e.into_inner());
+        while *held {
+            held = freed.wait(held).unwrap_or_else(|e| e.into_inner());
+        }
+        *held = true;
+    }
+
+    fn release(&self, slot: usize) {
+        let (held, freed) = &self.slots[slot];
+        *held.lock().unwrap_or_else(|e| e.into_inner()) = false;
+        freed.notify_one();
+    }
+}
+
+/// Exclusive use of a set of keys among [`Quadrille::lock_keys`] callers,
+/// with a transaction limited to writing them. It derefs to the
+/// transaction; [`commit`](Self::commit) it to publish, or drop it to roll
+/// back. Either way the locks are released.
+pub struct KeyGuard<T: KVStore> {
+    tx: Option<Transation<T>>,
+    shared: Arc<Shared<T>>,
+    slots: Vec<usize>,
+}
+
+impl<T: KVStore> Quadrille<T> {
+    /// Blocks until no other [`KeyGuard`] covers any of `keys`, then opens a
+    /// transaction over them, like [`transaction_for`](Self::transaction_for).
+    /// For a hot group of keys this replaces repeated retries with waiting
+    /// in line: while the guard is held, no other guard's commit can change
+    /// these keys, so its transaction commits without a write-write
+    /// conflict on them. The locks are purely advisory; ordinary
+    /// transactions can still write the keys, and then conflict as usual.
+    ///
+    /// Keys share locks by hash, so unrelated keys occasionally wait for
+    /// each other. Locks are always taken in the same order whatever the
+    /// order of `keys`, so guards over overlapping sets cannot deadlock.
+    /// Holding two guards on one thread can, as can holding one while
+    /// waiting on another thread that takes one.
+    pub fn lock_keys(&self, keys: &[&[u8]]) -> KeyGuard<T> {
+        let slots: BTreeSet<_> = keys.iter().map(|key| KeyLocks::slot(key)).collect();
+        for &slot in &slots {
+            self.shared.locks.acquire(slot);
+        }
+        KeyGuard {
+            // opened under the locks, so it starts from the latest writes
+            tx: Some(self.transaction_for(keys)),
+            shared: self.shared.clone(),
+            slots: slots.into_iter().collect(),
+        }
+    }
+}
+
+impl<T: KVStore> KeyGuard<T> {
+    /// Commits the transaction, then releases the locks.
+    pub fn commit(mut self) -> Result<Quadrille<T>, QuadrilleError> {
+        self.tx
+            .take()
+            .expect("guard holds its transaction")
+            .commit()
+    }
+}
+
+impl<T: KVStore> Deref for KeyGuard<T> {
+    type Target = Transation<T>;
+
+    fn deref(&self) -> &Transation<T> {
+        self.tx.as_ref().expect("guard holds its transaction")
+    }
+}
+
+impl<T: KVStore> DerefMut for KeyGuard<T> {
+    fn deref_mut(&mut self) -> &mut Transation<T> {
+        self.tx.as_mut().expect("guard holds its transaction")
+    }
+}
+
+impl<T: KVStore> Drop for KeyGuard<T> {
+    fn drop(&mut self) {
+        // an uncommitted transaction is discarded before anyone else can
+        // take the keys
+        self.tx = None;
+        for &slot in self.slots.iter().rev() {
+            self.shared.locks.release(slot);
+        }
+    }
+}
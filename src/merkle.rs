@@ -0,0 +1,212 @@
+//! Merkle hashing of store contents, for comparing replicas without
+//! transferring them.
+//!
+//! The tree is a byte-wise trie over keys: the node for a prefix commits to
+//! the value stored at exactly that key (if any) and to the hash of every
+//! non-empty child prefix one byte longer. Because node boundaries depend
+//! only on the keys, two replicas agree on the shape of every subtree they
+//! share, and [`merkle_diff`] can descend only into the subtrees whose hashes
+//! differ.
+
+use crate::{KVStore, Snapshot};
+
+pub type Hash = [u8; 32];
+
+/// One node of the Merkle trie, as exchanged between replicas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleNode {
+    /// Hash of the value stored at exactly this node's prefix.
+    pub value: Option<Hash>,
+    /// Hashes of the non-empty child subtrees, by next key byte, ascending.
+    pub children: Vec<(u8, Hash)>,
+}
+
+impl MerkleNode {
+    pub fn hash(&self) -> Hash {
+        let mut buf = Vec::with_capacity(2 + 32 + self.children.len() * 33);
+        buf.push(0x01);
+        match &self.value {
+            Some(value) => {
+                buf.push(0x01);
+                buf.extend_from_slice(value);
+            }
+            None => buf.push(0x00),
+        }
+        for (byte, hash) in &self.children {
+            buf.push(*byte);
+            buf.extend_from_slice(hash);
+        }
+        sha256(&buf)
+    }
+}
+
+/// Anything that can answer Merkle node queries, typically a local store or a
+/// proxy for a remote replica.
+pub trait MerkleSource {
+    fn merkle_node(&self, prefix: &[u8]) -> MerkleNode;
+}
+
+impl<T: KVStore> MerkleSource for T {
+    fn merkle_node(&self, prefix: &[u8]) -> MerkleNode {
+        let mut entries = self.scan_prefix(prefix);
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        node_from_entries(prefix, &entries)
+    }
+}
+
+impl<T: KVStore> MerkleSource for Snapshot<T> {
+    fn merkle_node(&self, prefix: &[u8]) -> MerkleNode {
+        self.root.merkle_node(prefix)
+    }
+}
+
+/// Keys whose values differ between `a` and `b`, including keys present on
+/// only one side, in ascending order.
+///
+/// Only subtrees with differing hashes are visited, so the number of queries
+/// is proportional to the number of differences times the key length rather
+/// than to the size of the stores.
+pub fn merkle_diff(a: &impl MerkleSource, b: &impl MerkleSource) -> Vec<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut prefix = Vec::new();
+    diff_node(a, b, &mut prefix, &mut out);
+    out
+}
+
+fn diff_node(
+    a: &impl MerkleSource,
+    b: &impl MerkleSource,
+    prefix: &mut Vec<u8>,
+    out: &mut Vec<Vec<u8>>,
+) {
+    let node_a = a.merkle_node(prefix);
+    let node_b = b.merkle_node(prefix);
+    if node_a.value != node_b.value {
+        out.push(prefix.clone());
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < node_a.children.len() || j < node_b.children.len() {
+        let child_a = node_a.children.get(i);
+        let child_b = node_b.children.get(j);
+        let byte = match (child_a, child_b) {
+            (Some((x, hx)), Some((y, hy))) if x == y => {
+                i += 1;
+                j += 1;
+                if hx == hy {
+                    continue;
+                }
+                *x
+            }
+            (Some((x, _)), Some((y, _))) if x < y => {
+                i += 1;
+                *x
+            }
+            (Some((x, _)), None) => {
+                i += 1;
+                *x
+            }
+            (_, Some((y, _))) => {
+                j += 1;
+                *y
+            }
+            (None, None) => unreachable!(),
+        };
+        prefix.push(byte);
+        diff_node(a, b, prefix, out);
+        prefix.pop();
+    }
+}
+
+/// Builds the node for `prefix` from all entries starting with it, sorted.
+pub(crate) fn node_from_entries(prefix: &[u8], entries: &[(Vec<u8>, Vec<u8>)]) -> MerkleNode {
+    let depth = prefix.len();
+    let mut rest = entries;
+    let mut value = None;
+    if let Some(((k, v), tail)) = rest.split_first() {
+        if k.len() == depth {
+            value = Some(sha256(v));
+            rest = tail;
+        }
+    }
+    let mut children = Vec::new();
+    while let Some((first, _)) = rest.first() {
+        let byte = first[depth];
+        let len = rest.iter().take_while(|(k, _)| k[depth] == byte).count();
+        let (group, tail) = rest.split_at(len);
+        children.push((byte, subtree_hash(depth + 1, group)));
+        rest = tail;
+    }
+    MerkleNode { value, children }
+}
+
+fn subtree_hash(depth: usize, entries: &[(Vec<u8>, Vec<u8>)]) -> Hash {
+    node_from_entries(&entries[0].0[..depth], entries).hash()
+}
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 of `data`.
+pub(crate) fn sha256(data: &[u8]) -> Hash {
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+    for block in msg.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (x, y) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *x = x.wrapping_add(y);
+        }
+    }
+    let mut out = [0; 32];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
@@ -0,0 +1,150 @@
+use std::ops::Bound;
+
+use crate::{prefix_end, KVStore, Quadrille, QuadrilleError, Transation};
+
+/// A view of a [`Quadrille`] confined to the keys starting with a fixed
+/// prefix, see [`Quadrille::namespace`]. Keys are given and returned without
+/// the prefix, so namespaces with different prefixes are separate key
+/// spaces, while still sharing the one store and its commits.
+///
+/// Prefixes are not escaped: if one namespace's prefix begins with
+/// another's, the shorter one also sees the longer one's keys.
+pub struct Namespace<T: KVStore> {
+    kv: Quadrille<T>,
+    prefix: Vec<u8>,
+}
+
+impl<T: KVStore> Clone for Namespace<T> {
+    fn clone(&self) -> Self {
+        Namespace {
+            kv: self.kv.clone(),
+            prefix: self.prefix.clone(),
+        }
+    }
+}
+
+impl<T: KVStore> Quadrille<T> {
+    /// A view of the keys under `prefix`, with the prefix stripped.
+    pub fn namespace(&self, prefix: Vec<u8>) -> Namespace<T> {
+        Namespace {
+            kv: self.clone(),
+            prefix,
+        }
+    }
+}
+
+impl<T: KVStore> Namespace<T> {
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// The latest committed value at `key` in this namespace.
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.kv.get(&self.full_key(key))
+    }
+
+    /// Entries of the latest committed state with keys between `start` and
+    /// `end` in this namespace, prefixes stripped.
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let (start, end) = self.full_range(start, end);
+        let entries = self.kv.snapshot().range(as_ref(&start), as_ref(&end));
+        self.strip(entries)
+    }
+
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let entries = self.kv.snapshot().scan_prefix(&self.full_key(prefix));
+        self.strip(entries)
+    }
+
+    pub fn transaction(&self) -> NamespaceTransation<T> {
+        NamespaceTransation {
+            namespace: self.clone(),
+            tx: self.kv.transaction(),
+        }
+    }
+
+    fn full_key(&self, key: &[u8]) -> Vec<u8> {
+        [self.prefix.as_slice(), key].concat()
+    }
+
+    /// `start..end` within the namespace as bounds on the full keys.
+    fn full_range(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+    ) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+        let start = match start {
+            Bound::Included(k) => Bound::Included(self.full_key(k)),
+            Bound::Excluded(k) => Bound::Excluded(self.full_key(k)),
+            Bound::Unbounded => Bound::Included(self.prefix.clone()),
+        };
+        let end = match end {
+            Bound::Included(k) => Bound::Included(self.full_key(k)),
+            Bound::Excluded(k) => Bound::Excluded(self.full_key(k)),
+            Bound::Unbounded => match prefix_end(&self.prefix) {
+                Some(end) => Bound::Excluded(end),
+                None => Bound::Unbounded,
+            },
+        };
+        (start, end)
+    }
+
+    fn strip(&self, entries: Vec<(Vec<u8>, Vec<u8>)>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let len = self.prefix.len();
+        entries
+            .into_iter()
+            .map(|(mut key, val)| {
+                key.drain(..len);
+                (key, val)
+            })
+            .collect()
+    }
+}
+
+fn as_ref(bound: &Bound<Vec<u8>>) -> Bound<&[u8]> {
+    match bound {
+        Bound::Included(k) => Bound::Included(k),
+        Bound::Excluded(k) => Bound::Excluded(k),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// A transaction over one [`Namespace`], committing through the store's
+/// usual commit path.
+pub struct NamespaceTransation<T: KVStore> {
+    namespace: Namespace<T>,
+    tx: Transation<T>,
+}
+
+impl<T: KVStore> NamespaceTransation<T> {
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.tx.get(&self.namespace.full_key(key))
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.tx.contains_key(&self.namespace.full_key(key))
+    }
+
+    pub fn insert(&mut self, key: Vec<u8>, val: Vec<u8>) -> Result<bool, QuadrilleError> {
+        self.tx.insert(self.namespace.full_key(&key), val)
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Result<bool, QuadrilleError> {
+        self.tx.remove(&self.namespace.full_key(key))
+    }
+
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let (start, end) = self.namespace.full_range(start, end);
+        let entries = self.tx.range(as_ref(&start), as_ref(&end));
+        self.namespace.strip(entries)
+    }
+
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let entries = self.tx.scan_prefix(&self.namespace.full_key(prefix));
+        self.namespace.strip(entries)
+    }
+
+    pub fn commit(self) -> Result<Quadrille<T>, QuadrilleError> {
+        self.tx.commit()
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use crate::{Change, ChangeSet, KVStore, QuadrilleError};
+
+/// Decides what a conflicting commit publishes, in place of the store's own
+/// [`KVStore::resolve`]. Set one with [`QuadrilleBuilder::resolver`].
+///
+/// `base` is the state the transaction's changes were made against, `theirs`
+/// the state concurrent commits have since published, and `ours` the
+/// transaction's state. The returned state is committed against `theirs`,
+/// repeating resolution if that conflicts as well.
+///
+/// [`QuadrilleBuilder::resolver`]: crate::QuadrilleBuilder::resolver
+pub trait ConflictResolver<T>: Send + Sync {
+    fn resolve(&self, base: &T, theirs: &T, ours: &T) -> Result<T, QuadrilleError>;
+
+    /// Like [`resolve`](Self::resolve), also given `changes`, the
+    /// transaction's writes: `ours` is `base` with `changes` applied.
+    /// Resolvers that only need the writes can apply them to `theirs` at a
+    /// cost proportional to the transaction rather than the store. This is
+    /// what commits call; the default ignores `changes`.
+    fn resolve_changes(
+        &self,
+        base: &T,
+        theirs: &T,
+        ours: &T,
+        changes: &ChangeSet,
+    ) -> Result<T, QuadrilleError> {
+        let _ = changes;
+        self.resolve(base, theirs, ours)
+    }
+}
+
+/// Fails every conflicting commit with [`QuadrilleError::KeyConflict`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FailFast;
+
+impl<T: KVStore> ConflictResolver<T> for FailFast {
+    fn resolve(&self, _base: &T, _theirs: &T, _ours: &T) -> Result<T, QuadrilleError> {
+        Err(QuadrilleError::KeyConflict)
+    }
+}
+
+/// Reapplies the transaction's writes on top of the concurrent commits, so
+/// the transaction wins wherever both wrote the same key.
+///
+/// On commit only the transaction's [`ChangeSet`] is applied, so a retry
+/// costs about as much as the transaction's writes; called directly,
+/// [`resolve`](ConflictResolver::resolve) diffs `base` against `ours`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LastWriteWins;
+
+impl<T: KVStore> ConflictResolver<T> for LastWriteWins {
+    fn resolve(&self, base: &T, theirs: &T, ours: &T) -> Result<T, QuadrilleError> {
+        let mut removed: BTreeMap<_, _> = base
+            .range(Bound::Unbounded, Bound::Unbounded)
+            .into_iter()
+            .collect();
+        let mut written = Vec::new();
+        for (key, val) in ours.range(Bound::Unbounded, Bound::Unbounded) {
+            if removed.remove(&key).as_ref() != Some(&val) {
+                written.push((key, val));
+            }
+        }
+        let mut out = if written.is_empty() {
+            theirs.retain(|_, _| true).0
+        } else {
+            theirs.insert_many(written).0
+        };
+        for key in removed.keys() {
+            out = out.remove(key).0;
+        }
+        Ok(out)
+    }
+
+    fn resolve_changes(
+        &self,
+        _base: &T,
+        theirs: &T,
+        _ours: &T,
+        changes: &ChangeSet,
+    ) -> Result<T, QuadrilleError> {
+        let mut written = Vec::new();
+        let mut removed = Vec::new();
+        for (key, change) in changes {
+            match change {
+                Change::Set(val) => written.push((key.clone(), val.clone())),
+                Change::Delete => removed.push(key),
+            }
+        }
+        let mut out = if written.is_empty() {
+            theirs.retain(|_, _| true).0
+        } else {
+            theirs.insert_many(written).0
+        };
+        for key in removed {
+            out = out.remove(key).0;
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,137 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::{KVStore, Quadrille, QuadrilleError, Transation};
+
+/// A store whose keys are partitioned by hash across several independent
+/// roots, so commits to different shards never contend on the same atomic
+/// pointer.
+///
+/// A transaction touching a single shard commits exactly like a
+/// [`Quadrille`] transaction. One touching several holds the commit locks of
+/// those shards, checks that none has moved since it began, and only then
+/// publishes to all of them, so it becomes visible everywhere or nowhere.
+/// Reads of the latest state through [`get`](Self::get) are per shard, and
+/// two of them may straddle a cross-shard commit.
+pub struct ShardedQuadrille<T: KVStore> {
+    shards: Arc<[Shard<T>]>,
+}
+
+struct Shard<T: KVStore> {
+    kv: Quadrille<T>,
+    /// Held while committing, so a cross-shard commit's check that this
+    /// shard is unchanged still holds when it publishes.
+    commit: Mutex<()>,
+}
+
+impl<T: KVStore> Clone for ShardedQuadrille<T> {
+    fn clone(&self) -> Self {
+        ShardedQuadrille {
+            shards: self.shards.clone(),
+        }
+    }
+}
+
+impl<T: KVStore> ShardedQuadrille<T> {
+    /// A store split into `shards` roots.
+    ///
+    /// # Panics
+    ///
+    /// If `shards` is 0.
+    pub fn new(shards: usize) -> ShardedQuadrille<T> {
+        assert!(shards > 0, "a sharded store needs at least one shard");
+        let shards = (0..shards)
+            .map(|_| Shard {
+                kv: Quadrille::new(),
+                commit: Mutex::new(()),
+            })
+            .collect();
+        ShardedQuadrille { shards }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The shard holding `key`.
+    pub fn shard_of(&self, key: &[u8]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    /// The latest committed value at `key`.
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.shards[self.shard_of(key)].kv.snapshot().get(key)
+    }
+
+    pub fn transaction(&self) -> ShardedTransation<T> {
+        ShardedTransation {
+            store: self.clone(),
+            txs: self.shards.iter().map(|s| s.kv.transaction()).collect(),
+        }
+    }
+}
+
+/// A transaction over a [`ShardedQuadrille`], with one underlying
+/// transaction per shard.
+pub struct ShardedTransation<T: KVStore> {
+    store: ShardedQuadrille<T>,
+    txs: Vec<Transation<T>>,
+}
+
+impl<T: KVStore> ShardedTransation<T> {
+    fn tx(&self, key: &[u8]) -> &Transation<T> {
+        &self.txs[self.store.shard_of(key)]
+    }
+
+    fn tx_mut(&mut self, key: &[u8]) -> &mut Transation<T> {
+        let shard = self.store.shard_of(key);
+        &mut self.txs[shard]
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.tx(key).get(key)
+    }
+
+    pub fn insert(&mut self, key: Vec<u8>, val: Vec<u8>) -> Result<bool, QuadrilleError> {
+        self.tx_mut(&key).insert(key, val)
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Result<bool, QuadrilleError> {
+        self.tx_mut(key).remove(key)
+    }
+
+    /// Commits every shard this transaction read or wrote. A single shard
+    /// commits, and resolves conflicts, as a plain transaction would.
+    /// Across several shards the commit is all-or-nothing and fails with
+    /// [`QuadrilleError::KeyConflict`] if any of them has moved on.
+    pub fn commit(self) -> Result<(), QuadrilleError> {
+        let mut involved: Vec<_> = self
+            .txs
+            .into_iter()
+            .enumerate()
+            .filter(|(_, tx)| tx.touched())
+            .collect();
+        let shards = &self.store.shards;
+        if let [(i, _)] = involved[..] {
+            let _lock = lock(&shards[i]);
+            let (_, tx) = involved.pop().unwrap();
+            return tx.commit().map(drop);
+        }
+        // in index order, so concurrent cross-shard commits cannot deadlock
+        let _locks: Vec<_> = involved.iter().map(|(i, _)| lock(&shards[*i])).collect();
+        for (_, tx) in &involved {
+            tx.prepare()?;
+        }
+        for (_, tx) in involved {
+            // prepared under the locks, so this publishes on the first attempt
+            tx.commit()?;
+        }
+        Ok(())
+    }
+}
+
+fn lock<T: KVStore>(shard: &Shard<T>) -> MutexGuard<'_, ()> {
+    shard.commit.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
+
+use crate::merkle::Hash;
+use crate::{Clock, EntryIter, Expiries, KVStore, OrderedKVStore};
+
+/// A consistent, read-only view of the store as of one committed state.
+///
+/// Commits made after the snapshot was taken are never visible through it,
+/// including ones that land partway through iterating it: every read and
+/// iterator goes through the state pinned when the snapshot was taken,
+/// never the live root.
+pub struct Snapshot<T> {
+    pub(crate) root: Arc<T>,
+    /// The TTLs committed with `root`, and the clock they expire by.
+    ttls: Option<(Arc<Expiries>, Arc<dyn Clock>)>,
+    /// Values already read through [`get_cached`](Snapshot::get_cached).
+    cache: Mutex<Cache>,
+}
+
+type Cache = HashMap<Vec<u8>, Option<Arc<[u8]>>>;
+
+/// Clones share the state but start with an empty cache.
+impl<T> Clone for Snapshot<T> {
+    fn clone(&self) -> Self {
+        Snapshot {
+            ttls: self.ttls.clone(),
+            ..Snapshot::new(self.root.clone())
+        }
+    }
+}
+
+impl<T> Snapshot<T> {
+    pub(crate) fn new(root: Arc<T>) -> Snapshot<T> {
+        Snapshot {
+            root,
+            ttls: None,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Treats keys as absent once `expiries` says they have expired.
+    pub(crate) fn expire_with(&mut self, expiries: Arc<Expiries>, clock: Arc<dyn Clock>) {
+        self.ttls = Some((expiries, clock));
+    }
+
+    pub(crate) fn expiries(&self) -> Option<Arc<Expiries>> {
+        self.ttls.as_ref().map(|(expiries, _)| expiries.clone())
+    }
+
+    /// Whether `key`'s TTL has passed by now.
+    fn expired(&self, key: &[u8]) -> bool {
+        self.ttls
+            .as_ref()
+            .is_some_and(|(expiries, clock)| expiries.get(key).is_some_and(|d| clock.now() >= *d))
+    }
+}
+
+impl<T: KVStore> Snapshot<T> {
+    /// Keys whose TTL has passed are absent, as they are in transactions.
+    /// Scans still include them.
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.root.get(key).filter(|_| !self.expired(key))
+    }
+
+    /// Like [`get`](Self::get), but copies each key's value out of the store
+    /// only the first time and hands back the same shared copy afterwards.
+    /// The state can't change under a snapshot, so the cache never goes
+    /// stale, though a cached key still expires on time.
+    ///
+    /// Every key read this way, and its value, stays in memory until the
+    /// snapshot is dropped, even once the state itself has been released
+    /// elsewhere; prefer [`get`](Self::get) for keys read once.
+    pub fn get_cached(&self, key: &[u8]) -> Option<Arc<[u8]>> {
+        if self.expired(key) {
+            return None;
+        }
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(val) = cache.get(key) {
+            return val.clone();
+        }
+        let val: Option<Arc<[u8]>> = self.root.get(key).map(Into::into);
+        cache.insert(key.to_vec(), val.clone());
+        val
+    }
+
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.root.contains_key(key) && !self.expired(key)
+    }
+
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.root.range(start, end)
+    }
+
+    /// Every entry in key order, borrowed from the snapshot without copying.
+    /// The snapshot's state stays alive at least as long as the iterator.
+    pub fn iter_ref(&self) -> EntryIter<'_> {
+        self.root.iter_ref()
+    }
+
+    pub fn range_keys(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<Vec<u8>> {
+        self.root.range_keys(start, end)
+    }
+
+    /// The entry with the largest key less than or equal to `key`.
+    pub fn floor(&self, key: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.root.floor(key)
+    }
+
+    /// The entry with the smallest key greater than or equal to `key`.
+    pub fn ceil(&self, key: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.root.ceil(key)
+    }
+
+    pub fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.root.scan_prefix(prefix)
+    }
+
+    pub fn merkle_root(&self) -> Hash {
+        self.root.merkle_root()
+    }
+}
+
+impl<T: OrderedKVStore> Snapshot<T> {
+    pub fn first(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.root.first()
+    }
+
+    pub fn last(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.root.last()
+    }
+}
+
+/// Copies every entry out in key order as the iteration reaches it.
+impl<'a, T: KVStore> IntoIterator for &'a Snapshot<T> {
+    type Item = (Vec<u8>, Vec<u8>);
+    type IntoIter = std::iter::Map<EntryIter<'a>, fn((&[u8], &[u8])) -> (Vec<u8>, Vec<u8>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_ref().map(|(k, v)| (k.to_vec(), v.to_vec()))
+    }
+}
+
+/// Copies every entry out in key order up front, releasing the snapshot's
+/// state; iterate `&snapshot` to copy them lazily instead.
+impl<T: KVStore> IntoIterator for Snapshot<T> {
+    type Item = (Vec<u8>, Vec<u8>);
+    type IntoIter = std::vec::IntoIter<(Vec<u8>, Vec<u8>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.range(Bound::Unbounded, Bound::Unbounded).into_iter()
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+
+/// Number of buckets in a [`SizeHistogram`]. Bucket 0 counts zero, bucket
+/// `i` counts sizes in `2^(i-1)..2^i`, and the last bucket also counts
+/// everything larger.
+pub const SIZE_BUCKETS: usize = 32;
+
+/// Distribution of committed transaction sizes, see
+/// [`Quadrille::size_histogram`](crate::Quadrille::size_histogram).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeHistogram {
+    /// Distinct keys written per transaction.
+    pub keys: [u64; SIZE_BUCKETS],
+    /// Bytes of keys and values written per transaction.
+    pub bytes: [u64; SIZE_BUCKETS],
+}
+
+impl SizeHistogram {
+    /// The bucket a size of `n` is counted in.
+    pub fn bucket(n: usize) -> usize {
+        ((usize::BITS - n.leading_zeros()) as usize).min(SIZE_BUCKETS - 1)
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct AtomicSizeHistogram {
+    keys: [AtomicU64; SIZE_BUCKETS],
+    bytes: [AtomicU64; SIZE_BUCKETS],
+}
+
+impl AtomicSizeHistogram {
+    pub(crate) fn record(&self, keys: usize, bytes: usize) {
+        self.keys[SizeHistogram::bucket(keys)].fetch_add(1, Relaxed);
+        self.bytes[SizeHistogram::bucket(bytes)].fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn load(&self) -> SizeHistogram {
+        SizeHistogram {
+            keys: self.keys.each_ref().map(|n| n.load(Relaxed)),
+            bytes: self.bytes.each_ref().map(|n| n.load(Relaxed)),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        for n in self.keys.iter().chain(&self.bytes) {
+            n.store(0, Relaxed);
+        }
+    }
+}
+
+/// Counts of attempts to publish a new root, see
+/// [`Quadrille::metrics`](crate::Quadrille::metrics). A transaction that
+/// conflicts and then commits counts once in each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CommitMetrics {
+    /// Attempts that installed their state.
+    pub commits: u64,
+    /// Attempts that found the root changed since their basis.
+    pub conflicts: u64,
+}
+
+impl CommitMetrics {
+    /// Fraction of attempts that conflicted, or 0 before any attempt.
+    pub fn conflict_rate(&self) -> f64 {
+        let attempts = self.commits + self.conflicts;
+        if attempts == 0 {
+            return 0.0;
+        }
+        self.conflicts as f64 / attempts as f64
+    }
+}
+
+/// A commit attempt that found the root changed, as passed to the callback
+/// set with [`Quadrille::observe_conflicts`](crate::Quadrille::observe_conflicts).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictInfo {
+    /// Keys the transaction read or wrote that concurrent commits changed,
+    /// in byte order. Empty if they changed only other keys.
+    pub keys: Vec<Vec<u8>>,
+    /// How many attempts of this commit have conflicted, counting this one.
+    pub attempt: u32,
+}
+
+#[derive(Default)]
+pub(crate) struct AtomicCommitMetrics {
+    commits: AtomicU64,
+    conflicts: AtomicU64,
+}
+
+impl AtomicCommitMetrics {
+    pub(crate) fn record(&self, committed: bool) {
+        let counter = if committed {
+            &self.commits
+        } else {
+            &self.conflicts
+        };
+        counter.fetch_add(1, Relaxed);
+    }
+
+    pub(crate) fn load(&self) -> CommitMetrics {
+        CommitMetrics {
+            commits: self.commits.load(Relaxed),
+            conflicts: self.conflicts.load(Relaxed),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.commits.store(0, Relaxed);
+        self.conflicts.store(0, Relaxed);
+    }
+}
+
+/// Bytes held by one version of a store, split by whether other live
+/// versions also reference them, see
+/// [`Quadrille::memory_report`](crate::Quadrille::memory_report).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    /// Held only by this version, so freed once it is dropped.
+    pub unique_bytes: usize,
+    /// Also referenced by other versions, e.g. retained history, snapshots
+    /// or open transactions.
+    pub shared_bytes: usize,
+}
+
+impl MemoryReport {
+    pub fn total_bytes(&self) -> usize {
+        self.unique_bytes + self.shared_bytes
+    }
+}
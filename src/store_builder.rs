@@ -0,0 +1,31 @@
+use crate::KVStore;
+
+/// Builds a store by writing entries into it in place, for bulk loads; see
+/// [`KVStore::builder`]. Nothing else can see the store until
+/// [`finish`](Self::finish), so no write has to leave the previous version
+/// intact the way [`KVStore::insert`] does.
+pub struct StoreBuilder<T>(T);
+
+impl<T: KVStore> StoreBuilder<T> {
+    pub(crate) fn new(state: T) -> StoreBuilder<T> {
+        StoreBuilder(state)
+    }
+
+    /// Adds `val` at `key`, replacing any earlier value, and returns whether
+    /// it did.
+    pub fn push(&mut self, key: Vec<u8>, val: Vec<u8>) -> bool {
+        self.0.insert_in_place(key, val)
+    }
+
+    pub fn finish(self) -> T {
+        self.0
+    }
+}
+
+impl<T: KVStore> Extend<(Vec<u8>, Vec<u8>)> for StoreBuilder<T> {
+    fn extend<I: IntoIterator<Item = (Vec<u8>, Vec<u8>)>>(&mut self, iter: I) {
+        for (key, val) in iter {
+            self.push(key, val);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+use crate::{valid_range, EntryIter, KVStore, QuadrilleError};
+
+/// A store that keeps up to `THRESHOLD` entries in a sorted `Vec`, searched
+/// linearly, and moves them into a `BTreeMap` once an insert takes it past
+/// that size. For a handful of keys the contiguous `Vec` is cheaper to scan
+/// and to clone than a tree.
+///
+/// Stores never demote back to the `Vec` after removals.
+pub struct Adaptive<const THRESHOLD: usize = 16>(Repr);
+
+enum Repr {
+    Small(Vec<(Vec<u8>, Vec<u8>)>),
+    Large(BTreeMap<Vec<u8>, Vec<u8>>),
+}
+
+impl<const THRESHOLD: usize> Adaptive<THRESHOLD> {
+    /// Whether the entries are still held in the `Vec`.
+    pub fn is_small(&self) -> bool {
+        matches!(self.0, Repr::Small(_))
+    }
+}
+
+impl<const THRESHOLD: usize> Default for Adaptive<THRESHOLD> {
+    fn default() -> Self {
+        Adaptive(Repr::Small(Vec::new()))
+    }
+}
+
+impl<const THRESHOLD: usize> KVStore for Adaptive<THRESHOLD> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        match &self.0 {
+            Repr::Small(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_slice() == key)
+                .map(|(_, v)| v.clone()),
+            Repr::Large(map) => map.get(key).cloned(),
+        }
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        match &self.0 {
+            Repr::Small(entries) => {
+                let mut new = entries.clone();
+                let at = new.iter().position(|(k, _)| *k >= key);
+                match at {
+                    Some(i) if new[i].0 == key => {
+                        new[i].1 = val;
+                        return (Adaptive(Repr::Small(new)), true);
+                    }
+                    Some(i) => new.insert(i, (key, val)),
+                    None => new.push((key, val)),
+                }
+                if new.len() > THRESHOLD {
+                    (Adaptive(Repr::Large(new.into_iter().collect())), false)
+                } else {
+                    (Adaptive(Repr::Small(new)), false)
+                }
+            }
+            Repr::Large(map) => {
+                let mut new = map.clone();
+                let found = new.insert(key, val).is_some();
+                (Adaptive(Repr::Large(new)), found)
+            }
+        }
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        match &self.0 {
+            Repr::Small(entries) => {
+                let mut new = entries.clone();
+                let at = new.iter().position(|(k, _)| k.as_slice() == key);
+                if let Some(i) = at {
+                    new.remove(i);
+                }
+                (Adaptive(Repr::Small(new)), at.is_some())
+            }
+            Repr::Large(map) => {
+                let mut new = map.clone();
+                let found = new.remove(key).is_some();
+                (Adaptive(Repr::Large(new)), found)
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        match &self.0 {
+            Repr::Small(entries) => entries.len(),
+            Repr::Large(map) => map.len(),
+        }
+    }
+
+    fn validate_invariants(&self) -> Result<(), String> {
+        let Repr::Small(entries) = &self.0 else {
+            return Ok(());
+        };
+        if entries.len() > THRESHOLD {
+            return Err(format!("{} entries left in the Vec", entries.len()));
+        }
+        match entries.windows(2).position(|w| w[0].0 >= w[1].0) {
+            Some(i) => Err(format!("entries {i} and {} are out of order", i + 1)),
+            None => Ok(()),
+        }
+    }
+
+    fn resolve(_basis: Arc<Self>, _prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        Err(QuadrilleError::KeyConflict)
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        if !valid_range(start, end, <[u8]>::cmp) {
+            return Vec::new();
+        }
+        match &self.0 {
+            Repr::Small(entries) => entries
+                .iter()
+                .filter(|(k, _)| (start, end).contains(k.as_slice()))
+                .cloned()
+                .collect(),
+            Repr::Large(map) => map
+                .range::<[u8], _>((start, end))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        match &self.0 {
+            Repr::Small(entries) => {
+                Box::new(entries.iter().map(|(k, v)| (k.as_slice(), v.as_slice())))
+            }
+            Repr::Large(map) => Box::new(map.iter().map(|(k, v)| (k.as_slice(), v.as_slice()))),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crate::{EntryIter, KVStore, Quadrille, QuadrilleError};
+
+/// Index bits of the sketch: `2^PRECISION` registers.
+const PRECISION: u32 = 10;
+const REGISTERS: usize = 1 << PRECISION;
+
+/// Wraps any store, keeping a HyperLogLog sketch of the distinct values
+/// written to it, so [`Quadrille::approx_distinct_values`] can estimate how
+/// many there are without a scan.
+///
+/// The sketch has 1024 one-byte registers and travels with each version,
+/// shared until a write changes a register, which few writes do once it has
+/// filled. Estimates have a relative standard error of about 3.3%, so nine
+/// in ten are within about 5.3% of the true count; small counts are nearly
+/// exact.
+///
+/// Values are counted as they are written: removing or overwriting one does
+/// not take it out of the estimate, which covers every value the store has
+/// seen.
+pub struct CardinalityTracked<S> {
+    inner: Arc<S>,
+    sketch: Arc<Sketch>,
+}
+
+#[derive(Clone)]
+struct Sketch([u8; REGISTERS]);
+
+impl Sketch {
+    fn add(&mut self, val: &[u8]) {
+        let (register, rank) = Sketch::position(val);
+        let old = &mut self.0[register];
+        *old = (*old).max(rank);
+    }
+
+    /// Whether recording `val` would change the sketch.
+    fn would_change(&self, val: &[u8]) -> bool {
+        let (register, rank) = Sketch::position(val);
+        rank > self.0[register]
+    }
+
+    /// The register `val` falls in, and the rank it scores there: one more
+    /// than the number of leading zeros in the rest of its hash.
+    fn position(val: &[u8]) -> (usize, u8) {
+        let mut hasher = DefaultHasher::new();
+        val.hash(&mut hasher);
+        let hash = hasher.finish();
+        let register = (hash >> (64 - PRECISION)) as usize;
+        let rest = hash << PRECISION;
+        let rank = rest.leading_zeros().min(64 - PRECISION) + 1;
+        (register, rank as u8)
+    }
+
+    fn union(&self, other: &Sketch) -> Sketch {
+        let mut out = self.clone();
+        for (a, b) in out.0.iter_mut().zip(other.0) {
+            *a = (*a).max(b);
+        }
+        out
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.0.iter().map(|&r| 2f64.powi(-i32::from(r))).sum();
+        let raw = alpha * m * m / sum;
+        let empty = self.0.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && empty > 0 {
+            // linear counting is more accurate while many registers are empty
+            return (m * (m / empty as f64).ln()).round() as u64;
+        }
+        raw.round() as u64
+    }
+}
+
+impl<S: KVStore> CardinalityTracked<S> {
+    /// Tracks `inner`, sketching the values it already holds. Start a
+    /// [`Quadrille`] from it with
+    /// [`QuadrilleBuilder::initial`](crate::QuadrilleBuilder::initial).
+    pub fn new(inner: S) -> CardinalityTracked<S> {
+        let mut sketch = Sketch([0; REGISTERS]);
+        for (_, val) in inner.iter_ref() {
+            sketch.add(val);
+        }
+        CardinalityTracked {
+            inner: Arc::new(inner),
+            sketch: Arc::new(sketch),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Estimated number of distinct values written to this version or the
+    /// ones it was derived from.
+    pub fn approx_distinct_values(&self) -> u64 {
+        self.sketch.estimate()
+    }
+
+    /// `inner` as the next version, with `val`, if any, added to the sketch.
+    fn wrap(&self, inner: S, val: Option<&[u8]>) -> CardinalityTracked<S> {
+        let mut sketch = self.sketch.clone();
+        if let Some(val) = val.filter(|val| sketch.would_change(val)) {
+            Arc::make_mut(&mut sketch).add(val);
+        }
+        CardinalityTracked {
+            inner: Arc::new(inner),
+            sketch,
+        }
+    }
+}
+
+impl<S: KVStore> Default for CardinalityTracked<S> {
+    fn default() -> Self {
+        CardinalityTracked::new(S::default())
+    }
+}
+
+impl<S: KVStore> KVStore for CardinalityTracked<S> {
+    fn with_capacity(capacity: usize) -> Self {
+        CardinalityTracked::new(S::with_capacity(capacity))
+    }
+
+    /// Keeps the sketch, which covers every value the store has seen.
+    fn cleared(&self) -> Self {
+        self.wrap(self.inner.cleared(), None)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.inner.get(key)
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        let (new, replaced) = self.inner.insert(key, val.clone());
+        (self.wrap(new, Some(&val)), replaced)
+    }
+
+    fn insert_in_place(&mut self, key: Vec<u8>, val: Vec<u8>) -> bool {
+        if self.sketch.would_change(&val) {
+            Arc::make_mut(&mut self.sketch).add(&val);
+        }
+        match Arc::get_mut(&mut self.inner) {
+            Some(inner) => inner.insert_in_place(key, val),
+            None => {
+                let (new, replaced) = self.inner.insert(key, val);
+                self.inner = Arc::new(new);
+                replaced
+            }
+        }
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let (new, found) = self.inner.remove(key);
+        (self.wrap(new, None), found)
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.inner.contains_key(key)
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    fn resolve(basis: Arc<Self>, prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        let inner = S::resolve(basis.inner.clone(), prev.inner.clone())?;
+        // the resolved state may hold values from either side
+        let sketch = Arc::new(basis.sketch.union(&prev.sketch));
+        Ok(Arc::new(CardinalityTracked { inner, sketch }))
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.inner.range(start, end)
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        self.inner.iter_ref()
+    }
+
+    fn compare_keys(a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        S::compare_keys(a, b)
+    }
+
+    fn validate_invariants(&self) -> Result<(), String> {
+        self.inner.validate_invariants()
+    }
+
+    fn estimated_size_bytes(&self) -> usize {
+        self.inner.estimated_size_bytes()
+    }
+}
+
+impl<S: KVStore> Quadrille<CardinalityTracked<S>> {
+    /// Estimated number of distinct values ever committed, see
+    /// [`CardinalityTracked`].
+    pub fn approx_distinct_values(&self) -> u64 {
+        self.inner.get().approx_distinct_values()
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+
+use crate::KVStore;
+
+/// Runs `ops` random writes against `T`, starting from an empty store and
+/// seeded by `seed`, and after each one checks
+/// [`validate_invariants`](KVStore::validate_invariants) and that the
+/// entries match a `BTreeMap` given the same writes. Returns the first
+/// failure, naming the operation that caused it.
+///
+/// Meant for testing new backends; keys are short and drawn from a small
+/// space so that writes often hit existing entries.
+pub fn fuzz_invariants<T: KVStore>(seed: u64, ops: usize) -> Result<(), String> {
+    // xorshift never leaves zero
+    let mut rng = seed | 1;
+    let mut next = move || {
+        rng ^= rng << 13;
+        rng ^= rng >> 7;
+        rng ^= rng << 17;
+        rng
+    };
+    let mut store = T::default();
+    let mut model = BTreeMap::new();
+    for i in 0..ops {
+        let r = next();
+        let key = vec![(r % 6) as u8, (r >> 8) as u8 % 6];
+        let op = match r >> 16 & 7 {
+            0 | 1 => {
+                store = store.remove(&key).0;
+                model.remove(&key);
+                format!("remove {key:?}")
+            }
+            2 => {
+                let entries: Vec<_> = (0..3u8)
+                    .map(|j| (vec![key[0], key[1].wrapping_add(j)], vec![j]))
+                    .collect();
+                store = store.insert_many(entries.clone()).0;
+                model.extend(entries);
+                format!("insert_many at {key:?}")
+            }
+            3 => {
+                store = store.remove_prefix(&key[..1]).0;
+                model.retain(|k: &Vec<u8>, _| !k.starts_with(&key[..1]));
+                format!("remove_prefix {:?}", &key[..1])
+            }
+            _ => {
+                let val = vec![(r >> 24) as u8];
+                store = store.insert(key.clone(), val.clone()).0;
+                model.insert(key.clone(), val);
+                format!("insert {key:?}")
+            }
+        };
+        store
+            .validate_invariants()
+            .map_err(|err| format!("after op {i} ({op}): {err}"))?;
+        let entries = store.iter_ref();
+        if !entries.eq(model.iter().map(|(k, v)| (k.as_slice(), v.as_slice()))) {
+            return Err(format!(
+                "after op {i} ({op}): entries differ from the model"
+            ));
+        }
+    }
+    Ok(())
+}
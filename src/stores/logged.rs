@@ -0,0 +1,241 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::{Bound, ControlFlow};
+use std::sync::Arc;
+
+use crate::{EntryIter, KVStore, QuadrilleError};
+
+/// One call made on a [`Logged`] store, with its outcome.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogEvent {
+    Get { key: Vec<u8>, found: bool },
+    Insert { key: Vec<u8>, replaced: bool },
+    Remove { key: Vec<u8>, found: bool },
+    Resolve { ok: bool },
+}
+
+/// Receives every [`LogEvent`] of a [`Logged`] store, from whichever thread
+/// made the call.
+pub type LogSink = Arc<dyn Fn(LogEvent) + Send + Sync>;
+
+/// Wraps any store, reporting each `get`, `insert`, `remove` and `resolve`
+/// to a sink before passing on the result; `contains_key` reports as a
+/// `get`, writes in place as the write they make, and `retain` as a
+/// `remove` of each key it drops. Other operations are
+/// passed straight through to the inner store, so backends keep their own
+/// fast paths, and include nothing in the log.
+///
+/// Every version derived from a logged state reports to the same sink. To
+/// log a [`Quadrille`], start it from one with
+/// [`QuadrilleBuilder::initial`]; a default `Logged` has no sink and adds
+/// only an `Option` check per call.
+///
+/// [`Quadrille`]: crate::Quadrille
+/// [`QuadrilleBuilder::initial`]: crate::QuadrilleBuilder::initial
+pub struct Logged<S> {
+    inner: Arc<S>,
+    sink: Option<LogSink>,
+}
+
+impl<S> Logged<S> {
+    pub fn new(inner: S, sink: impl Fn(LogEvent) + Send + Sync + 'static) -> Logged<S> {
+        Logged {
+            inner: Arc::new(inner),
+            sink: Some(Arc::new(sink)),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// `inner` as the next version, reporting to the same sink.
+    fn wrap(&self, inner: S) -> Logged<S> {
+        Logged {
+            inner: Arc::new(inner),
+            sink: self.sink.clone(),
+        }
+    }
+
+    fn log(&self, event: impl FnOnce() -> LogEvent) {
+        if let Some(sink) = &self.sink {
+            sink(event());
+        }
+    }
+}
+
+impl<S: Default> Default for Logged<S> {
+    fn default() -> Self {
+        Logged {
+            inner: Arc::default(),
+            sink: None,
+        }
+    }
+}
+
+impl<S> fmt::Debug for Logged<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Logged")
+            .field("sink", &self.sink.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: KVStore> KVStore for Logged<S> {
+    fn with_capacity(capacity: usize) -> Self {
+        Logged {
+            inner: Arc::new(S::with_capacity(capacity)),
+            sink: None,
+        }
+    }
+
+    fn cleared(&self) -> Self {
+        self.wrap(self.inner.cleared())
+    }
+
+    fn reserve(&self, additional: usize) -> Option<Self> {
+        self.inner.reserve(additional).map(|new| self.wrap(new))
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let val = self.inner.get(key);
+        self.log(|| LogEvent::Get {
+            key: key.to_vec(),
+            found: val.is_some(),
+        });
+        val
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        let logged = self.sink.is_some().then(|| key.clone());
+        let (new, replaced) = self.inner.insert(key, val);
+        if let Some(key) = logged {
+            self.log(|| LogEvent::Insert { key, replaced });
+        }
+        (self.wrap(new), replaced)
+    }
+
+    fn insert_in_place(&mut self, key: Vec<u8>, val: Vec<u8>) -> bool {
+        let logged = self.sink.is_some().then(|| key.clone());
+        let replaced = match Arc::get_mut(&mut self.inner) {
+            Some(inner) => inner.insert_in_place(key, val),
+            None => {
+                let (new, replaced) = self.inner.insert(key, val);
+                self.inner = Arc::new(new);
+                replaced
+            }
+        };
+        if let Some(key) = logged {
+            self.log(|| LogEvent::Insert { key, replaced });
+        }
+        replaced
+    }
+
+    fn remove_in_place(&mut self, key: &[u8]) -> bool {
+        let found = match Arc::get_mut(&mut self.inner) {
+            Some(inner) => inner.remove_in_place(key),
+            None => {
+                let (new, found) = self.inner.remove(key);
+                self.inner = Arc::new(new);
+                found
+            }
+        };
+        self.log(|| LogEvent::Remove {
+            key: key.to_vec(),
+            found,
+        });
+        found
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let (new, found) = self.inner.remove(key);
+        self.log(|| LogEvent::Remove {
+            key: key.to_vec(),
+            found,
+        });
+        (self.wrap(new), found)
+    }
+
+    fn retain(&self, pred: impl Fn(&[u8], &[u8]) -> bool) -> (Self, usize) {
+        if self.sink.is_none() {
+            let (new, removed) = self.inner.retain(pred);
+            return (self.wrap(new), removed);
+        }
+        let dropped = RefCell::new(Vec::new());
+        let (new, removed) = self.inner.retain(|key, val| {
+            let keep = pred(key, val);
+            if !keep {
+                dropped.borrow_mut().push(key.to_vec());
+            }
+            keep
+        });
+        for key in dropped.into_inner() {
+            self.log(|| LogEvent::Remove { key, found: true });
+        }
+        (self.wrap(new), removed)
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        let found = self.inner.contains_key(key);
+        self.log(|| LogEvent::Get {
+            key: key.to_vec(),
+            found,
+        });
+        found
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    fn resolve(basis: Arc<Self>, prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        let resolved = S::resolve(basis.inner.clone(), prev.inner.clone());
+        basis.log(|| LogEvent::Resolve {
+            ok: resolved.is_ok(),
+        });
+        Ok(Arc::new(Logged {
+            inner: resolved?,
+            sink: basis.sink.clone(),
+        }))
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.inner.range(start, end)
+    }
+
+    fn range_count(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> usize {
+        self.inner.range_count(start, end)
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        self.inner.iter_ref()
+    }
+
+    fn fold<A>(&self, init: A, f: impl FnMut(A, &[u8], &[u8]) -> A) -> A {
+        self.inner.fold(init, f)
+    }
+
+    fn try_for_each(&self, f: impl FnMut(&[u8], &[u8]) -> ControlFlow<()>) -> ControlFlow<()> {
+        self.inner.try_for_each(f)
+    }
+
+    fn structurally_equal(&self, other: &Self) -> bool {
+        self.inner.structurally_equal(&other.inner)
+    }
+
+    fn compare_keys(a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        S::compare_keys(a, b)
+    }
+
+    fn validate_invariants(&self) -> Result<(), String> {
+        self.inner.validate_invariants()
+    }
+
+    fn estimated_size_bytes(&self) -> usize {
+        self.inner.estimated_size_bytes()
+    }
+}
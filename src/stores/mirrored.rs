@@ -0,0 +1,122 @@
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crate::{EntryIter, KVStore, QuadrilleError};
+
+/// A store that applies every write to both a primary and a secondary
+/// store, for migrating between backends while keeping the old one in sync.
+/// Reads, scans and size estimates are served from the primary alone.
+///
+/// The two only hold the same entries if they did when the mirror was
+/// built, e.g. both empty; afterwards each write goes to both, so keys
+/// written through the mirror always agree. Conflicts are resolved by each
+/// store's own `resolve`, and fail if either fails.
+pub struct Mirrored<P, S> {
+    primary: Arc<P>,
+    secondary: Arc<S>,
+}
+
+impl<P, S> Mirrored<P, S> {
+    /// Mirrors writes to `primary` onto `secondary`. Start a
+    /// [`Quadrille`](crate::Quadrille) from it with
+    /// [`QuadrilleBuilder::initial`](crate::QuadrilleBuilder::initial).
+    pub fn new(primary: P, secondary: S) -> Mirrored<P, S> {
+        Mirrored {
+            primary: Arc::new(primary),
+            secondary: Arc::new(secondary),
+        }
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    pub fn secondary(&self) -> &S {
+        &self.secondary
+    }
+}
+
+impl<P: Default, S: Default> Default for Mirrored<P, S> {
+    fn default() -> Self {
+        Mirrored::new(P::default(), S::default())
+    }
+}
+
+impl<P: KVStore, S: KVStore> KVStore for Mirrored<P, S> {
+    fn with_capacity(capacity: usize) -> Self {
+        Mirrored::new(P::with_capacity(capacity), S::with_capacity(capacity))
+    }
+
+    fn cleared(&self) -> Self {
+        Mirrored::new(self.primary.cleared(), self.secondary.cleared())
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.primary.get(key)
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        let (secondary, _) = self.secondary.insert(key.clone(), val.clone());
+        let (primary, found) = self.primary.insert(key, val);
+        (Mirrored::new(primary, secondary), found)
+    }
+
+    fn insert_many(&self, entries: Vec<(Vec<u8>, Vec<u8>)>) -> (Self, usize) {
+        let (secondary, _) = self.secondary.insert_many(entries.clone());
+        let (primary, replaced) = self.primary.insert_many(entries);
+        (Mirrored::new(primary, secondary), replaced)
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let (secondary, _) = self.secondary.remove(key);
+        let (primary, found) = self.primary.remove(key);
+        (Mirrored::new(primary, secondary), found)
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> (Self, usize) {
+        let (secondary, _) = self.secondary.remove_prefix(prefix);
+        let (primary, removed) = self.primary.remove_prefix(prefix);
+        (Mirrored::new(primary, secondary), removed)
+    }
+
+    fn retain(&self, pred: impl Fn(&[u8], &[u8]) -> bool) -> (Self, usize) {
+        let (secondary, _) = self.secondary.retain(&pred);
+        let (primary, removed) = self.primary.retain(pred);
+        (Mirrored::new(primary, secondary), removed)
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.primary.contains_key(key)
+    }
+
+    fn len(&self) -> usize {
+        self.primary.len()
+    }
+
+    fn resolve(basis: Arc<Self>, prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        let primary = P::resolve(basis.primary.clone(), prev.primary.clone())?;
+        let secondary = S::resolve(basis.secondary.clone(), prev.secondary.clone())?;
+        Ok(Arc::new(Mirrored { primary, secondary }))
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.primary.range(start, end)
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        self.primary.iter_ref()
+    }
+
+    fn compare_keys(a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        P::compare_keys(a, b)
+    }
+
+    fn validate_invariants(&self) -> Result<(), String> {
+        self.primary.validate_invariants()?;
+        self.secondary.validate_invariants()
+    }
+
+    fn estimated_size_bytes(&self) -> usize {
+        self.primary.estimated_size_bytes()
+    }
+}
@@ -1,3 +1,21 @@
+mod adaptive;
+mod cardinality;
+mod fuzz;
+mod logged;
+mod mirrored;
 mod naive_btree;
+mod naive_hash_map;
+mod ordered_btree;
+mod sorted_vec;
+mod treap;
 
+pub use adaptive::Adaptive;
+pub use cardinality::CardinalityTracked;
+pub use fuzz::fuzz_invariants;
+pub use logged::{LogEvent, LogSink, Logged};
+pub use mirrored::Mirrored;
 pub use naive_btree::NaiveBTree;
+pub use naive_hash_map::NaiveHashMap;
+pub use ordered_btree::{Comparator, NaturalOrder, OrderedBTree, ReverseOrder};
+pub use sorted_vec::SortedVecStore;
+pub use treap::Treap;
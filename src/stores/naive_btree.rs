@@ -1,7 +1,10 @@
 use std::collections::BTreeMap;
+use std::ops::{Bound, ControlFlow};
 use std::sync::Arc;
 
-use crate::{KVStore, QuadrilleError};
+use crate::{
+    entry_size, prefix_end, valid_range, Entry, EntryIter, KVStore, OrderedKVStore, QuadrilleError,
+};
 
 #[derive(Default)]
 pub struct NaiveBTree(BTreeMap<Vec<u8>, Vec<u8>>);
@@ -17,7 +20,144 @@ impl KVStore for NaiveBTree {
         (NaiveBTree(new), found)
     }
 
+    fn insert_many(&self, entries: Vec<(Vec<u8>, Vec<u8>)>) -> (Self, usize) {
+        let mut new = self.0.clone();
+        let replaced = entries
+            .into_iter()
+            .filter_map(|(k, v)| new.insert(k, v))
+            .count();
+        (NaiveBTree(new), replaced)
+    }
+
+    fn insert_in_place(&mut self, key: Vec<u8>, val: Vec<u8>) -> bool {
+        self.0.insert(key, val).is_some()
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let mut new = self.0.clone();
+        let found = new.remove(key).is_some();
+        (NaiveBTree(new), found)
+    }
+
+    fn remove_in_place(&mut self, key: &[u8]) -> bool {
+        self.0.remove(key).is_some()
+    }
+
+    fn remove_prefix(&self, prefix: &[u8]) -> (Self, usize) {
+        let mut new = self.0.clone();
+        let mut matched = new.split_off(prefix);
+        if let Some(end) = prefix_end(prefix) {
+            new.append(&mut matched.split_off(&end));
+        }
+        (NaiveBTree(new), matched.len())
+    }
+
+    fn retain(&self, pred: impl Fn(&[u8], &[u8]) -> bool) -> (Self, usize) {
+        let mut new = self.0.clone();
+        new.retain(|k, v| pred(k, v));
+        let removed = self.0.len() - new.len();
+        (NaiveBTree(new), removed)
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.0.contains_key(key)
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
     fn resolve(_basis: Arc<Self>, _prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
         Err(QuadrilleError::KeyConflict)
     }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        if !valid_range(start, end, <[u8]>::cmp) {
+            return Vec::new();
+        }
+        self.0
+            .range::<[u8], _>((start, end))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        Box::new(self.0.iter().map(|(k, v)| (k.as_slice(), v.as_slice())))
+    }
+
+    fn structurally_equal(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+
+    fn fold<A>(&self, init: A, mut f: impl FnMut(A, &[u8], &[u8]) -> A) -> A {
+        self.0.iter().fold(init, |acc, (k, v)| f(acc, k, v))
+    }
+
+    fn try_for_each(&self, mut f: impl FnMut(&[u8], &[u8]) -> ControlFlow<()>) -> ControlFlow<()> {
+        self.0.iter().try_for_each(|(k, v)| f(k, v))
+    }
+
+    fn range_keys(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<Vec<u8>> {
+        if !valid_range(start, end, <[u8]>::cmp) {
+            return Vec::new();
+        }
+        self.0
+            .range::<[u8], _>((start, end))
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+
+    fn range_count(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> usize {
+        if !valid_range(start, end, <[u8]>::cmp) {
+            return 0;
+        }
+        self.0.range::<[u8], _>((start, end)).count()
+    }
+
+    fn floor(&self, key: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.0
+            .range::<[u8], _>((Bound::Unbounded, Bound::Included(key)))
+            .next_back()
+            .map(|(k, v)| (k.clone(), v.clone()))
+    }
+
+    fn ceil(&self, key: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.0
+            .range::<[u8], _>((Bound::Included(key), Bound::Unbounded))
+            .next()
+            .map(|(k, v)| (k.clone(), v.clone()))
+    }
+
+    fn estimated_size_bytes(&self) -> usize {
+        self.0.iter().map(|(k, v)| entry_size(k, v)).sum()
+    }
+}
+
+impl OrderedKVStore for NaiveBTree {
+    fn first(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.0.iter().next().map(|(k, v)| (k.clone(), v.clone()))
+    }
+
+    fn last(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.0
+            .iter()
+            .next_back()
+            .map(|(k, v)| (k.clone(), v.clone()))
+    }
+
+    fn pop_first(&self) -> (Self, Option<Entry>) {
+        let mut new = self.0.clone();
+        let first = new.pop_first();
+        (NaiveBTree(new), first)
+    }
+
+    fn pop_last(&self) -> (Self, Option<Entry>) {
+        let mut new = self.0.clone();
+        let last = new.pop_last();
+        (NaiveBTree(new), last)
+    }
 }
@@ -0,0 +1,138 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::ops::{Bound, ControlFlow, RangeBounds};
+use std::sync::Arc;
+
+use crate::{entry_size, valid_range, EntryIter, KVStore, QuadrilleError};
+
+/// A store backed by a `HashMap`, copied on every write like [`NaiveBTree`].
+/// Point reads and writes avoid tree comparisons, but ordered operations
+/// such as [`range`](KVStore::range) sort the matching entries on each call.
+///
+/// Keys are hashed with `S`, by default the DoS-resistant [`RandomState`];
+/// a faster non-cryptographic hasher can be substituted where keys are
+/// trusted.
+///
+/// Copies keep the map's capacity, so a store built with
+/// [`Quadrille::with_capacity`] does not rehash while growing to that size.
+///
+/// [`NaiveBTree`]: crate::stores::NaiveBTree
+/// [`Quadrille::with_capacity`]: crate::Quadrille::with_capacity
+#[derive(Default)]
+pub struct NaiveHashMap<S = RandomState>(HashMap<Vec<u8>, Vec<u8>, S>);
+
+impl<S> NaiveHashMap<S> {
+    /// Entries the map can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+
+    fn sorted(&self) -> Vec<(&[u8], &[u8])> {
+        let mut entries: Vec<_> = self
+            .0
+            .iter()
+            .map(|(k, v)| (k.as_slice(), v.as_slice()))
+            .collect();
+        entries.sort_unstable_by_key(|&(k, _)| k);
+        entries
+    }
+}
+
+impl<S: BuildHasher + Default + Clone> KVStore for NaiveHashMap<S> {
+    fn with_capacity(capacity: usize) -> Self {
+        NaiveHashMap(HashMap::with_capacity_and_hasher(capacity, S::default()))
+    }
+
+    fn reserve(&self, additional: usize) -> Option<Self> {
+        let mut new = self.0.clone();
+        new.reserve(additional);
+        Some(NaiveHashMap(new))
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key).cloned()
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        let mut new = self.0.clone();
+        let found = new.insert(key, val).is_some();
+        (NaiveHashMap(new), found)
+    }
+
+    fn insert_many(&self, entries: Vec<(Vec<u8>, Vec<u8>)>) -> (Self, usize) {
+        let mut new = self.0.clone();
+        let replaced = entries
+            .into_iter()
+            .filter_map(|(k, v)| new.insert(k, v))
+            .count();
+        (NaiveHashMap(new), replaced)
+    }
+
+    fn insert_in_place(&mut self, key: Vec<u8>, val: Vec<u8>) -> bool {
+        self.0.insert(key, val).is_some()
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let mut new = self.0.clone();
+        let found = new.remove(key).is_some();
+        (NaiveHashMap(new), found)
+    }
+
+    fn remove_in_place(&mut self, key: &[u8]) -> bool {
+        self.0.remove(key).is_some()
+    }
+
+    fn retain(&self, pred: impl Fn(&[u8], &[u8]) -> bool) -> (Self, usize) {
+        let mut new = self.0.clone();
+        new.retain(|k, v| pred(k, v));
+        let removed = self.0.len() - new.len();
+        (NaiveHashMap(new), removed)
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.0.contains_key(key)
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn resolve(_basis: Arc<Self>, _prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        Err(QuadrilleError::KeyConflict)
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        if !valid_range(start, end, <[u8]>::cmp) {
+            return Vec::new();
+        }
+        let mut entries: Vec<_> = self
+            .0
+            .iter()
+            .filter(|(k, _)| (start, end).contains(k.as_slice()))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        entries.sort_unstable();
+        entries
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        Box::new(self.sorted().into_iter())
+    }
+
+    fn try_for_each(&self, mut f: impl FnMut(&[u8], &[u8]) -> ControlFlow<()>) -> ControlFlow<()> {
+        self.sorted().into_iter().try_for_each(|(k, v)| f(k, v))
+    }
+
+    fn structurally_equal(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+
+    fn estimated_size_bytes(&self) -> usize {
+        self.0.iter().map(|(k, v)| entry_size(k, v)).sum()
+    }
+}
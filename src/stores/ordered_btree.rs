@@ -0,0 +1,150 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use crate::{valid_range, EntryIter, KVStore, QuadrilleError};
+
+/// A total order over byte-string keys, used by [`OrderedBTree`] in place of
+/// the natural lexicographic order.
+pub trait Comparator {
+    fn compare(a: &[u8], b: &[u8]) -> Ordering;
+}
+
+/// Plain lexicographic byte order, as used by `NaiveBTree`.
+pub struct NaturalOrder;
+
+impl Comparator for NaturalOrder {
+    fn compare(a: &[u8], b: &[u8]) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// Lexicographic byte order, reversed.
+pub struct ReverseOrder;
+
+impl Comparator for ReverseOrder {
+    fn compare(a: &[u8], b: &[u8]) -> Ordering {
+        b.cmp(a)
+    }
+}
+
+/// Key newtype whose `Ord` is driven by `C`.
+struct OrderedKey<C>(Vec<u8>, PhantomData<C>);
+
+impl<C> OrderedKey<C> {
+    fn new(key: Vec<u8>) -> Self {
+        OrderedKey(key, PhantomData)
+    }
+}
+
+impl<C> Clone for OrderedKey<C> {
+    fn clone(&self) -> Self {
+        OrderedKey::new(self.0.clone())
+    }
+}
+
+impl<C: Comparator> PartialEq for OrderedKey<C> {
+    fn eq(&self, other: &Self) -> bool {
+        C::compare(&self.0, &other.0) == Ordering::Equal
+    }
+}
+
+impl<C: Comparator> Eq for OrderedKey<C> {}
+
+impl<C: Comparator> PartialOrd for OrderedKey<C> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<C: Comparator> Ord for OrderedKey<C> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        C::compare(&self.0, &other.0)
+    }
+}
+
+/// A `BTreeMap` store whose key order, and therefore range and prefix scan
+/// order, is given by the comparator `C`.
+pub struct OrderedBTree<C>(BTreeMap<OrderedKey<C>, Vec<u8>>);
+
+impl<C> Default for OrderedBTree<C> {
+    fn default() -> Self {
+        OrderedBTree(BTreeMap::new())
+    }
+}
+
+fn owned_bound<C>(bound: Bound<&[u8]>) -> Bound<OrderedKey<C>> {
+    match bound {
+        Bound::Included(k) => Bound::Included(OrderedKey::new(k.to_vec())),
+        Bound::Excluded(k) => Bound::Excluded(OrderedKey::new(k.to_vec())),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+impl<C: Comparator> KVStore for OrderedBTree<C> {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(&OrderedKey::new(key.to_vec())).cloned()
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        let mut new = self.0.clone();
+        let found = new.insert(OrderedKey::new(key), val).is_some();
+        (OrderedBTree(new), found)
+    }
+
+    fn insert_in_place(&mut self, key: Vec<u8>, val: Vec<u8>) -> bool {
+        self.0.insert(OrderedKey::new(key), val).is_some()
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let mut new = self.0.clone();
+        let found = new.remove(&OrderedKey::new(key.to_vec())).is_some();
+        (OrderedBTree(new), found)
+    }
+
+    fn remove_in_place(&mut self, key: &[u8]) -> bool {
+        self.0.remove(&OrderedKey::new(key.to_vec())).is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn resolve(_basis: Arc<Self>, _prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        Err(QuadrilleError::KeyConflict)
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        if !valid_range(start, end, C::compare) {
+            return Vec::new();
+        }
+        self.0
+            .range((owned_bound::<C>(start), owned_bound::<C>(end)))
+            .map(|(k, v)| (k.0.clone(), v.clone()))
+            .collect()
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        Box::new(self.0.iter().map(|(k, v)| (k.0.as_slice(), v.as_slice())))
+    }
+
+    fn compare_keys(a: &[u8], b: &[u8]) -> Ordering {
+        C::compare(a, b)
+    }
+
+    // Keys sharing a prefix need not be contiguous under an arbitrary order,
+    // so filter a full scan rather than computing bounds.
+    fn scan_prefix(&self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.0
+            .iter()
+            .filter(|(k, _)| k.0.starts_with(prefix))
+            .map(|(k, v)| (k.0.clone(), v.clone()))
+            .collect()
+    }
+}
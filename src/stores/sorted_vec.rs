@@ -0,0 +1,181 @@
+use std::ops::{Bound, ControlFlow};
+use std::sync::Arc;
+
+use crate::{entry_size, valid_range, EntryIter, KVStore, OrderedKVStore, QuadrilleError};
+
+/// A store holding its entries in one `Vec`, sorted by key and searched by
+/// binary search. For a few hundred entries its contiguous layout tends to
+/// read faster than a tree, at the cost of an O(n) copy on every write.
+#[derive(Default)]
+pub struct SortedVecStore(Vec<(Vec<u8>, Vec<u8>)>);
+
+impl SortedVecStore {
+    fn search(&self, key: &[u8]) -> Result<usize, usize> {
+        self.0.binary_search_by(|(k, _)| k.as_slice().cmp(key))
+    }
+
+    /// The entries between `start` and `end`, which must be a valid range.
+    fn slice(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> &[(Vec<u8>, Vec<u8>)] {
+        let lo = match start {
+            Bound::Included(s) => self.0.partition_point(|(k, _)| k.as_slice() < s),
+            Bound::Excluded(s) => self.0.partition_point(|(k, _)| k.as_slice() <= s),
+            Bound::Unbounded => 0,
+        };
+        let hi = match end {
+            Bound::Included(e) => self.0.partition_point(|(k, _)| k.as_slice() <= e),
+            Bound::Excluded(e) => self.0.partition_point(|(k, _)| k.as_slice() < e),
+            Bound::Unbounded => self.0.len(),
+        };
+        &self.0[lo..hi.max(lo)]
+    }
+}
+
+impl KVStore for SortedVecStore {
+    fn with_capacity(capacity: usize) -> Self {
+        SortedVecStore(Vec::with_capacity(capacity))
+    }
+
+    fn reserve(&self, additional: usize) -> Option<Self> {
+        let mut new = Vec::with_capacity(self.0.len() + additional);
+        new.extend_from_slice(&self.0);
+        Some(SortedVecStore(new))
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.search(key).ok().map(|i| self.0[i].1.clone())
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        // keeps any capacity hint, which a plain clone would drop
+        let mut new = Vec::with_capacity(self.0.capacity().max(self.0.len() + 1));
+        new.extend_from_slice(&self.0);
+        match self.search(&key) {
+            Ok(i) => {
+                new[i].1 = val;
+                (SortedVecStore(new), true)
+            }
+            Err(i) => {
+                new.insert(i, (key, val));
+                (SortedVecStore(new), false)
+            }
+        }
+    }
+
+    fn insert_in_place(&mut self, key: Vec<u8>, val: Vec<u8>) -> bool {
+        // keys pushed in order land at the end without shifting anything
+        match self.search(&key) {
+            Ok(i) => {
+                self.0[i].1 = val;
+                true
+            }
+            Err(i) => {
+                self.0.insert(i, (key, val));
+                false
+            }
+        }
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let mut new = self.0.clone();
+        let at = self.search(key).ok();
+        if let Some(i) = at {
+            new.remove(i);
+        }
+        (SortedVecStore(new), at.is_some())
+    }
+
+    fn remove_in_place(&mut self, key: &[u8]) -> bool {
+        let at = self.search(key).ok();
+        if let Some(i) = at {
+            self.0.remove(i);
+        }
+        at.is_some()
+    }
+
+    fn contains_key(&self, key: &[u8]) -> bool {
+        self.search(key).is_ok()
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn validate_invariants(&self) -> Result<(), String> {
+        match self.0.windows(2).position(|w| w[0].0 >= w[1].0) {
+            Some(i) => Err(format!("entries {i} and {} are out of order", i + 1)),
+            None => Ok(()),
+        }
+    }
+
+    fn resolve(_basis: Arc<Self>, _prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        Err(QuadrilleError::KeyConflict)
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        if !valid_range(start, end, <[u8]>::cmp) {
+            return Vec::new();
+        }
+        self.slice(start, end).to_vec()
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        Box::new(self.0.iter().map(|(k, v)| (k.as_slice(), v.as_slice())))
+    }
+
+    fn range_count(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> usize {
+        if !valid_range(start, end, <[u8]>::cmp) {
+            return 0;
+        }
+        self.slice(start, end).len()
+    }
+
+    fn range_keys(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<Vec<u8>> {
+        if !valid_range(start, end, <[u8]>::cmp) {
+            return Vec::new();
+        }
+        self.slice(start, end)
+            .iter()
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+
+    fn structurally_equal(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+
+    fn fold<A>(&self, init: A, mut f: impl FnMut(A, &[u8], &[u8]) -> A) -> A {
+        self.0.iter().fold(init, |acc, (k, v)| f(acc, k, v))
+    }
+
+    fn try_for_each(&self, mut f: impl FnMut(&[u8], &[u8]) -> ControlFlow<()>) -> ControlFlow<()> {
+        self.0.iter().try_for_each(|(k, v)| f(k, v))
+    }
+
+    fn floor(&self, key: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        let at = self.0.partition_point(|(k, _)| k.as_slice() <= key);
+        at.checked_sub(1).map(|i| self.0[i].clone())
+    }
+
+    fn ceil(&self, key: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        let at = self.0.partition_point(|(k, _)| k.as_slice() < key);
+        self.0.get(at).cloned()
+    }
+
+    fn estimated_size_bytes(&self) -> usize {
+        self.0.iter().map(|(k, v)| entry_size(k, v)).sum()
+    }
+}
+
+impl OrderedKVStore for SortedVecStore {
+    fn first(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.0.first().cloned()
+    }
+
+    fn last(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.0.last().cloned()
+    }
+}
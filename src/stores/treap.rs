@@ -0,0 +1,359 @@
+use std::cmp::Ordering::{Equal, Greater, Less};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::mem::size_of;
+use std::ops::{Bound, ControlFlow, RangeBounds};
+use std::sync::Arc;
+
+use crate::{
+    entry_size, valid_range, EntryIter, KVStore, MemoryReport, OrderedKVStore, QuadrilleError,
+};
+
+/// A persistent store: a treap of `Arc` nodes, where each write copies only
+/// the path from the root to the changed key and shares every other node
+/// with the previous version. Writes cost O(log n) rather than a full copy,
+/// and versions kept alive together mostly share memory.
+///
+/// Node priorities are a hash of the key, so a given set of entries always
+/// has the same shape, which lets [`structurally_equal`] skip shared
+/// subtrees.
+///
+/// [`structurally_equal`]: KVStore::structurally_equal
+#[derive(Default)]
+pub struct Treap {
+    root: Link,
+    len: usize,
+}
+
+type Link = Option<Arc<Node>>;
+
+struct Node {
+    key: Vec<u8>,
+    val: Vec<u8>,
+    priority: u64,
+    left: Link,
+    right: Link,
+}
+
+impl Node {
+    fn new(key: Vec<u8>, val: Vec<u8>, left: Link, right: Link) -> Arc<Node> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let priority = hasher.finish();
+        Arc::new(Node {
+            key,
+            val,
+            priority,
+            left,
+            right,
+        })
+    }
+
+    fn with_children(&self, left: Link, right: Link) -> Arc<Node> {
+        Arc::new(Node {
+            key: self.key.clone(),
+            val: self.val.clone(),
+            priority: self.priority,
+            left,
+            right,
+        })
+    }
+
+    /// Whether `self` belongs above `other`. Ties on priority fall back to
+    /// the key so the shape stays unique.
+    fn above(&self, other: &Node) -> bool {
+        (self.priority, &self.key) > (other.priority, &other.key)
+    }
+
+    /// Approximate heap bytes of the node's allocation and its entry.
+    fn size(&self) -> usize {
+        size_of::<Node>() + 2 * size_of::<usize>() + self.key.len() + self.val.len()
+    }
+}
+
+fn insert(link: &Link, node: Arc<Node>) -> (Arc<Node>, bool) {
+    let Some(t) = link else {
+        return (node, false);
+    };
+    match node.key.cmp(&t.key) {
+        Equal => (node.with_children(t.left.clone(), t.right.clone()), true),
+        _ if node.above(t) => {
+            // the key cannot be below a node it belongs above
+            let (left, right) = split(link, &node.key);
+            (node.with_children(left, right), false)
+        }
+        Less => {
+            let (left, found) = insert(&t.left, node);
+            (t.with_children(Some(left), t.right.clone()), found)
+        }
+        Greater => {
+            let (right, found) = insert(&t.right, node);
+            (t.with_children(t.left.clone(), Some(right)), found)
+        }
+    }
+}
+
+/// Splits into the keys before and after `key`, which must be absent.
+fn split(link: &Link, key: &[u8]) -> (Link, Link) {
+    let Some(t) = link else {
+        return (None, None);
+    };
+    if t.key.as_slice() < key {
+        let (mid, right) = split(&t.right, key);
+        (Some(t.with_children(t.left.clone(), mid)), right)
+    } else {
+        let (left, mid) = split(&t.left, key);
+        (left, Some(t.with_children(mid, t.right.clone())))
+    }
+}
+
+/// Joins two treaps where every key of `left` is before every key of `right`.
+fn merge(left: &Link, right: &Link) -> Link {
+    match (left, right) {
+        (None, _) => right.clone(),
+        (_, None) => left.clone(),
+        (Some(l), Some(r)) if l.above(r) => {
+            Some(l.with_children(l.left.clone(), merge(&l.right, right)))
+        }
+        (Some(_), Some(r)) => Some(r.with_children(merge(left, &r.left), r.right.clone())),
+    }
+}
+
+fn remove(link: &Link, key: &[u8]) -> Option<Link> {
+    let t = link.as_ref()?;
+    match key.cmp(&t.key) {
+        Equal => Some(merge(&t.left, &t.right)),
+        Less => {
+            let left = remove(&t.left, key)?;
+            Some(Some(t.with_children(left, t.right.clone())))
+        }
+        Greater => {
+            let right = remove(&t.right, key)?;
+            Some(Some(t.with_children(t.left.clone(), right)))
+        }
+    }
+}
+
+fn same(a: &Link, b: &Link) -> bool {
+    match (a, b) {
+        (None, None) => true,
+        (Some(a), Some(b)) if Arc::ptr_eq(a, b) => true,
+        (Some(a), Some(b)) => {
+            a.key == b.key && a.val == b.val && same(&a.left, &b.left) && same(&a.right, &b.right)
+        }
+        _ => false,
+    }
+}
+
+/// Checks that the keys under `link` are strictly between `lo` and `hi` and
+/// that no child belongs above its parent, returning how many nodes there
+/// are.
+fn check(link: &Link, lo: Option<&[u8]>, hi: Option<&[u8]>) -> Result<usize, String> {
+    let Some(t) = link else {
+        return Ok(0);
+    };
+    let key = t.key.as_slice();
+    if lo.is_some_and(|lo| key <= lo) || hi.is_some_and(|hi| key >= hi) {
+        return Err(format!("key {key:?} is out of order"));
+    }
+    for child in [&t.left, &t.right].into_iter().flatten() {
+        if child.above(t) {
+            return Err(format!("key {:?} is above its parent {key:?}", child.key));
+        }
+    }
+    Ok(1 + check(&t.left, lo, Some(key))? + check(&t.right, Some(key), hi)?)
+}
+
+/// Adds the sizes under `link` to `report`, as shared from the first node
+/// that something besides this version also references.
+fn measure(link: &Link, shared: bool, report: &mut MemoryReport) {
+    let Some(t) = link else {
+        return;
+    };
+    let shared = shared || Arc::strong_count(t) > 1;
+    if shared {
+        report.shared_bytes += t.size();
+    } else {
+        report.unique_bytes += t.size();
+    }
+    measure(&t.left, shared, report);
+    measure(&t.right, shared, report);
+}
+
+/// In-order traversal, keeping the nodes whose right subtrees remain.
+struct Iter<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iter<'a> {
+    fn new(root: &'a Link) -> Self {
+        let mut iter = Iter { stack: Vec::new() };
+        iter.descend(root);
+        iter
+    }
+
+    /// Starts at the first key within `start`, skipping every subtree
+    /// before it, so a range costs O(log n) plus the entries it yields.
+    fn from_bound(root: &'a Link, start: Bound<&[u8]>) -> Self {
+        let mut iter = Iter { stack: Vec::new() };
+        let mut link = root;
+        while let Some(t) = link {
+            if (start, Bound::Unbounded).contains(t.key.as_slice()) {
+                iter.stack.push(t);
+                link = &t.left;
+            } else {
+                link = &t.right;
+            }
+        }
+        iter
+    }
+
+    fn descend(&mut self, mut link: &'a Link) {
+        while let Some(t) = link {
+            self.stack.push(t);
+            link = &t.left;
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<&'a Node> {
+        let t = self.stack.pop()?;
+        self.descend(&t.right);
+        Some(t)
+    }
+}
+
+impl Treap {
+    fn nodes(&self) -> Iter<'_> {
+        Iter::new(&self.root)
+    }
+
+    /// The nodes from `start` to `end`, in order.
+    fn nodes_in<'a>(
+        &'a self,
+        start: Bound<&[u8]>,
+        end: Bound<&'a [u8]>,
+    ) -> impl Iterator<Item = &'a Node> {
+        Iter::from_bound(&self.root, start)
+            .take_while(move |t| (Bound::Unbounded, end).contains(t.key.as_slice()))
+    }
+}
+
+impl KVStore for Treap {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let mut link = &self.root;
+        while let Some(t) = link {
+            link = match key.cmp(&t.key) {
+                Equal => return Some(t.val.clone()),
+                Less => &t.left,
+                Greater => &t.right,
+            };
+        }
+        None
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        let (root, found) = insert(&self.root, Node::new(key, val, None, None));
+        let len = self.len + usize::from(!found);
+        (
+            Treap {
+                root: Some(root),
+                len,
+            },
+            found,
+        )
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        match remove(&self.root, key) {
+            Some(root) => (
+                Treap {
+                    root,
+                    len: self.len - 1,
+                },
+                true,
+            ),
+            None => (
+                Treap {
+                    root: self.root.clone(),
+                    len: self.len,
+                },
+                false,
+            ),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    fn validate_invariants(&self) -> Result<(), String> {
+        let nodes = check(&self.root, None, None)?;
+        if nodes != self.len {
+            return Err(format!("{nodes} nodes but a length of {}", self.len));
+        }
+        Ok(())
+    }
+
+    fn resolve(_basis: Arc<Self>, _prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        Err(QuadrilleError::KeyConflict)
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        if !valid_range(start, end, <[u8]>::cmp) {
+            return Vec::new();
+        }
+        self.nodes_in(start, end)
+            .map(|t| (t.key.clone(), t.val.clone()))
+            .collect()
+    }
+
+    fn range_count(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> usize {
+        if !valid_range(start, end, <[u8]>::cmp) {
+            return 0;
+        }
+        self.nodes_in(start, end).count()
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        Box::new(self.nodes().map(|t| (t.key.as_slice(), t.val.as_slice())))
+    }
+
+    fn try_for_each(&self, mut f: impl FnMut(&[u8], &[u8]) -> ControlFlow<()>) -> ControlFlow<()> {
+        self.nodes().try_for_each(|t| f(&t.key, &t.val))
+    }
+
+    fn structurally_equal(&self, other: &Self) -> bool {
+        self.len == other.len && same(&self.root, &other.root)
+    }
+
+    fn estimated_size_bytes(&self) -> usize {
+        self.nodes().map(|t| entry_size(&t.key, &t.val)).sum()
+    }
+
+    fn memory_report(&self) -> MemoryReport {
+        let mut report = MemoryReport::default();
+        measure(&self.root, false, &mut report);
+        report
+    }
+}
+
+impl OrderedKVStore for Treap {
+    fn first(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        self.nodes().next().map(|t| (t.key.clone(), t.val.clone()))
+    }
+
+    fn last(&self) -> Option<(Vec<u8>, Vec<u8>)> {
+        let mut link = self.root.as_ref()?;
+        while let Some(right) = &link.right {
+            link = right;
+        }
+        Some((link.key.clone(), link.val.clone()))
+    }
+}
@@ -1,7 +1,14 @@
 use crate::*;
-use crate::stores::NaiveBTree;
+
+use crate::stores::{
+    fuzz_invariants, Adaptive, CardinalityTracked, LogEvent, Logged, Mirrored, NaiveBTree,
+    NaiveHashMap, NaturalOrder, OrderedBTree, ReverseOrder, SortedVecStore, Treap,
+};
+use std::ops::Bound;
+use std::sync::atomic::Ordering::Relaxed;
 
 #[test]
+#[allow(unused_mut)]
 fn basic() {
     let kv = Quadrille::<NaiveBTree>::new();
     let mut tx_a = kv.transaction();
@@ -11,7 +18,7 @@ fn basic() {
     assert_eq!(tmp, None);
     //
     let tmp = tx_a.insert(vec![0], vec![1]);
-    assert_eq!(tmp, false);
+    assert_eq!(tmp, Ok(false));
     //
     let tmp = tx_a.get(&[0]);
     assert_eq!(tmp, Some(vec![1]));
@@ -27,3 +34,3836 @@ fn basic() {
     let tmp = tx_c.get(&[0]);
     assert_eq!(tmp, Some(vec![1]));
 }
+
+#[test]
+fn reverse_comparator() {
+    let kv = Quadrille::<OrderedBTree<ReverseOrder>>::new();
+    let mut tx = kv.transaction();
+    for k in [vec![1], vec![3], vec![2], vec![2, 0], vec![4]] {
+        tx.insert(k.clone(), k).unwrap();
+    }
+    //
+    let keys: Vec<_> = tx
+        .range(Bound::Unbounded, Bound::Unbounded)
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect();
+    assert_eq!(keys, vec![vec![4], vec![3], vec![2, 0], vec![2], vec![1]]);
+    //
+    let keys: Vec<_> = tx
+        .range(Bound::Included(&[3]), Bound::Excluded(&[2]))
+        .into_iter()
+        .map(|(k, _)| k)
+        .collect();
+    assert_eq!(keys, vec![vec![3], vec![2, 0]]);
+    // bounds given in ascending byte order are inverted under this comparator
+    let tmp = tx.range(Bound::Included(&[1]), Bound::Included(&[4]));
+    assert_eq!(tmp, vec![]);
+    //
+    let keys: Vec<_> = tx.scan_prefix(&[2]).into_iter().map(|(k, _)| k).collect();
+    assert_eq!(keys, vec![vec![2, 0], vec![2]]);
+    //
+    assert_eq!(tx.get(&[2, 0]), Some(vec![2, 0]));
+}
+
+/// Delegates to `NaiveBTree` but panics whenever a conflict has to be resolved.
+#[derive(Default)]
+struct PanicOnResolve(NaiveBTree);
+
+impl KVStore for PanicOnResolve {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key)
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        let (new, found) = self.0.insert(key, val);
+        (PanicOnResolve(new), found)
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let (new, found) = self.0.remove(key);
+        (PanicOnResolve(new), found)
+    }
+
+    fn resolve(_basis: Arc<Self>, _prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        panic!("resolve")
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.0.range(start, end)
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        self.0.iter_ref()
+    }
+}
+
+/// Also run under Miri, which finds no undefined behaviour or leaks:
+/// `cargo +nightly miri test panic_in_resolve`.
+#[test]
+fn panic_in_resolve() {
+    let kv = Quadrille::<PanicOnResolve>::new();
+    let mut tx_a = kv.transaction();
+    let mut tx_b = kv.transaction();
+    tx_a.insert(vec![0], vec![1]).unwrap();
+    tx_b.insert(vec![0], vec![2]).unwrap();
+    assert!(tx_a.commit().is_ok());
+    //
+    let tmp = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| tx_b.commit()));
+    assert!(tmp.is_err());
+    // only the root itself and the handle below hold the committed state
+    let root = kv.inner.get();
+    assert_eq!(Arc::strong_count(&root), 2);
+    drop(root);
+    //
+    let mut tx_c = kv.transaction();
+    assert_eq!(tx_c.get(&[0]), Some(vec![1]));
+    tx_c.insert(vec![1], vec![1]).unwrap();
+    assert!(tx_c.commit().is_ok());
+    assert_eq!(kv.transaction().get(&[1]), Some(vec![1]));
+}
+
+#[test]
+fn estimate_memory() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    assert_eq!(kv.estimate_memory(), 0);
+    //
+    let mut tx = kv.transaction();
+    tx.insert(vec![0; 10], vec![0; 100]).unwrap();
+    tx.insert(vec![1; 20], vec![]).unwrap();
+    assert!(tx.commit().is_ok());
+    //
+    assert_eq!(kv.estimate_memory(), 10 + 100 + 20 + 2 * ENTRY_OVERHEAD);
+}
+
+thread_local! {
+    /// Store and number of rival commits `Contended::resolve` still has to make.
+    static RIVAL: std::cell::RefCell<Option<(Quadrille<Contended>, u32)>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Resolves every conflict in favour of the committing transaction, but first
+/// commits a rival transaction so the next attempt conflicts again.
+#[derive(Default)]
+struct Contended(NaiveBTree);
+
+impl KVStore for Contended {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key)
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        let (new, found) = self.0.insert(key, val);
+        (Contended(new), found)
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let (new, found) = self.0.remove(key);
+        (Contended(new), found)
+    }
+
+    fn resolve(_basis: Arc<Self>, prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        RIVAL.with(|rival| {
+            if let Some((kv, remaining)) = rival.borrow_mut().as_mut() {
+                if *remaining > 0 {
+                    *remaining -= 1;
+                    let mut tx = kv.transaction_with_priority(Priority::High);
+                    tx.insert(vec![0], vec![0]).unwrap();
+                    assert!(tx.commit().is_ok());
+                }
+            }
+        });
+        Ok(prev)
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.0.range(start, end)
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        self.0.iter_ref()
+    }
+}
+
+#[test]
+fn priority_retry_limit() {
+    let kv = Quadrille::<Contended>::new();
+    let conflicts = Priority::Low.retry_limit().unwrap() + 4;
+    //
+    let mut tx_low = kv.transaction_with_priority(Priority::Low);
+    let mut tx_high = kv.transaction_with_priority(Priority::High);
+    tx_low.insert(vec![1], vec![1]).unwrap();
+    tx_high.insert(vec![2], vec![2]).unwrap();
+    // force the first attempt of each to conflict, then keep conflicting
+    let mut tx = kv.transaction();
+    tx.insert(vec![0], vec![0]).unwrap();
+    assert!(tx.commit().is_ok());
+    //
+    RIVAL.with(|r| *r.borrow_mut() = Some((kv.clone(), conflicts)));
+    let tmp = tx_low.commit();
+    assert_eq!(tmp.err(), Some(QuadrilleError::RetryLimit));
+    //
+    RIVAL.with(|r| *r.borrow_mut() = Some((kv.clone(), conflicts)));
+    let tmp = tx_high.commit();
+    assert!(tmp.is_ok());
+    RIVAL.with(|r| *r.borrow_mut() = None);
+    //
+    let tx = kv.transaction();
+    assert_eq!(tx.get(&[1]), None);
+    assert_eq!(tx.get(&[2]), Some(vec![2]));
+}
+
+#[test]
+fn read_through_loader() {
+    let loads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = loads.clone();
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .loader(move |key| {
+            counter.fetch_add(1, Relaxed);
+            (key[0] != 0).then(|| vec![key[0] * 2])
+        })
+        .build();
+    let mut tx = kv.transaction();
+    //
+    assert_eq!(tx.get_or_load(&[2]), Some(vec![4]));
+    assert_eq!(tx.get_or_load(&[2]), Some(vec![4]));
+    assert_eq!(tx.get(&[2]), Some(vec![4]));
+    assert_eq!(loads.load(Relaxed), 1);
+    // genuine absence is not buffered
+    assert_eq!(tx.get_or_load(&[0]), None);
+    assert_eq!(tx.get(&[0]), None);
+    assert_eq!(loads.load(Relaxed), 2);
+    //
+    assert!(tx.commit().is_ok());
+    assert_eq!(kv.transaction().get(&[2]), Some(vec![4]));
+}
+
+#[test]
+fn fold() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    for (k, v) in [(1u8, 10u32), (2, 20), (3, 300), (10, 4000)] {
+        tx.insert(vec![k], v.to_le_bytes().to_vec()).unwrap();
+    }
+    assert!(tx.commit().is_ok());
+    //
+    let tmp = kv.fold(0, |n, k, _| if k[0] < 10 { n + 1 } else { n });
+    assert_eq!(tmp, 3);
+    //
+    let tmp = kv.fold(0, |sum, _, v| {
+        sum + u32::from_le_bytes(v.try_into().unwrap())
+    });
+    assert_eq!(tmp, 4330);
+}
+
+#[test]
+fn empty_keys_and_values() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    assert!(!tx.contains_key(&[]));
+    //
+    assert!(!tx.insert(vec![], vec![7]).unwrap());
+    assert!(!tx.insert(vec![1], vec![]).unwrap());
+    assert!(tx.commit().is_ok());
+    //
+    let tx = kv.transaction();
+    assert_eq!(tx.get(&[]), Some(vec![7]));
+    assert_eq!(tx.get(&[1]), Some(vec![]));
+    assert_eq!(tx.get(&[2]), None);
+    assert!(tx.contains_key(&[]));
+    assert!(tx.contains_key(&[1]));
+    assert!(!tx.contains_key(&[2]));
+    //
+    let tmp = tx.scan_prefix(&[]);
+    assert_eq!(tmp, vec![(vec![], vec![7]), (vec![1], vec![])]);
+}
+
+#[test]
+fn transaction_from_snapshot() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(vec![0], vec![0]).unwrap();
+    assert!(tx.commit().is_ok());
+    //
+    let snap = kv.snapshot();
+    let mut tx = kv.transaction_from_snapshot(&snap);
+    assert_eq!(tx.get(&[0]), Some(vec![0]));
+    tx.insert(vec![0], vec![1]).unwrap();
+    assert!(tx.commit().is_ok());
+    assert_eq!(kv.snapshot().get(&[0]), Some(vec![1]));
+    // the old snapshot still reads its own state, and is now stale
+    assert_eq!(snap.get(&[0]), Some(vec![0]));
+    let mut tx = kv.transaction_from_snapshot(&snap);
+    tx.insert(vec![0], vec![2]).unwrap();
+    assert_eq!(tx.commit().err(), Some(QuadrilleError::KeyConflict));
+    assert_eq!(kv.snapshot().get(&[0]), Some(vec![1]));
+}
+
+#[test]
+fn remove_prefix() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    for k in [
+        vec![0],
+        vec![1],
+        vec![1, 0],
+        vec![1, 0xff],
+        vec![2],
+        vec![0xff],
+        vec![0xff, 1],
+    ] {
+        tx.insert(k, vec![]).unwrap();
+    }
+    assert!(tx.commit().is_ok());
+    //
+    let mut tx = kv.transaction();
+    assert_eq!(tx.remove_prefix(&[1]).unwrap(), 3);
+    assert_eq!(tx.remove_prefix(&[1]).unwrap(), 0);
+    assert_eq!(tx.remove_prefix(&[0xff]).unwrap(), 2);
+    assert!(tx.remove(&[0]).unwrap());
+    assert!(!tx.remove(&[0]).unwrap());
+    assert!(tx.commit().is_ok());
+    let tmp = kv.snapshot().range(Bound::Unbounded, Bound::Unbounded);
+    assert_eq!(tmp, vec![(vec![2], vec![])]);
+    //
+    let mut tx = kv.transaction();
+    assert_eq!(tx.remove_prefix(&[]).unwrap(), 1);
+    assert_eq!(tx.range(Bound::Unbounded, Bound::Unbounded), vec![]);
+    // removals conflict like any other write
+    let mut tx = kv.transaction();
+    let mut tx_b = kv.transaction();
+    tx.remove_prefix(&[2]).unwrap();
+    tx_b.insert(vec![3], vec![]).unwrap();
+    assert!(tx_b.commit().is_ok());
+    assert_eq!(tx.commit().err(), Some(QuadrilleError::KeyConflict));
+}
+
+#[test]
+fn remove_prefix_default() {
+    let kv = Quadrille::<OrderedBTree<ReverseOrder>>::new();
+    let mut tx = kv.transaction();
+    for k in [vec![1], vec![1, 0], vec![2]] {
+        tx.insert(k, vec![]).unwrap();
+    }
+    assert_eq!(tx.remove_prefix(&[3]).unwrap(), 0);
+    assert_eq!(tx.remove_prefix(&[1]).unwrap(), 2);
+    assert_eq!(tx.scan_prefix(&[]), vec![(vec![2], vec![])]);
+}
+
+#[test]
+fn commit_if() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let non_negative = |store: &NaiveBTree| store.fold(true, |ok, _, v| ok && v[0] < 0x80);
+    //
+    let mut tx = kv.transaction();
+    tx.insert(vec![0], vec![10]).unwrap();
+    tx.insert(vec![1], vec![5]).unwrap();
+    assert!(tx.commit_if(non_negative).is_ok());
+    //
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], (-5i8).to_le_bytes().to_vec()).unwrap();
+    let tmp = tx.commit_if(non_negative);
+    assert_eq!(tmp.err(), Some(QuadrilleError::PreconditionFailed));
+    assert_eq!(kv.snapshot().get(&[1]), Some(vec![5]));
+}
+
+#[test]
+fn sha256_vectors() {
+    let hex = |h: [u8; 32]| h.iter().map(|b| format!("{b:02x}")).collect::<String>();
+    assert_eq!(
+        hex(merkle::sha256(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hex(merkle::sha256(b"abc")),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    assert_eq!(
+        hex(merkle::sha256(&[b'a'; 64])),
+        "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb"
+    );
+}
+
+#[test]
+fn merkle_diff() {
+    let kv_a = Quadrille::<NaiveBTree>::new();
+    let kv_b = Quadrille::<OrderedBTree<ReverseOrder>>::new();
+    let entries: Vec<_> = (0u16..300)
+        .map(|i| (i.to_be_bytes().to_vec(), vec![i as u8]))
+        .collect();
+    let mut tx = kv_a.transaction();
+    for (k, v) in &entries {
+        tx.insert(k.clone(), v.clone()).unwrap();
+    }
+    assert!(tx.commit().is_ok());
+    let mut tx = kv_b.transaction();
+    for (k, v) in &entries {
+        tx.insert(k.clone(), v.clone()).unwrap();
+    }
+    assert!(tx.commit().is_ok());
+    //
+    let (snap_a, snap_b) = (kv_a.snapshot(), kv_b.snapshot());
+    assert_eq!(snap_a.merkle_root(), snap_b.merkle_root());
+    assert_eq!(merkle::merkle_diff(&snap_a, &snap_b), Vec::<Vec<u8>>::new());
+    //
+    let mut tx = kv_b.transaction();
+    tx.insert(vec![0, 7], vec![0]).unwrap();
+    tx.insert(vec![0, 7, 1], vec![]).unwrap();
+    assert!(tx.commit().is_ok());
+    let snap_b = kv_b.snapshot();
+    assert_ne!(snap_a.merkle_root(), snap_b.merkle_root());
+    assert_eq!(
+        merkle::merkle_diff(&snap_a, &snap_b),
+        vec![vec![0, 7], vec![0, 7, 1]]
+    );
+}
+
+#[test]
+fn insert_new() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    assert_eq!(tx.insert_new(vec![0], vec![1]), Ok(()));
+    let tmp = tx.insert_new(vec![0], vec![2]);
+    assert_eq!(tmp, Err(QuadrilleError::KeyExists(vec![0])));
+    assert_eq!(tx.get(&[0]), Some(vec![1]));
+}
+
+/// Records span names and event messages, in order.
+#[cfg(feature = "tracing")]
+#[derive(Clone, Default)]
+struct Capture(Arc<std::sync::Mutex<Vec<String>>>);
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for Capture {
+    fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut log = self.0.lock().unwrap();
+        log.push(format!("span {}", span.metadata().name()));
+        tracing::span::Id::from_u64(log.len() as u64)
+    }
+
+    fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, event: &tracing::Event<'_>) {
+        struct Message(String);
+        impl tracing::field::Visit for Message {
+            fn record_debug(&mut self, field: &tracing::field::Field, val: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0 = format!("{val:?}");
+                }
+            }
+        }
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        self.0.lock().unwrap().push(message.0);
+    }
+
+    fn enter(&self, _: &tracing::span::Id) {}
+
+    fn exit(&self, _: &tracing::span::Id) {}
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_commit_and_conflict() {
+    let capture = Capture::default();
+    tracing::subscriber::with_default(capture.clone(), || {
+        let kv = Quadrille::<NaiveBTree>::new();
+        let mut tx_a = kv.transaction();
+        let mut tx_b = kv.transaction();
+        tx_a.insert(vec![0], vec![0]).unwrap();
+        tx_b.insert(vec![0], vec![1]).unwrap();
+        assert!(tx_a.commit().is_ok());
+        assert!(tx_b.commit().is_err());
+    });
+    let log = capture.0.lock().unwrap();
+    let expected = [
+        "transaction opened",
+        "transaction opened",
+        "span commit",
+        "committed",
+        "span commit",
+        "commit conflict",
+        "span resolve",
+        "resolve failed",
+    ];
+    assert_eq!(*log, expected);
+}
+
+#[test]
+fn drain() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![1]).unwrap();
+    tx.insert(vec![0], vec![0]).unwrap();
+    assert!(tx.commit().is_ok());
+    let mut stale = kv.transaction();
+    stale.insert(vec![2], vec![2]).unwrap();
+    //
+    let tmp = kv.drain();
+    assert_eq!(tmp, vec![(vec![0], vec![0]), (vec![1], vec![1])]);
+    assert_eq!(
+        kv.snapshot().range(Bound::Unbounded, Bound::Unbounded),
+        vec![]
+    );
+    assert_eq!(stale.commit().err(), Some(QuadrilleError::KeyConflict));
+
+    // the emptied state keeps the store's own configuration
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = log.clone();
+    let store = Logged::new(NaiveBTree::default(), move |e| sink.lock().unwrap().push(e));
+    let kv = Quadrille::builder().initial(store).build();
+    kv.drain();
+    assert_eq!(kv.get(b"a"), None);
+    assert_eq!(log.lock().unwrap().len(), 1);
+}
+
+static LIVE_COUNTED: std::sync::atomic::AtomicIsize = std::sync::atomic::AtomicIsize::new(0);
+
+/// `NaiveBTree` that tracks how many store versions are alive.
+struct Counted(NaiveBTree);
+
+impl Counted {
+    fn new(inner: NaiveBTree) -> Self {
+        LIVE_COUNTED.fetch_add(1, Relaxed);
+        Counted(inner)
+    }
+}
+
+impl Default for Counted {
+    fn default() -> Self {
+        Counted::new(NaiveBTree::default())
+    }
+}
+
+impl Drop for Counted {
+    fn drop(&mut self) {
+        LIVE_COUNTED.fetch_sub(1, Relaxed);
+    }
+}
+
+impl KVStore for Counted {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key)
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        let (new, found) = self.0.insert(key, val);
+        (Counted::new(new), found)
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let (new, found) = self.0.remove(key);
+        (Counted::new(new), found)
+    }
+
+    fn resolve(_basis: Arc<Self>, prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        Ok(prev)
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.0.range(start, end)
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        self.0.iter_ref()
+    }
+}
+
+#[test]
+fn no_leaks_across_commits() {
+    const COMMITS: usize = if cfg!(feature = "expensive_tests") {
+        20_000_000
+    } else {
+        20_000
+    };
+    let kv = Quadrille::<Counted>::new();
+    for i in 0..COMMITS {
+        let mut tx = kv.transaction();
+        tx.insert(vec![(i % 4) as u8], i.to_le_bytes().to_vec())
+            .unwrap();
+        if i % 3 == 0 {
+            // conflict and resolve on every third commit
+            let mut rival = kv.transaction();
+            rival.remove(&[0]).unwrap();
+            assert!(rival.commit().is_ok());
+        }
+        assert!(tx.commit().is_ok());
+        if i % 1024 == 0 {
+            assert_eq!(LIVE_COUNTED.load(Relaxed), 1);
+            assert_eq!(Arc::strong_count(&kv.inner.get()), 2);
+        }
+    }
+    drop(kv);
+    assert_eq!(LIVE_COUNTED.load(Relaxed), 0);
+}
+
+#[test]
+fn retired_roots_drain_after_reads() {
+    let root = AtomicRoot::new(0u32);
+    let old = Arc::downgrade(&root.get());
+    // a reader paused mid-load holds back every swap's release
+    root.readers.fetch_add(1, SeqCst);
+    for i in 1..4 {
+        root.swap(Arc::new(i));
+    }
+    assert!(old.upgrade().is_some());
+    assert_eq!(root.retired.lock().unwrap().len(), 3);
+    root.readers.fetch_sub(1, SeqCst);
+    // the next reader out releases them without waiting for a swap
+    assert_eq!(*root.get(), 3);
+    assert!(old.upgrade().is_none());
+    assert!(root.retired.lock().unwrap().is_empty());
+}
+
+#[test]
+fn structurally_equal() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(vec![0], vec![0]).unwrap();
+    assert!(tx.commit().is_ok());
+    // read-only transactions commit even when stale
+    let tx_read = kv.transaction();
+    assert_eq!(tx_read.get(&[0]), Some(vec![0]));
+    // equal content reached independently
+    let mut tx_a = kv.transaction();
+    let mut tx_b = kv.transaction();
+    tx_a.insert(vec![1], vec![1]).unwrap();
+    tx_b.insert(vec![1], vec![1]).unwrap();
+    assert!(!Arc::ptr_eq(&tx_a.current, &tx_b.current));
+    assert!(tx_a.current.structurally_equal(&tx_b.current));
+    assert!(tx_a.commit().is_ok());
+    assert!(tx_read.commit().is_ok());
+    assert!(tx_b.commit().is_ok());
+    //
+    let snap = kv.snapshot();
+    assert!(snap.root.structurally_equal(&kv.inner.get()));
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![2]).unwrap();
+    assert!(!snap.root.structurally_equal(&tx.current));
+}
+
+#[test]
+fn adaptive_promotion() {
+    let kv = Quadrille::<Adaptive<4>>::new();
+    let mut tx = kv.transaction();
+    for k in [3u8, 1, 0, 2] {
+        assert!(!tx.insert(vec![k], vec![k]).unwrap());
+    }
+    // exactly at the threshold, and overwrites don't grow it
+    assert!(tx.insert(vec![2], vec![20]).unwrap());
+    assert!(tx.current.is_small());
+    assert_eq!(tx.get(&[2]), Some(vec![20]));
+    let tmp = tx.range(Bound::Excluded(&[0]), Bound::Included(&[2]));
+    assert_eq!(tmp, vec![(vec![1], vec![1]), (vec![2], vec![20])]);
+    //
+    assert!(!tx.insert(vec![4], vec![4]).unwrap());
+    assert!(!tx.current.is_small());
+    let keys: Vec<_> = tx.scan_prefix(&[]).into_iter().map(|(k, _)| k).collect();
+    assert_eq!(keys, vec![vec![0], vec![1], vec![2], vec![3], vec![4]]);
+    assert_eq!(tx.get(&[2]), Some(vec![20]));
+    assert!(tx.remove(&[4]).unwrap());
+    assert_eq!(tx.get(&[4]), None);
+    assert!(tx.commit().is_ok());
+    assert_eq!(kv.snapshot().get(&[3]), Some(vec![3]));
+}
+
+#[test]
+fn default_commit_timeout() {
+    let kv = Quadrille::<Contended>::builder()
+        .commit_timeout(std::time::Duration::from_millis(1))
+        .build();
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![1]).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(vec![0], vec![0]).unwrap();
+    assert!(rival.commit().is_ok());
+    //
+    RIVAL.with(|r| *r.borrow_mut() = Some((kv.clone(), u32::MAX)));
+    let tmp = tx.commit();
+    RIVAL.with(|r| *r.borrow_mut() = None);
+    assert_eq!(tmp.err(), Some(QuadrilleError::Timeout));
+    assert_eq!(kv.snapshot().get(&[1]), None);
+}
+
+#[test]
+fn range_keys() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    for k in 0u8..10 {
+        tx.insert(vec![k], vec![k; 1 << 20]).unwrap();
+    }
+    let tmp = tx.range_keys(Bound::Included(&[3]), Bound::Excluded(&[6]));
+    assert_eq!(tmp, vec![vec![3], vec![4], vec![5]]);
+    let tmp = tx.range_keys(Bound::Excluded(&[8]), Bound::Unbounded);
+    assert_eq!(tmp, vec![vec![9]]);
+    // empty and inverted ranges
+    let tmp = tx.range_keys(Bound::Excluded(&[3]), Bound::Excluded(&[3]));
+    assert_eq!(tmp, Vec::<Vec<u8>>::new());
+    let tmp = tx.range_keys(Bound::Included(&[6]), Bound::Included(&[3]));
+    assert_eq!(tmp, Vec::<Vec<u8>>::new());
+    let tmp = tx.range_keys(Bound::Included(&[3]), Bound::Included(&[3]));
+    assert_eq!(tmp, vec![vec![3]]);
+}
+
+#[test]
+fn ttl_with_test_clock() {
+    let clock = TestClock::new();
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .clock(clock.clone())
+        .build();
+    let ttl = std::time::Duration::from_secs(60);
+    let mut tx = kv.transaction();
+    tx.insert_with_ttl(vec![0], vec![0], ttl).unwrap();
+    tx.insert_with_ttl(vec![1], vec![1], ttl).unwrap();
+    tx.insert(vec![2], vec![2]).unwrap();
+    assert!(tx.commit().is_ok());
+    // overwriting without a TTL makes the key permanent
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![10]).unwrap();
+    assert!(tx.commit().is_ok());
+    //
+    clock.advance(ttl / 2);
+    assert_eq!(kv.transaction().get(&[0]), Some(vec![0]));
+    clock.advance(ttl / 2);
+    let tx = kv.transaction();
+    assert_eq!(tx.get(&[0]), None);
+    assert!(!tx.contains_key(&[0]));
+    assert_eq!(tx.get(&[1]), Some(vec![10]));
+    assert_eq!(tx.get(&[2]), Some(vec![2]));
+    //
+    assert_eq!(kv.purge_expired(), Ok(1));
+    assert_eq!(kv.snapshot().get(&[0]), None);
+    assert_eq!(kv.snapshot().get(&[1]), Some(vec![10]));
+
+    // removing a key drops the TTL buffered for it
+    let mut tx = kv.transaction();
+    tx.insert_with_ttl(vec![5, 0], vec![0], ttl).unwrap();
+    tx.insert_with_ttl(vec![6], vec![0], ttl).unwrap();
+    assert_eq!(tx.remove_prefix(&[5]), Ok(1));
+    assert_eq!(tx.retain(|key, _| key != [6]), Ok(1));
+    tx.commit().unwrap();
+    let expiries = kv.shared.expiries.read().unwrap().clone();
+    assert!(!expiries.contains_key(&[5, 0][..]));
+    assert!(!expiries.contains_key(&[6][..]));
+}
+
+#[test]
+fn check_and_set() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(b"alice".to_vec(), vec![10]).unwrap();
+    tx.insert(b"bob".to_vec(), vec![10]).unwrap();
+    //
+    let conditions = [
+        (b"alice".to_vec(), Some(vec![10])),
+        (b"bob".to_vec(), Some(vec![10])),
+        (b"carol".to_vec(), None),
+    ];
+    let writes = vec![(b"alice".to_vec(), vec![5]), (b"bob".to_vec(), vec![15])];
+    assert!(tx.check_and_set(&conditions, writes.clone()).unwrap());
+    assert_eq!(tx.get(b"alice"), Some(vec![5]));
+    assert_eq!(tx.get(b"bob"), Some(vec![15]));
+    // alice no longer matches, so bob is untouched too
+    let writes = vec![(b"alice".to_vec(), vec![0]), (b"bob".to_vec(), vec![20])];
+    assert!(!tx.check_and_set(&conditions, writes).unwrap());
+    assert_eq!(tx.get(b"alice"), Some(vec![5]));
+    assert_eq!(tx.get(b"bob"), Some(vec![15]));
+    assert!(tx.commit().is_ok());
+}
+
+#[test]
+fn size_histogram() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    assert_eq!(kv.size_histogram(), None);
+    //
+    let kv = Quadrille::<NaiveBTree>::builder().record_sizes().build();
+    for n in [1usize, 3, 3, 40] {
+        let mut tx = kv.transaction();
+        for k in 0..n {
+            tx.insert(vec![k as u8], vec![0; 7]).unwrap();
+        }
+        // rewriting a key doesn't count twice
+        tx.insert(vec![0], vec![0; 7]).unwrap();
+        assert!(tx.commit().is_ok());
+    }
+    let tmp = kv.size_histogram().unwrap();
+    let mut keys = [0; SIZE_BUCKETS];
+    keys[1] = 1;
+    keys[2] = 2;
+    keys[6] = 1;
+    assert_eq!(tmp.keys, keys);
+    // 8, 24, 24 and 320 bytes
+    let mut bytes = [0; SIZE_BUCKETS];
+    bytes[4] = 1;
+    bytes[5] = 2;
+    bytes[9] = 1;
+    assert_eq!(tmp.bytes, bytes);
+}
+
+#[test]
+fn cas_race() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    assert_eq!(kv.cas(&[0], Some(&[1]), Some(vec![2])), Ok(false));
+    assert_eq!(kv.cas(&[0], None, Some(vec![0])), Ok(true));
+    //
+    for round in 0u8..50 {
+        let barrier = std::sync::Barrier::new(3);
+        let wins: usize = std::thread::scope(|s| {
+            let racers: Vec<_> = (1..=2u8)
+                .map(|id| {
+                    let (kv, barrier) = (kv.clone(), &barrier);
+                    s.spawn(move || {
+                        barrier.wait();
+                        let new = vec![round.wrapping_add(id)];
+                        usize::from(kv.cas(&[0], Some(&[round]), Some(new)).unwrap())
+                    })
+                })
+                .collect();
+            // unrelated writes only cause retries
+            let bystander = s.spawn(|| {
+                barrier.wait();
+                let prev = round.checked_sub(1).map(|r| vec![r]);
+                kv.cas(&[1], prev.as_deref(), Some(vec![round])).unwrap()
+            });
+            assert!(bystander.join().unwrap());
+            racers.into_iter().map(|r| r.join().unwrap()).sum()
+        });
+        assert_eq!(wins, 1);
+        let winner = kv.snapshot().get(&[0]).unwrap()[0];
+        assert!(winner == round + 1 || winner == round + 2);
+        assert_eq!(
+            kv.cas(&[0], Some(&[winner]), Some(vec![round + 1])),
+            Ok(true)
+        );
+    }
+    assert_eq!(kv.cas(&[0], Some(&[50]), None), Ok(true));
+    assert_eq!(kv.snapshot().get(&[0]), None);
+}
+
+#[test]
+fn extend_transaction() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(vec![0], vec![0]).unwrap();
+    tx.extend((0u8..5).map(|i| (vec![i], vec![i + 10])));
+    tx.extend(std::iter::empty());
+    assert_eq!(
+        tx.insert_many([(vec![4], vec![4]), (vec![9], vec![9])])
+            .unwrap(),
+        1
+    );
+    assert!(tx.commit().is_ok());
+    //
+    let snap = kv.snapshot();
+    assert_eq!(snap.get(&[0]), Some(vec![10]));
+    assert_eq!(snap.get(&[3]), Some(vec![13]));
+    assert_eq!(snap.get(&[4]), Some(vec![4]));
+    assert_eq!(snap.get(&[9]), Some(vec![9]));
+    // the default implementation agrees
+    let kv = Quadrille::<Adaptive<2>>::new();
+    let mut tx = kv.transaction();
+    tx.extend((0u8..5).map(|i| (vec![i], vec![i])));
+    assert_eq!(
+        tx.insert_many([(vec![1], vec![1]), (vec![7], vec![7])])
+            .unwrap(),
+        1
+    );
+    assert_eq!(tx.range_keys(Bound::Unbounded, Bound::Unbounded).len(), 6);
+}
+
+#[test]
+fn floor_and_ceil() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    assert_eq!(kv.snapshot().floor(&[5]), None);
+    assert_eq!(kv.snapshot().ceil(&[5]), None);
+    let mut tx = kv.transaction();
+    tx.insert(vec![10], vec![1]).unwrap();
+    tx.insert(vec![20], vec![2]).unwrap();
+    assert!(tx.commit().is_ok());
+    let snap = kv.snapshot();
+    // at
+    assert_eq!(snap.floor(&[10]), Some((vec![10], vec![1])));
+    assert_eq!(snap.ceil(&[20]), Some((vec![20], vec![2])));
+    // between
+    assert_eq!(snap.floor(&[15]), Some((vec![10], vec![1])));
+    assert_eq!(snap.ceil(&[15]), Some((vec![20], vec![2])));
+    assert_eq!(snap.ceil(&[10, 0]), Some((vec![20], vec![2])));
+    // below
+    assert_eq!(snap.floor(&[5]), None);
+    assert_eq!(snap.ceil(&[]), Some((vec![10], vec![1])));
+    // above
+    assert_eq!(snap.floor(&[25]), Some((vec![20], vec![2])));
+    assert_eq!(snap.ceil(&[25]), None);
+}
+
+#[test]
+fn validator_vetoes_commit() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .validator(
+            |_, changes| match changes.iter().find(|(k, _)| k.starts_with(b"forbidden")) {
+                Some((k, _)) => Err(QuadrilleError::Rejected(format!("{k:?}"))),
+                None => Ok(()),
+            },
+        )
+        .build();
+    let mut tx = kv.transaction();
+    tx.insert(b"allowed".to_vec(), vec![1]).unwrap();
+    assert!(tx.commit().is_ok());
+    //
+    let mut tx = kv.transaction();
+    tx.insert(b"allowed".to_vec(), vec![2]).unwrap();
+    tx.insert(b"forbidden".to_vec(), vec![2]).unwrap();
+    assert!(matches!(tx.commit(), Err(QuadrilleError::Rejected(_))));
+    let snap = kv.snapshot();
+    assert_eq!(snap.get(b"allowed"), Some(vec![1]));
+    assert_eq!(snap.get(b"forbidden"), None);
+}
+
+#[test]
+fn bounded_version_history() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .retain_versions(3)
+        .build();
+    for i in 1..=5u8 {
+        let mut tx = kv.transaction();
+        tx.insert(vec![0], vec![i]).unwrap();
+        assert!(tx.commit().is_ok());
+    }
+    assert_eq!(kv.version(), 5);
+    // versions 3..=5 are retained
+    assert_eq!(kv.snapshot_at_version(5).unwrap().get(&[0]), Some(vec![5]));
+    assert_eq!(kv.snapshot_at_version(3).unwrap().get(&[0]), Some(vec![3]));
+    assert!(kv.snapshot_at_version(2).is_none());
+    assert!(kv.snapshot_at_version(0).is_none());
+    assert!(kv.snapshot_at_version(6).is_none());
+    // without retention only the live root exists
+    let kv = Quadrille::<NaiveBTree>::new();
+    assert!(kv.snapshot_at_version(0).is_none());
+}
+
+#[test]
+fn retain() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.extend((0..10u8).map(|i| (vec![i], vec![i])));
+    assert!(tx.commit().is_ok());
+    let mut tx = kv.transaction();
+    let calls = std::cell::Cell::new(0);
+    let even = |k: &[u8], _: &[u8]| {
+        calls.set(calls.get() + 1);
+        k[0].is_multiple_of(2)
+    };
+    assert_eq!(tx.retain(even).unwrap(), 5);
+    // asked once per entry
+    assert_eq!(calls.get(), 10);
+    assert!(tx.commit().is_ok());
+    let keys: Vec<_> = kv.snapshot().range_keys(Bound::Unbounded, Bound::Unbounded);
+    assert_eq!(keys, [[0], [2], [4], [6], [8]]);
+    // the default implementation agrees
+    let store = OrderedBTree::<NaturalOrder>::default()
+        .insert_many((0..10u8).map(|i| (vec![i], vec![i])).collect())
+        .0;
+    let (kept, removed) = store.retain(|k, _| k[0] % 2 == 0);
+    assert_eq!(removed, 5);
+    assert_eq!(kept.range_keys(Bound::Unbounded, Bound::Unbounded), keys);
+}
+
+#[test]
+fn swappable_resolver() {
+    fn race(kv: &Quadrille<NaiveBTree>) -> Result<Quadrille<NaiveBTree>, QuadrilleError> {
+        let mut tx = kv.transaction();
+        tx.insert(vec![1], vec![1]).unwrap();
+        let mut rival = kv.transaction();
+        rival.insert(vec![2], vec![2]).unwrap();
+        assert!(rival.commit().is_ok());
+        tx.commit()
+    }
+    // NaiveBTree's own policy refuses every conflict
+    let kv = Quadrille::<NaiveBTree>::new();
+    assert_eq!(race(&kv).err(), Some(QuadrilleError::KeyConflict));
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(FailFast)
+        .build();
+    assert_eq!(race(&kv).err(), Some(QuadrilleError::KeyConflict));
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(LastWriteWins)
+        .build();
+    assert!(race(&kv).is_ok());
+    let snap = kv.snapshot();
+    assert_eq!(snap.get(&[1]), Some(vec![1]));
+    assert_eq!(snap.get(&[2]), Some(vec![2]));
+}
+
+#[test]
+fn flush_reads() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(LastWriteWins)
+        .build();
+    let race = |flush: bool| {
+        let mut tx = kv.transaction();
+        let _ = tx.get(&[1]);
+        if flush {
+            tx.flush_reads();
+        }
+        tx.insert(vec![2], vec![2]).unwrap();
+        let mut rival = kv.transaction();
+        rival.insert(vec![1], vec![flush as u8]).unwrap();
+        assert!(rival.commit().is_ok());
+        tx.commit().map(|_| ())
+    };
+    assert_eq!(race(false), Err(QuadrilleError::KeyConflict));
+    assert_eq!(race(true), Ok(()));
+    assert_eq!(kv.snapshot().get(&[2]), Some(vec![2]));
+}
+
+#[test]
+fn commit_metrics() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(LastWriteWins)
+        .build();
+    assert_eq!(kv.metrics().conflict_rate(), 0.0);
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![1]).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(vec![2], vec![2]).unwrap();
+    assert!(rival.commit().is_ok());
+    assert!(tx.commit().is_ok());
+    let metrics = kv.metrics();
+    assert_eq!(metrics.commits, 2);
+    assert_eq!(metrics.conflicts, 1);
+    assert!((metrics.conflict_rate() - 1.0 / 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn deep_clone_forks() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![1]).unwrap();
+    assert!(tx.commit().is_ok());
+    let shared = kv.clone();
+    let fork = kv.deep_clone();
+    assert_eq!(fork.snapshot().get(&[1]), Some(vec![1]));
+    //
+    let mut tx = fork.transaction();
+    tx.insert(vec![1], vec![2]).unwrap();
+    assert!(tx.commit().is_ok());
+    assert_eq!(fork.snapshot().get(&[1]), Some(vec![2]));
+    assert_eq!(kv.snapshot().get(&[1]), Some(vec![1]));
+    //
+    let mut tx = shared.transaction();
+    tx.insert(vec![1], vec![3]).unwrap();
+    assert!(tx.commit().is_ok());
+    assert_eq!(kv.snapshot().get(&[1]), Some(vec![3]));
+    assert_eq!(fork.snapshot().get(&[1]), Some(vec![2]));
+}
+
+#[test]
+fn get_with_default() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![1]).unwrap();
+    assert_eq!(tx.get_with_default(&[1], vec![9]), vec![1]);
+    assert_eq!(tx.get_with_default(&[2], vec![9]), vec![9]);
+    assert!(!tx.contains_key(&[2]));
+}
+
+/// Runs random writes against `T` and a `BTreeMap`, checking they agree.
+fn matches_btreemap<T: KVStore>() {
+    use std::collections::BTreeMap;
+    let mut rng = 0x2545_F491_4F6C_DD1Du64;
+    let mut next = move || {
+        rng ^= rng << 13;
+        rng ^= rng >> 7;
+        rng ^= rng << 17;
+        rng
+    };
+    let mut store = T::default();
+    let mut reference = BTreeMap::new();
+    for _ in 0..2000 {
+        let r = next();
+        let key = vec![(r % 7) as u8, (r >> 8) as u8 % 5];
+        if r >> 16 & 3 == 0 {
+            let (new, found) = store.remove(&key);
+            assert_eq!(found, reference.remove(&key).is_some());
+            store = new;
+        } else {
+            let (new, found) = store.insert(key.clone(), vec![r as u8]);
+            assert_eq!(found, reference.insert(key, vec![r as u8]).is_some());
+            store = new;
+        }
+        let probe = [(r >> 24) as u8 % 8, (r >> 32) as u8 % 6];
+        assert_eq!(store.get(&probe), reference.get(&probe[..]).cloned());
+        assert_eq!(
+            store.contains_key(&probe),
+            reference.contains_key(&probe[..])
+        );
+        let floor = reference.range(..=probe.to_vec()).next_back();
+        assert_eq!(
+            store.floor(&probe),
+            floor.map(|(k, v)| (k.clone(), v.clone()))
+        );
+        let ceil = reference.range(probe.to_vec()..).next();
+        assert_eq!(
+            store.ceil(&probe),
+            ceil.map(|(k, v)| (k.clone(), v.clone()))
+        );
+        let scanned: Vec<_> = reference
+            .iter()
+            .filter(|(k, _)| k[0] == probe[0])
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        assert_eq!(store.scan_prefix(&probe[..1]), scanned);
+    }
+    let all: Vec<_> = reference.into_iter().collect();
+    assert_eq!(store.range(Bound::Unbounded, Bound::Unbounded), all);
+    let lo = [3u8];
+    let hi = [5u8, 2];
+    let expected: Vec<_> = all
+        .iter()
+        .filter(|(k, _)| k.as_slice() > &lo[..] && k.as_slice() <= &hi[..])
+        .cloned()
+        .collect();
+    assert_eq!(
+        store.range(Bound::Excluded(&lo), Bound::Included(&hi)),
+        expected
+    );
+    assert!(store
+        .range(Bound::Included(&hi), Bound::Excluded(&lo))
+        .is_empty());
+}
+
+#[test]
+fn sorted_vec_matches_btreemap() {
+    matches_btreemap::<SortedVecStore>();
+}
+
+#[test]
+fn try_commit_hands_back_on_conflict() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![1]).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(vec![2], vec![2]).unwrap();
+    assert!(rival.try_commit().is_ok());
+    //
+    let Err((mut tx, err)) = tx.try_commit() else {
+        panic!("expected a conflict");
+    };
+    assert_eq!(err, QuadrilleError::KeyConflict);
+    assert_eq!(tx.get(&[1]), Some(vec![1]));
+    assert_eq!(tx.get(&[2]), Some(vec![2]));
+    tx.insert(vec![1], vec![1]).unwrap();
+    assert!(tx.try_commit().is_ok());
+    let snap = kv.snapshot();
+    assert_eq!(snap.get(&[1]), Some(vec![1]));
+    assert_eq!(snap.get(&[2]), Some(vec![2]));
+}
+
+#[test]
+fn validate_values() {
+    /// Values must be UTF-8 without control characters.
+    struct Text;
+    impl Validate for Text {
+        fn validate(bytes: &[u8]) -> Result<(), String> {
+            let text = std::str::from_utf8(bytes).map_err(|e| e.to_string())?;
+            match text.chars().any(char::is_control) {
+                true => Err("control character".into()),
+                false => Ok(()),
+            }
+        }
+    }
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .validate_values::<Text>()
+        .build();
+    let mut tx = kv.transaction();
+    assert_eq!(tx.insert(b"a".to_vec(), b"fine".to_vec()), Ok(false));
+    assert_eq!(
+        tx.insert(b"b".to_vec(), b"bell\x07".to_vec()),
+        Err(QuadrilleError::InvalidData("control character".into()))
+    );
+    assert!(tx.insert(b"c".to_vec(), vec![0xff]).is_err());
+    assert!(tx
+        .insert_many([(b"d".to_vec(), b"ok".to_vec()), (b"e".to_vec(), vec![0])])
+        .is_err());
+    assert!(tx.get(b"b").is_none() && tx.get(b"d").is_none());
+    assert!(tx.commit().is_ok());
+    assert!(matches!(
+        kv.cas(b"a", Some(b"fine"), Some(vec![0])),
+        Err(QuadrilleError::InvalidData(_))
+    ));
+    assert_eq!(kv.snapshot().get(b"a"), Some(b"fine".to_vec()));
+}
+
+#[test]
+fn for_each_stops_early() {
+    use std::ops::ControlFlow;
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.extend((0..10u8).rev().map(|i| (vec![i], vec![i])));
+    assert!(tx.commit().is_ok());
+    let mut seen = Vec::new();
+    let flow = kv.for_each(|k, _| {
+        seen.push(k[0]);
+        ControlFlow::Continue(())
+    });
+    assert_eq!(flow, ControlFlow::Continue(()));
+    assert_eq!(seen, (0..10).collect::<Vec<_>>());
+    //
+    seen.clear();
+    let flow = kv.for_each(|k, _| {
+        seen.push(k[0]);
+        if k[0] == 3 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    assert_eq!(flow, ControlFlow::Break(()));
+    assert_eq!(seen, [0, 1, 2, 3]);
+}
+
+#[test]
+fn commutative_counter() {
+    fn count(val: Option<&[u8]>) -> u64 {
+        val.map_or(0, |v| u64::from_be_bytes(v.try_into().unwrap()))
+    }
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .commutative(b"counter/".to_vec(), |base, theirs, ours| {
+            let sum = count(theirs) + count(ours) - count(base);
+            Some(sum.to_be_bytes().to_vec())
+        })
+        .build();
+    let increment = |tx: &mut Transation<NaiveBTree>| {
+        let n = count(tx.get(b"counter/hits").as_deref());
+        tx.insert(b"counter/hits".to_vec(), (n + 1).to_be_bytes().to_vec())
+            .unwrap();
+    };
+    let mut a = kv.transaction();
+    let mut b = kv.transaction();
+    increment(&mut a);
+    increment(&mut b);
+    assert!(a.commit().is_ok());
+    assert!(b.commit().is_ok());
+    assert_eq!(count(kv.snapshot().get(b"counter/hits").as_deref()), 2);
+    // b was combined with a rather than committed on top of it
+    assert_eq!(kv.metrics().conflicts, 1);
+
+    // many threads incrementing at once lose none of the increments
+    std::thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                for _ in 0..50 {
+                    let mut tx = kv.transaction();
+                    increment(&mut tx);
+                    tx.commit().unwrap();
+                }
+            });
+        }
+    });
+    assert_eq!(count(kv.snapshot().get(b"counter/hits").as_deref()), 202);
+
+    // other keys still conflict
+    let mut a = kv.transaction();
+    let mut b = kv.transaction();
+    increment(&mut a);
+    a.insert(b"plain".to_vec(), vec![1]).unwrap();
+    increment(&mut b);
+    b.insert(b"plain".to_vec(), vec![2]).unwrap();
+    assert!(a.commit().is_ok());
+    assert_eq!(b.commit().err(), Some(QuadrilleError::KeyConflict));
+    assert_eq!(count(kv.snapshot().get(b"counter/hits").as_deref()), 203);
+}
+
+#[test]
+fn snapshot_iter_ref() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.extend((0..4u8).map(|i| (vec![i], vec![i * 2])));
+    assert!(tx.commit().is_ok());
+    let snap = kv.snapshot();
+    let iter = snap.iter_ref();
+    // the live root moving on and every handle going away leave it intact
+    let mut tx = kv.transaction();
+    tx.remove_prefix(&[]).unwrap();
+    assert!(tx.commit().is_ok());
+    drop(kv);
+    let entries: Vec<(&[u8], &[u8])> = iter.collect();
+    assert_eq!(
+        entries,
+        [
+            (&[0][..], &[0][..]),
+            (&[1], &[2]),
+            (&[2], &[4]),
+            (&[3], &[6])
+        ]
+    );
+}
+
+#[test]
+fn treap_matches_btreemap() {
+    matches_btreemap::<Treap>();
+    // equal contents give equal shapes, whatever the insertion order
+    let forward = Treap::default().insert_many((0..50u8).map(|i| (vec![i], vec![])).collect());
+    let backward =
+        Treap::default().insert_many((0..50u8).rev().map(|i| (vec![i], vec![])).collect());
+    assert!(forward.0.structurally_equal(&backward.0));
+    assert!(!forward.0.structurally_equal(&backward.0.remove(&[7]).0));
+
+    // ranges seek to their start, so check every kind of bound against a scan
+    let even = Treap::default()
+        .insert_many((0..50u8).map(|i| (vec![i * 2], vec![i])).collect())
+        .0;
+    let all = even.range(Bound::Unbounded, Bound::Unbounded);
+    for lo in 0..101u8 {
+        for hi in (lo..101).step_by(7) {
+            let (lo, hi) = ([lo], [hi]);
+            for bounds in [
+                (Bound::Included(&lo[..]), Bound::Excluded(&hi[..])),
+                (Bound::Excluded(&lo[..]), Bound::Included(&hi[..])),
+                (Bound::Included(&lo[..]), Bound::Unbounded),
+                (Bound::Unbounded, Bound::Excluded(&hi[..])),
+            ] {
+                let expected: Vec<_> = all
+                    .iter()
+                    .filter(|(k, _)| std::ops::RangeBounds::contains(&bounds, k.as_slice()))
+                    .cloned()
+                    .collect();
+                assert_eq!(even.range(bounds.0, bounds.1), expected);
+                assert_eq!(even.range_count(bounds.0, bounds.1), expected.len());
+            }
+        }
+    }
+}
+
+#[test]
+fn memory_report_shared_versions() {
+    let kv = Quadrille::<Treap>::builder().retain_versions(2).build();
+    let mut tx = kv.transaction();
+    tx.extend((0..1000u32).map(|i| (i.to_be_bytes().to_vec(), vec![0; 32])));
+    assert!(tx.commit().is_ok());
+    let report = kv.memory_report();
+    assert_eq!(report.shared_bytes, 0);
+    //
+    let mut tx = kv.transaction();
+    tx.insert(7u32.to_be_bytes().to_vec(), vec![1; 32]).unwrap();
+    assert!(tx.commit().is_ok());
+    let after = kv.memory_report();
+    assert!(after.unique_bytes * 20 < after.shared_bytes, "{after:?}");
+    assert_eq!(after.total_bytes(), report.total_bytes());
+    // without sharing everything is unique
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .retain_versions(2)
+        .build();
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![1]).unwrap();
+    assert!(tx.commit().is_ok());
+    assert_eq!(kv.memory_report().shared_bytes, 0);
+}
+
+#[test]
+fn rename_key() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(b"old".to_vec(), vec![1]).unwrap();
+    tx.insert(b"taken".to_vec(), vec![2]).unwrap();
+    assert!(tx.commit().is_ok());
+    //
+    let mut tx = kv.transaction();
+    assert_eq!(tx.rename_key(b"old", b"new"), Ok(true));
+    assert_eq!(tx.rename_key(b"missing", b"taken"), Ok(false));
+    assert_eq!(tx.rename_key(b"taken", b"taken"), Ok(true));
+    assert!(tx.commit().is_ok());
+    let snap = kv.snapshot();
+    assert_eq!(snap.get(b"old"), None);
+    assert_eq!(snap.get(b"new"), Some(vec![1]));
+    assert_eq!(snap.get(b"taken"), Some(vec![2]));
+}
+
+#[test]
+fn sharded_cross_shard_atomic() {
+    let kv = ShardedQuadrille::<NaiveBTree>::new(4);
+    // two accounts on different shards
+    let a = b"a".to_vec();
+    let b = (0u8..)
+        .map(|i| vec![b'b', i])
+        .find(|k| kv.shard_of(k) != kv.shard_of(&a))
+        .unwrap();
+    let mut tx = kv.transaction();
+    tx.insert(a.clone(), vec![100]).unwrap();
+    tx.insert(b.clone(), vec![100]).unwrap();
+    assert!(tx.commit().is_ok());
+    // a stale shard fails the whole commit
+    let mut tx = kv.transaction();
+    tx.insert(a.clone(), vec![90]).unwrap();
+    tx.insert(b.clone(), vec![110]).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(b.clone(), vec![0]).unwrap();
+    assert!(rival.commit().is_ok());
+    assert_eq!(tx.commit(), Err(QuadrilleError::KeyConflict));
+    assert_eq!(kv.get(&a), Some(vec![100]));
+    assert_eq!(kv.get(&b), Some(vec![0]));
+    // concurrent transfers keep the total
+    let mut tx = kv.transaction();
+    tx.insert(b.clone(), vec![100]).unwrap();
+    assert!(tx.commit().is_ok());
+    std::thread::scope(|s| {
+        for t in 0..4 {
+            let (kv, a, b) = (kv.clone(), a.clone(), b.clone());
+            s.spawn(move || {
+                let (from, to) = if t % 2 == 0 { (&a, &b) } else { (&b, &a) };
+                for _ in 0..200 {
+                    loop {
+                        let mut tx = kv.transaction();
+                        let x = tx.get(from).unwrap()[0];
+                        let y = tx.get(to).unwrap()[0];
+                        if x == 0 {
+                            break;
+                        }
+                        tx.insert(from.clone(), vec![x - 1]).unwrap();
+                        tx.insert(to.clone(), vec![y + 1]).unwrap();
+                        if tx.commit().is_ok() {
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    let total = kv.get(&a).unwrap()[0] as u32 + kv.get(&b).unwrap()[0] as u32;
+    assert_eq!(total, 200);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn resolve_lost_writes() {
+    /// Keeps the concurrent state, dropping the transaction's writes.
+    struct KeepTheirs;
+    impl ConflictResolver<NaiveBTree> for KeepTheirs {
+        fn resolve(
+            &self,
+            _base: &NaiveBTree,
+            theirs: &NaiveBTree,
+            _ours: &NaiveBTree,
+        ) -> Result<NaiveBTree, QuadrilleError> {
+            Ok(theirs.retain(|_, _| true).0)
+        }
+    }
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(KeepTheirs)
+        .build();
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![1]).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(vec![2], vec![2]).unwrap();
+    assert!(rival.commit().is_ok());
+    assert_eq!(
+        tx.commit().err(),
+        Some(QuadrilleError::ResolveLostWrites {
+            keys: vec![vec![1]]
+        })
+    );
+    let snap = kv.snapshot();
+    assert_eq!(snap.get(&[1]), None);
+    assert_eq!(snap.get(&[2]), Some(vec![2]));
+}
+
+#[test]
+fn declared_write_set() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction_for(&[b"mine"]);
+    assert_eq!(
+        tx.insert(b"other".to_vec(), vec![1]),
+        Err(QuadrilleError::Undeclared(b"other".to_vec()))
+    );
+    assert!(tx.remove(b"other").is_err());
+    tx.insert(b"mine".to_vec(), vec![1]).unwrap();
+    // a concurrent commit elsewhere does not conflict, even for NaiveBTree
+    let mut rival = kv.transaction();
+    rival.insert(b"other".to_vec(), vec![2]).unwrap();
+    assert!(rival.commit().is_ok());
+    assert!(tx.commit().is_ok());
+    let snap = kv.snapshot();
+    assert_eq!(snap.get(b"mine"), Some(vec![1]));
+    assert_eq!(snap.get(b"other"), Some(vec![2]));
+    // one on a declared key still does
+    let mut tx = kv.transaction_for(&[b"mine"]);
+    tx.insert(b"mine".to_vec(), vec![3]).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(b"mine".to_vec(), vec![4]).unwrap();
+    assert!(rival.commit().is_ok());
+    assert_eq!(tx.commit().err(), Some(QuadrilleError::KeyConflict));
+}
+
+#[test]
+fn swap_values() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![10]).unwrap();
+    tx.insert(vec![2], vec![20]).unwrap();
+    tx.swap_values(&[1], &[2]).unwrap();
+    assert_eq!(
+        (tx.get(&[1]), tx.get(&[2])),
+        (Some(vec![20]), Some(vec![10]))
+    );
+    // with an absent key it is a move
+    tx.swap_values(&[2], &[3]).unwrap();
+    assert_eq!((tx.get(&[2]), tx.get(&[3])), (None, Some(vec![10])));
+    tx.swap_values(&[1], &[1]).unwrap();
+    assert_eq!(tx.get(&[1]), Some(vec![20]));
+    assert!(tx.commit().is_ok());
+    let keys = kv.snapshot().range_keys(Bound::Unbounded, Bound::Unbounded);
+    assert_eq!(keys, [[1], [3]]);
+
+    // a swap that cannot write both keys writes neither
+    let mut tx = kv.transaction_for(&[&[1]]);
+    assert_eq!(
+        tx.swap_values(&[1], &[3]),
+        Err(QuadrilleError::Undeclared(vec![3]))
+    );
+    assert_eq!(
+        (tx.get(&[1]), tx.get(&[3])),
+        (Some(vec![20]), Some(vec![10]))
+    );
+    let kv = Quadrille::<NaiveBTree>::builder().max_entries(1).build();
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], vec![10]).unwrap();
+    tx.swap_values(&[2], &[1]).unwrap();
+    assert_eq!((tx.get(&[1]), tx.get(&[2])), (None, Some(vec![10])));
+}
+
+#[test]
+fn first_and_last() {
+    fn check<T: OrderedKVStore>() {
+        let kv = Quadrille::<T>::new();
+        assert_eq!(kv.snapshot().first(), None);
+        assert_eq!(kv.transaction().last(), None);
+        let mut tx = kv.transaction();
+        tx.extend([5u8, 1, 9, 3].map(|i| (vec![i], vec![i * 10])));
+        assert_eq!(tx.first(), Some((vec![1], vec![10])));
+        assert!(tx.commit().is_ok());
+        let snap = kv.snapshot();
+        assert_eq!(snap.first(), Some((vec![1], vec![10])));
+        assert_eq!(snap.last(), Some((vec![9], vec![90])));
+    }
+    check::<NaiveBTree>();
+    check::<SortedVecStore>();
+    check::<Treap>();
+}
+
+#[test]
+fn pop_first_and_last() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(LastWriteWins)
+        .build();
+    assert_eq!(kv.transaction().pop_first(), Ok(None));
+    let mut tx = kv.transaction();
+    tx.extend((1..=4u8).map(|i| (vec![i], vec![i])));
+    assert!(tx.commit().is_ok());
+    //
+    let mut tx = kv.transaction();
+    assert_eq!(tx.pop_first(), Ok(Some((vec![1], vec![1]))));
+    assert_eq!(tx.pop_last(), Ok(Some((vec![4], vec![4]))));
+    assert!(tx.commit().is_ok());
+    let keys = kv.snapshot().range_keys(Bound::Unbounded, Bound::Unbounded);
+    assert_eq!(keys, [[2], [3]]);
+    // the default implementation on another store agrees
+    let (rest, last) = Treap::default().insert(vec![1], vec![]).0.pop_last();
+    assert_eq!((rest.first(), last), (None, Some((vec![1], vec![]))));
+    // two consumers of the same head conflict
+    let mut a = kv.transaction();
+    let mut b = kv.transaction();
+    assert_eq!(a.pop_first(), Ok(Some((vec![2], vec![2]))));
+    assert_eq!(b.pop_first(), Ok(Some((vec![2], vec![2]))));
+    assert!(a.commit().is_ok());
+    assert_eq!(b.commit().err(), Some(QuadrilleError::KeyConflict));
+}
+
+#[test]
+#[cfg(feature = "coalesce")]
+fn coalesced_commits_keep_order() {
+    // holds the writer in its first commit while the rest queue up
+    let gate = Arc::new(std::sync::Mutex::new(()));
+    let held = gate.lock().unwrap();
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .validator({
+            let gate = gate.clone();
+            move |_, _| {
+                drop(gate.lock().unwrap());
+                Ok(())
+            }
+        })
+        .build();
+    let coalescer = kv.coalescer();
+    let handles: Vec<_> = (0..200u8)
+        .map(|i| {
+            let mut tx = kv.transaction();
+            tx.insert(b"last".to_vec(), vec![i]).unwrap();
+            tx.insert(vec![i], vec![i]).unwrap();
+            coalescer.submit(tx)
+        })
+        .collect();
+    drop(held);
+    for handle in handles {
+        assert_eq!(handle.wait(), Ok(()));
+    }
+    let snap = kv.snapshot();
+    assert_eq!(snap.get(b"last"), Some(vec![199]));
+    assert!((0..200u8).all(|i| snap.get(&[i]) == Some(vec![i])));
+    // at most the first alone, then everything queued behind it
+    assert!(kv.metrics().commits <= 2);
+}
+
+#[test]
+#[cfg(feature = "coalesce")]
+fn coalesced_writes_are_blind() {
+    use std::sync::mpsc::channel;
+
+    // stops the writer before each attempt to publish, until told to go on
+    let (entered, writer_entered) = channel();
+    let (resume, writer_resumes) = channel::<()>();
+    let writer_resumes = std::sync::Mutex::new(writer_resumes);
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .validator(move |_, changes| {
+            if changes.contains_key(b"batched") {
+                let _ = entered.send(());
+                let _ = writer_resumes.lock().unwrap().recv();
+            }
+            Ok(())
+        })
+        .build();
+    let coalescer = kv.coalescer();
+    let mut tx = kv.transaction();
+    tx.insert(b"k".to_vec(), b"coalesced".to_vec()).unwrap();
+    tx.insert(b"batched".to_vec(), vec![]).unwrap();
+    let handle = coalescer.submit(tx);
+
+    // a direct commit to the same key lands ahead of each of the writer's
+    // first two attempts
+    for i in 0..2u8 {
+        writer_entered.recv().unwrap();
+        let mut tx = kv.transaction();
+        tx.insert(b"k".to_vec(), vec![i]).unwrap();
+        tx.commit().unwrap();
+        resume.send(()).unwrap();
+    }
+    drop(resume);
+    // the store refuses to resolve conflicts, yet the later write wins
+    assert_eq!(handle.wait(), Ok(()));
+    assert_eq!(kv.get(b"k"), Some(b"coalesced".to_vec()));
+}
+
+#[test]
+#[cfg(feature = "coalesce")]
+fn coalescer_reports_a_dead_writer() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .validator(|_, _| panic!("writer dies"))
+        .build();
+    let coalescer = kv.coalescer();
+    let submit = || {
+        let mut tx = kv.transaction();
+        tx.insert(b"k".to_vec(), b"v".to_vec()).unwrap();
+        coalescer.submit(tx).wait()
+    };
+    assert_eq!(submit(), Err(QuadrilleError::Closed));
+    assert_eq!(submit(), Err(QuadrilleError::Closed));
+    assert_eq!(kv.get(b"k"), None);
+}
+
+#[test]
+fn stats_reset() {
+    let kv = Quadrille::<NaiveBTree>::builder().record_sizes().build();
+    let commit = |key: u8| {
+        let mut tx = kv.transaction();
+        tx.insert(vec![key], vec![key]).unwrap();
+        assert!(tx.commit().is_ok());
+    };
+    commit(1);
+    commit(2);
+    assert_eq!(kv.metrics().commits, 2);
+    kv.stats_reset();
+    assert_eq!(kv.metrics(), CommitMetrics::default());
+    assert!(kv.size_histogram().unwrap().keys.iter().all(|&n| n == 0));
+    commit(3);
+    assert_eq!(kv.metrics().commits, 1);
+    assert_eq!(kv.size_histogram().unwrap().keys[1], 1);
+    assert_eq!(kv.version(), 3);
+}
+
+#[test]
+fn large_values() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let big: Vec<u8> = (0..LARGE_CHUNK_SIZE * 3 + 7).map(|i| i as u8).collect();
+    let mut tx = kv.transaction();
+    tx.insert_large(b"blob".to_vec(), &big).unwrap();
+    tx.commit().unwrap();
+
+    let mut tx = kv.transaction();
+    assert_eq!(tx.get_large(b"blob").unwrap(), Some(big));
+    assert_eq!(tx.get_large(b"missing").unwrap(), None);
+    let small = vec![9; LARGE_CHUNK_SIZE + 1];
+    tx.insert_large(b"blob".to_vec(), &small).unwrap();
+    tx.commit().unwrap();
+
+    let tx = kv.transaction();
+    assert_eq!(tx.get_large(b"blob").unwrap(), Some(small));
+    // the manifest and two chunks, with the stale trailing chunks gone
+    assert_eq!(kv.len(), 3);
+    assert_eq!(tx.scan_prefix(LARGE_CHUNK_PREFIX).len(), 2);
+
+    // plain values are neither read nor overwritten as manifests, however
+    // long a count they appear to hold
+    let mut tx = kv.transaction();
+    for plain in [&b"value"[..], &[0xff; 4], &[0xff, b'Q', b'L', b'V']] {
+        tx.insert(b"plain".to_vec(), plain.to_vec()).unwrap();
+        assert!(matches!(
+            tx.get_large(b"plain"),
+            Err(QuadrilleError::InvalidData(_))
+        ));
+        assert!(matches!(
+            tx.insert_large(b"plain".to_vec(), b"big"),
+            Err(QuadrilleError::InvalidData(_))
+        ));
+        assert_eq!(tx.get(b"plain"), Some(plain.to_vec()));
+    }
+
+    // a user key that looks like another value's chunk is left alone
+    let mut tx = kv.transaction();
+    tx.insert(b"blob\x00\x00\x00\x00\x05".to_vec(), b"mine".to_vec())
+        .unwrap();
+    tx.insert_large(b"blob".to_vec(), b"tiny").unwrap();
+    assert_eq!(tx.get_large(b"blob").unwrap(), Some(b"tiny".to_vec()));
+    assert_eq!(tx.get(b"blob\x00\x00\x00\x00\x05"), Some(b"mine".to_vec()));
+}
+
+#[test]
+fn replace_if() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(b"k".to_vec(), b"old".to_vec()).unwrap();
+    tx.commit().unwrap();
+
+    let mut tx = kv.transaction();
+    let replaced = tx.replace_if(b"k", |v| v == b"old", b"new".to_vec());
+    assert_eq!(replaced.unwrap(), Some(b"old".to_vec()));
+    let replaced = tx.replace_if(b"k", |v| v == b"old", b"newer".to_vec());
+    assert_eq!(replaced.unwrap(), None);
+    assert_eq!(tx.get(b"k"), Some(b"new".to_vec()));
+    let replaced = tx.replace_if(b"absent", |_| true, b"v".to_vec());
+    assert_eq!(replaced.unwrap(), None);
+    assert_eq!(tx.get(b"absent"), None);
+
+    let mut rival = kv.transaction();
+    rival.insert(b"k".to_vec(), b"rival".to_vec()).unwrap();
+    rival.commit().unwrap();
+    assert_eq!(tx.commit().err(), Some(QuadrilleError::KeyConflict));
+}
+
+#[test]
+fn hot_get_caches_until_commit() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(b"hot".to_vec(), b"1".to_vec()).unwrap();
+    tx.commit().unwrap();
+
+    let first = kv.hot_get(b"hot").unwrap();
+    let second = kv.hot_get(b"hot").unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(kv.hot_get(b"cold"), None);
+
+    let mut tx = kv.transaction();
+    tx.insert(b"hot".to_vec(), b"2".to_vec()).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(&*kv.hot_get(b"hot").unwrap(), b"2");
+
+    // reading many keys does not grow the cache without bound
+    for i in 0..10_000u32 {
+        kv.hot_get(&i.to_be_bytes());
+    }
+    assert!(kv.shared.hot.len() <= 1024);
+    assert_eq!(&*kv.hot_get(b"hot").unwrap(), b"2");
+
+    let clock = TestClock::new();
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .clock(clock.clone())
+        .build();
+    let ttl = Duration::from_secs(60);
+    let mut tx = kv.transaction();
+    tx.insert_with_ttl(b"hot".to_vec(), b"1".to_vec(), ttl)
+        .unwrap();
+    tx.commit().unwrap();
+    assert!(kv.hot_get(b"hot").is_some());
+    // a cached value still expires
+    clock.advance(ttl);
+    assert_eq!(kv.hot_get(b"hot"), None);
+}
+
+#[test]
+fn hash_map_matches_btreemap() {
+    matches_btreemap::<NaiveHashMap>();
+}
+
+#[test]
+fn with_capacity_presizes() {
+    let n = 1000;
+    let kv = Quadrille::<NaiveHashMap>::with_capacity(n);
+    let initial = kv.snapshot().root.capacity();
+    assert!(initial >= n);
+    for i in 0..n {
+        let mut tx = kv.transaction();
+        tx.insert(i.to_be_bytes().to_vec(), vec![1]).unwrap();
+        tx.commit().unwrap();
+    }
+    let root = kv.snapshot().root;
+    assert_eq!(root.capacity(), initial);
+    assert_eq!(root.range(Bound::Unbounded, Bound::Unbounded).len(), n);
+
+    // backends without a capacity ignore the hint
+    let kv = Quadrille::<NaiveBTree>::with_capacity(n);
+    assert_eq!(kv.snapshot().get(b"k"), None);
+}
+
+#[test]
+fn isolation_levels() {
+    // the invariant is that at least one of the two stays on call
+    let skew = |level| {
+        let kv = Quadrille::<NaiveBTree>::new();
+        let mut tx = kv.transaction();
+        tx.insert(b"alice".to_vec(), b"on".to_vec()).unwrap();
+        tx.insert(b"bob".to_vec(), b"on".to_vec()).unwrap();
+        tx.commit().unwrap();
+
+        let mut alice = kv.transaction_with_isolation(level);
+        let mut bob = kv.transaction_with_isolation(level);
+        for (tx, me, other) in [
+            (&mut alice, &b"alice"[..], &b"bob"[..]),
+            (&mut bob, b"bob", b"alice"),
+        ] {
+            assert_eq!(tx.get(other), Some(b"on".to_vec()));
+            tx.insert(me.to_vec(), b"off".to_vec()).unwrap();
+        }
+        alice.commit().unwrap();
+        bob.commit().map(drop)
+    };
+    assert_eq!(
+        skew(Isolation::Serializable),
+        Err(QuadrilleError::KeyConflict)
+    );
+    assert_eq!(skew(Isolation::Snapshot), Ok(()));
+
+    // write-write conflicts still fail under snapshot isolation, but not
+    // under read committed
+    let lost_update = |level| {
+        let kv = Quadrille::<NaiveBTree>::new();
+        let mut tx = kv.transaction_with_isolation(level);
+        tx.insert(b"k".to_vec(), b"ours".to_vec()).unwrap();
+        let mut rival = kv.transaction();
+        rival.insert(b"k".to_vec(), b"theirs".to_vec()).unwrap();
+        rival.commit().unwrap();
+        tx.commit().map(|kv| kv.snapshot().get(b"k"))
+    };
+    assert_eq!(
+        lost_update(Isolation::Snapshot),
+        Err(QuadrilleError::KeyConflict)
+    );
+    assert_eq!(
+        lost_update(Isolation::ReadCommitted),
+        Ok(Some(b"ours".to_vec()))
+    );
+}
+
+#[test]
+fn logged_store() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = log.clone();
+    let store = Logged::new(NaiveBTree::default(), move |e| sink.lock().unwrap().push(e));
+    let kv = Quadrille::builder().initial(store).build();
+
+    let mut tx = kv.transaction();
+    tx.insert(b"a".to_vec(), b"1".to_vec()).unwrap();
+    assert_eq!(tx.get(b"a"), Some(b"1".to_vec()));
+    assert!(!tx.remove(b"b").unwrap());
+    tx.commit().unwrap();
+
+    let mut rival = kv.transaction();
+    rival.insert(b"c".to_vec(), b"3".to_vec()).unwrap();
+    let mut tx = kv.transaction();
+    tx.insert(b"d".to_vec(), b"4".to_vec()).unwrap();
+    rival.commit().unwrap();
+    assert_eq!(tx.commit().err(), Some(QuadrilleError::KeyConflict));
+    assert_eq!(kv.snapshot().get(b"c"), Some(b"3".to_vec()));
+
+    let key = |k: &[u8]| k.to_vec();
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            LogEvent::Insert {
+                key: key(b"a"),
+                replaced: false
+            },
+            LogEvent::Get {
+                key: key(b"a"),
+                found: true
+            },
+            LogEvent::Remove {
+                key: key(b"b"),
+                found: false
+            },
+            LogEvent::Insert {
+                key: key(b"c"),
+                replaced: false
+            },
+            LogEvent::Insert {
+                key: key(b"d"),
+                replaced: false
+            },
+            // checking whether the rival already wrote our value
+            LogEvent::Get {
+                key: key(b"d"),
+                found: false
+            },
+            LogEvent::Resolve { ok: false },
+            LogEvent::Get {
+                key: key(b"c"),
+                found: true
+            },
+        ]
+    );
+
+    // scans go straight to the inner store, and contains_key logs as a get
+    log.lock().unwrap().clear();
+    let root = kv.snapshot().root;
+    assert_eq!(root.fold(0, |n, _, _| n + 1), 2);
+    assert_eq!(root.range_count(Bound::Unbounded, Bound::Unbounded), 2);
+    assert!(root.structurally_equal(&root));
+    assert!(root.contains_key(b"a"));
+    assert_eq!(
+        *log.lock().unwrap(),
+        [LogEvent::Get {
+            key: key(b"a"),
+            found: true
+        }]
+    );
+
+    // retain logs only the keys it drops, so resolving onto an unchanged
+    // state logs just the resolver's own writes
+    let ours = root.remove(b"c").0;
+    log.lock().unwrap().clear();
+    let resolved = LastWriteWins.resolve(&*root, &*root, &ours).unwrap();
+    assert_eq!(resolved.len(), 1);
+    assert_eq!(root.retain(|k, _| k != b"a").1, 1);
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            LogEvent::Remove {
+                key: key(b"c"),
+                found: true
+            },
+            LogEvent::Remove {
+                key: key(b"a"),
+                found: true
+            },
+        ]
+    );
+}
+
+#[test]
+fn resolve_changes_matches_diff() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(LastWriteWins)
+        .build();
+    let mut tx = kv.transaction();
+    tx.extend((0..50u8).map(|i| (vec![i], vec![i])));
+    tx.commit().unwrap();
+
+    let base = kv.snapshot().root;
+    let mut tx = kv.transaction();
+    tx.insert(vec![1], b"ours".to_vec()).unwrap();
+    tx.insert(vec![100], b"new".to_vec()).unwrap();
+    tx.remove(&[2]).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(vec![1], b"theirs".to_vec()).unwrap();
+    rival.insert(vec![3], b"theirs".to_vec()).unwrap();
+    rival.remove(&[4]).unwrap();
+    rival.commit().unwrap();
+
+    let theirs = kv.snapshot().root;
+    let diffed = LastWriteWins.resolve(&*base, &theirs, &tx.current).unwrap();
+    let applied = LastWriteWins
+        .resolve_changes(&*base, &theirs, &tx.current, &tx.changes)
+        .unwrap();
+    assert!(applied.structurally_equal(&diffed));
+
+    tx.commit().unwrap();
+    assert!(kv.snapshot().root.structurally_equal(&applied));
+    let snap = kv.snapshot();
+    assert_eq!(snap.get(&[1]), Some(b"ours".to_vec()));
+    assert_eq!(snap.get(&[2]), None);
+    assert_eq!(snap.get(&[3]), Some(b"theirs".to_vec()));
+    assert_eq!(snap.get(&[4]), None);
+}
+
+#[test]
+fn split_off() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.extend((0..10u8).map(|i| (vec![i], vec![i])));
+    tx.commit().unwrap();
+    let mut stale = kv.transaction();
+    stale.insert(vec![7], b"late".to_vec()).unwrap();
+
+    let upper = kv.split_off(&[5]);
+    let keys =
+        |kv: &Quadrille<NaiveBTree>| kv.snapshot().range_keys(Bound::Unbounded, Bound::Unbounded);
+    assert_eq!(keys(&kv), (0..5u8).map(|i| vec![i]).collect::<Vec<_>>());
+    assert_eq!(keys(&upper), (5..10u8).map(|i| vec![i]).collect::<Vec<_>>());
+    assert_eq!(upper.version(), 0);
+
+    // the split is a commit like any other
+    assert_eq!(stale.commit().err(), Some(QuadrilleError::KeyConflict));
+    let mut tx = upper.transaction();
+    tx.remove(&[9]).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(kv.snapshot().get(&[9]), None);
+    assert_eq!(keys(&upper).len(), 4);
+}
+
+#[test]
+fn keycodec_orders_and_round_trips() {
+    use crate::keycodec::*;
+    use std::collections::BTreeMap;
+
+    let ints = [i64::MIN, -300, -1, 0, 1, 255, 256, i64::MAX];
+    let sorted: BTreeMap<_, _> = ints.iter().map(|&i| (encode_i64(i), i)).collect();
+    assert!(sorted.values().copied().eq(ints));
+    for i in ints {
+        assert_eq!(decode_i64(&encode_i64(i)), Some(i));
+    }
+    let uints = [0, 1, 255, 256, 1 << 40, u64::MAX];
+    let sorted: BTreeMap<_, _> = uints.iter().map(|&u| (encode_u64(u), u)).collect();
+    assert!(sorted.values().copied().eq(uints));
+    assert_eq!(decode_u64(&encode_u64(1 << 40)), Some(1 << 40));
+    assert_eq!(decode_u64(&[1, 2, 3]), None);
+
+    let tuples = [
+        (String::new(), 5u64),
+        ("a".to_string(), 2),
+        ("a".to_string(), 10),
+        ("a\0".to_string(), 0),
+        ("ab".to_string(), 1),
+    ];
+    let sorted: BTreeMap<_, _> = tuples.iter().map(|t| (encode(t), t.clone())).collect();
+    assert!(sorted.values().eq(&tuples));
+    for t in &tuples {
+        assert_eq!(decode::<(String, u64)>(&encode(t)).as_ref(), Some(t));
+    }
+    assert_eq!(decode_str(&encode_str("x\0y")), Some("x\0y".to_string()));
+    assert_eq!(decode_str(b"unterminated"), None);
+
+    // numeric range scans over encoded keys
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.extend([3u64, 1000, 20, 7].map(|i| (encode_u64(i), vec![])));
+    let keys = tx.range_keys(Bound::Included(&encode_u64(5)), Bound::Unbounded);
+    let keys: Vec<_> = keys.iter().filter_map(|k| decode_u64(k)).collect();
+    assert_eq!(keys, [7, 20, 1000]);
+}
+
+#[test]
+fn get_races_commits() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let rounds: u64 = if cfg!(miri) { 20 } else { 2000 };
+    std::thread::scope(|s| {
+        let writer = kv.clone();
+        s.spawn(move || {
+            for i in 1..=rounds {
+                let mut tx = writer.transaction();
+                tx.insert(b"k".to_vec(), i.to_be_bytes().to_vec()).unwrap();
+                tx.commit().unwrap();
+            }
+        });
+        for _ in 0..2 {
+            let reader = kv.clone();
+            s.spawn(move || {
+                let mut last = 0;
+                while last < rounds {
+                    if let Some(val) = reader.get(b"k") {
+                        let seen = u64::from_be_bytes(val.try_into().unwrap());
+                        assert!(seen >= last);
+                        last = seen;
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(kv.get(b"k"), Some(rounds.to_be_bytes().to_vec()));
+    assert_eq!(kv.get(b"missing"), None);
+}
+
+#[test]
+fn apply_patch_converges() {
+    let primary = Quadrille::<NaiveBTree>::new();
+    let replica = Quadrille::<OrderedBTree<ReverseOrder>>::new();
+    let mut tx = replica.transaction();
+    tx.extend([
+        (b"a".to_vec(), b"1".to_vec()),
+        (b"stale".to_vec(), b"x".to_vec()),
+    ]);
+    tx.commit().unwrap();
+    let mut tx = primary.transaction();
+    tx.extend([
+        (b"a".to_vec(), b"2".to_vec()),
+        (b"b".to_vec(), b"3".to_vec()),
+    ]);
+    tx.commit().unwrap();
+
+    let (from, to) = (primary.snapshot(), replica.snapshot());
+    let patch: Vec<_> = merkle::merkle_diff(&from, &to)
+        .into_iter()
+        .map(|key| {
+            let change = match from.get(&key) {
+                Some(val) => Change::Set(val),
+                None => Change::Delete,
+            };
+            (key, change)
+        })
+        .collect();
+    assert_eq!(patch.len(), 3);
+    let mut tx = replica.transaction();
+    tx.apply_patch(&patch).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(
+        replica.snapshot().merkle_root(),
+        primary.snapshot().merkle_root()
+    );
+}
+
+/// Runs `check::<T>()` on every backend, and on each wrapper over one, that
+/// orders keys bytewise, so generic store tests cover them all alike.
+macro_rules! for_each_store {
+    ($check:ident) => {
+        $check::<NaiveBTree>();
+        $check::<NaiveHashMap>();
+        $check::<OrderedBTree<NaturalOrder>>();
+        $check::<SortedVecStore>();
+        $check::<Treap>();
+        $check::<Adaptive<4>>();
+        $check::<Logged<NaiveBTree>>();
+        $check::<Mirrored<Treap, NaiveBTree>>();
+        $check::<CardinalityTracked<SortedVecStore>>();
+    };
+}
+
+#[test]
+fn len_and_is_empty() {
+    fn check<T: KVStore>() {
+        let kv = Quadrille::<T>::new();
+        assert!(kv.is_empty());
+        assert_eq!(kv.len(), 0);
+        let mut tx = kv.transaction();
+        tx.extend((0..20u8).map(|i| (vec![i], vec![])));
+        tx.commit().unwrap();
+        assert!(!kv.is_empty());
+        assert_eq!(kv.len(), 20);
+        let mut tx = kv.transaction();
+        tx.remove_prefix(&[]).unwrap();
+        tx.commit().unwrap();
+        assert!(kv.is_empty());
+        assert_eq!(kv.len(), 0);
+    }
+    for_each_store!(check);
+    check::<OrderedBTree<ReverseOrder>>();
+}
+
+/// Hashes only the first byte of a key, so most keys collide.
+#[derive(Default)]
+struct FirstByte(u64);
+
+impl std::hash::Hasher for FirstByte {
+    fn write(&mut self, bytes: &[u8]) {
+        if self.0 == 0 {
+            self.0 = bytes.first().map_or(1, |&b| u64::from(b) + 2);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[test]
+fn hash_map_custom_hasher() {
+    matches_btreemap::<NaiveHashMap<std::hash::BuildHasherDefault<FirstByte>>>();
+}
+
+#[test]
+fn invariant_on_key_group() {
+    let sum = |kv: &NaiveBTree| {
+        kv.scan_prefix(b"bal:")
+            .iter()
+            .map(|(_, v)| u64::from_be_bytes(v[..].try_into().unwrap()))
+            .sum::<u64>()
+    };
+    let checks = Arc::new(AtomicUsize::new(0));
+    let counted = checks.clone();
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .invariant("balances sum to total", ["bal:", "total"], move |kv| {
+            counted.fetch_add(1, Relaxed);
+            let total = kv
+                .get(b"total")
+                .map(|v| u64::from_be_bytes(v[..].try_into().unwrap()));
+            total.unwrap_or(0) == sum(kv)
+        })
+        .build();
+    let amount = |n: u64| n.to_be_bytes().to_vec();
+    let mut tx = kv.transaction();
+    tx.insert(b"bal:a".to_vec(), amount(70)).unwrap();
+    tx.insert(b"bal:b".to_vec(), amount(30)).unwrap();
+    tx.insert(b"total".to_vec(), amount(100)).unwrap();
+    tx.commit().unwrap();
+
+    let mut transfer = kv.transaction();
+    transfer.insert(b"bal:a".to_vec(), amount(50)).unwrap();
+    transfer.insert(b"bal:b".to_vec(), amount(50)).unwrap();
+    transfer.commit().unwrap();
+
+    let mut theft = kv.transaction();
+    theft.insert(b"bal:a".to_vec(), amount(0)).unwrap();
+    let err = theft.commit().err();
+    assert_eq!(
+        err,
+        Some(QuadrilleError::InvariantViolated {
+            name: "balances sum to total".into()
+        })
+    );
+    assert_eq!(kv.get(b"bal:a"), Some(amount(50)));
+
+    // writes outside the group skip the check
+    let before = checks.load(Relaxed);
+    let mut tx = kv.transaction();
+    tx.insert(b"other".to_vec(), vec![]).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(checks.load(Relaxed), before);
+}
+
+#[test]
+fn reserve_presizes_transaction() {
+    let kv = Quadrille::<NaiveHashMap>::new();
+    // reserving alone leaves nothing to publish
+    let mut tx = kv.transaction();
+    tx.reserve(500);
+    tx.commit().unwrap();
+    assert_eq!(kv.version(), 0);
+    let mut tx = kv.transaction();
+    tx.reserve(500);
+    let reserved = tx.current.capacity();
+    assert!(reserved >= 500);
+    for i in 0..500u32 {
+        tx.insert(i.to_be_bytes().to_vec(), vec![1]).unwrap();
+    }
+    assert_eq!(tx.current.capacity(), reserved);
+    tx.commit().unwrap();
+    assert_eq!(kv.len(), 500);
+    assert_eq!(kv.get(&7u32.to_be_bytes()), Some(vec![1]));
+
+    let kv = Quadrille::<SortedVecStore>::new();
+    let mut tx = kv.transaction();
+    tx.reserve(100);
+    tx.extend((0..100u8).rev().map(|i| (vec![i], vec![i])));
+    tx.commit().unwrap();
+    let keys = kv.snapshot().range_keys(Bound::Unbounded, Bound::Unbounded);
+    assert_eq!(keys, (0..100u8).map(|i| vec![i]).collect::<Vec<_>>());
+}
+
+#[test]
+fn commit_guard() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    {
+        let mut tx = kv.transaction().commit_on_drop();
+        tx.insert(b"kept".to_vec(), vec![]).unwrap();
+    }
+    assert!(kv.get(b"kept").is_some());
+
+    let unwound = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut tx = kv.transaction().commit_on_drop();
+        tx.insert(b"dropped".to_vec(), vec![]).unwrap();
+        panic!("abandon the transaction");
+    }));
+    assert!(unwound.is_err());
+    assert_eq!(kv.get(b"dropped"), None);
+
+    let mut tx = kv.transaction().commit_on_drop();
+    tx.insert(b"rolled back".to_vec(), vec![]).unwrap();
+    tx.rollback();
+    assert_eq!(kv.get(b"rolled back"), None);
+
+    let failed = Arc::new(std::sync::Mutex::new(None));
+    let seen = failed.clone();
+    {
+        let mut tx = kv
+            .transaction()
+            .commit_on_drop()
+            .on_error(move |err| *seen.lock().unwrap() = Some(err));
+        tx.insert(b"kept".to_vec(), b"ours".to_vec()).unwrap();
+        let mut rival = kv.transaction();
+        rival.insert(b"kept".to_vec(), b"theirs".to_vec()).unwrap();
+        rival.commit().unwrap();
+    }
+    assert_eq!(*failed.lock().unwrap(), Some(QuadrilleError::KeyConflict));
+}
+
+#[test]
+fn get_stale_serves_cached_snapshot() {
+    let clock = TestClock::new();
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .clock(clock.clone())
+        .build();
+    let max_age = std::time::Duration::from_secs(1);
+    let set = |val: &[u8]| {
+        let mut tx = kv.transaction();
+        tx.insert(b"k".to_vec(), val.to_vec()).unwrap();
+        tx.commit().unwrap();
+    };
+    set(b"old");
+    assert_eq!(kv.get_stale(b"k", max_age), Some(b"old".to_vec()));
+    set(b"new");
+    clock.advance(max_age / 2);
+    assert_eq!(kv.get_stale(b"k", max_age), Some(b"old".to_vec()));
+    clock.advance(max_age / 2);
+    assert_eq!(kv.get_stale(b"k", max_age), Some(b"new".to_vec()));
+
+    // a TTL passing hides the key even in the cached snapshot
+    clock.advance(max_age);
+    let mut tx = kv.transaction();
+    tx.insert_with_ttl(b"brief".to_vec(), b"v".to_vec(), max_age / 4)
+        .unwrap();
+    tx.commit().unwrap();
+    assert_eq!(kv.get_stale(b"brief", max_age), Some(b"v".to_vec()));
+    clock.advance(max_age / 2);
+    assert_eq!(kv.get_stale(b"brief", max_age), None);
+}
+
+/// A sorted-vec store whose inserts forget to keep the entries sorted, and
+/// whose resolve keeps the transaction's state.
+#[derive(Default)]
+struct Unsorted(Vec<(Vec<u8>, Vec<u8>)>);
+
+impl KVStore for Unsorted {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        let mut new = self.0.clone();
+        match new.iter_mut().find(|(k, _)| *k == key) {
+            Some(entry) => entry.1 = val,
+            None => {
+                new.push((key, val));
+                return (Unsorted(new), false);
+            }
+        }
+        (Unsorted(new), true)
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let mut new = self.0.clone();
+        new.retain(|(k, _)| k != key);
+        let found = new.len() < self.0.len();
+        (Unsorted(new), found)
+    }
+
+    fn resolve(_basis: Arc<Self>, prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        Ok(prev)
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        let mut out: Vec<_> = self
+            .0
+            .iter()
+            .filter(|(k, _)| std::ops::RangeBounds::contains(&(start, end), k.as_slice()))
+            .cloned()
+            .collect();
+        out.sort();
+        out
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        Box::new(self.0.iter().map(|(k, v)| (k.as_slice(), v.as_slice())))
+    }
+
+    fn validate_invariants(&self) -> Result<(), String> {
+        match self.0.windows(2).position(|w| w[0].0 >= w[1].0) {
+            Some(i) => Err(format!("entries {i} and {} are out of order", i + 1)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn validate_invariants() {
+    for seed in 0..4 {
+        fuzz_invariants::<NaiveBTree>(seed, 500).unwrap();
+        fuzz_invariants::<NaiveHashMap>(seed, 500).unwrap();
+        fuzz_invariants::<SortedVecStore>(seed, 500).unwrap();
+        fuzz_invariants::<Treap>(seed, 500).unwrap();
+        fuzz_invariants::<Adaptive<4>>(seed, 500).unwrap();
+    }
+    let err = fuzz_invariants::<Unsorted>(0, 500).unwrap_err();
+    assert!(err.contains("out of order"), "{err}");
+
+    // debug builds also check what conflict resolution produced
+    if cfg!(debug_assertions) {
+        let kv = Quadrille::<Unsorted>::new();
+        let mut tx = kv.transaction();
+        tx.insert(vec![2], vec![]).unwrap();
+        tx.insert(vec![1], vec![]).unwrap();
+        let mut rival = kv.transaction();
+        rival.insert(vec![3], vec![]).unwrap();
+        rival.commit().unwrap();
+        assert!(matches!(tx.commit(), Err(QuadrilleError::BrokenStore(_))));
+    }
+}
+
+#[test]
+fn range_count() {
+    fn check<T: KVStore>() {
+        let kv = Quadrille::<T>::new();
+        let mut tx = kv.transaction();
+        tx.extend((0..20u8).map(|i| (vec![i * 2], vec![])));
+        let count = |start, end| {
+            let counted = tx.range_count(start, end);
+            assert_eq!(counted, tx.range(start, end).len());
+            counted
+        };
+        use Bound::*;
+        assert_eq!(count(Unbounded, Unbounded), 20);
+        assert_eq!(count(Included(&[10]), Excluded(&[20])), 5);
+        assert_eq!(count(Excluded(&[10]), Included(&[20])), 5);
+        assert_eq!(count(Included(&[11]), Included(&[11])), 0);
+        assert_eq!(count(Included(&[10]), Included(&[10])), 1);
+        assert_eq!(count(Excluded(&[10]), Excluded(&[10])), 0);
+        assert_eq!(count(Included(&[30]), Excluded(&[10])), 0);
+        assert_eq!(count(Included(&[100]), Unbounded), 0);
+    }
+    for_each_store!(check);
+}
+
+#[test]
+fn mirrored_writes() {
+    let primary = NaiveBTree::default()
+        .insert(b"p".to_vec(), b"primary".to_vec())
+        .0;
+    let secondary = SortedVecStore::default()
+        .insert(b"p".to_vec(), b"secondary".to_vec())
+        .0;
+    let kv = Quadrille::builder()
+        .initial(Mirrored::new(primary, secondary))
+        .build();
+    assert_eq!(kv.get(b"p"), Some(b"primary".to_vec()));
+
+    let mut tx = kv.transaction();
+    tx.insert(b"a".to_vec(), b"1".to_vec()).unwrap();
+    tx.insert(b"b".to_vec(), b"2".to_vec()).unwrap();
+    tx.commit().unwrap();
+    let root = kv.snapshot().root;
+    for key in [b"a", b"b"] {
+        assert_eq!(root.primary().get(key), root.secondary().get(key));
+        assert!(root.secondary().contains_key(key));
+    }
+
+    let mut tx = kv.transaction();
+    tx.remove(b"a").unwrap();
+    tx.insert(b"p".to_vec(), b"both".to_vec()).unwrap();
+    tx.commit().unwrap();
+    let root = kv.snapshot().root;
+    assert!(!root.primary().contains_key(b"a"));
+    assert!(!root.secondary().contains_key(b"a"));
+    assert_eq!(root.secondary().get(b"p"), Some(b"both".to_vec()));
+    fuzz_invariants::<Mirrored<Treap, SortedVecStore>>(1, 300).unwrap();
+}
+
+#[test]
+fn read_set_allocation() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(b"k".to_vec(), vec![]).unwrap();
+    tx.remove(b"k").unwrap();
+    assert_eq!(tx.reads.borrow().capacity(), 0);
+    tx.commit().unwrap();
+
+    let tx = kv.transaction_readset_capacity(1000);
+    let reserved = tx.reads.borrow().capacity();
+    assert!(reserved >= 1000);
+    for i in 0..1000u32 {
+        tx.get(&i.to_be_bytes());
+    }
+    assert_eq!(tx.reads.borrow().len(), 1000);
+    assert_eq!(tx.reads.borrow().capacity(), reserved);
+}
+
+#[test]
+fn snapshot_into_iter() {
+    let kv = Quadrille::<Treap>::new();
+    let mut tx = kv.transaction();
+    tx.extend((0..10u8).rev().map(|i| (vec![i], vec![i * 2])));
+    tx.commit().unwrap();
+    let expected: Vec<_> = (0..10u8).map(|i| (vec![i], vec![i * 2])).collect();
+
+    let snapshot = kv.snapshot();
+    let mut seen = Vec::new();
+    for (k, v) in &snapshot {
+        seen.push((k, v));
+    }
+    assert_eq!(seen, expected);
+    let mut seen = Vec::new();
+    for entry in snapshot {
+        seen.push(entry);
+    }
+    assert_eq!(seen, expected);
+}
+
+#[test]
+fn merge_from_combines() {
+    let kv = Quadrille::<Treap>::new();
+    let mut tx = kv.transaction();
+    tx.insert(b"a".to_vec(), vec![5]).unwrap();
+    tx.insert(b"b".to_vec(), vec![1]).unwrap();
+    tx.commit().unwrap();
+
+    let incoming = vec![
+        (b"a".to_vec(), vec![3]),
+        (b"b".to_vec(), vec![9]),
+        (b"c".to_vec(), vec![7]),
+    ];
+    kv.merge_from(incoming.into_iter(), |_, old, new| match old {
+        Some(old) => old.max(new).to_vec(),
+        None => new.to_vec(),
+    })
+    .unwrap();
+
+    let snapshot = kv.snapshot();
+    assert_eq!(snapshot.get(b"a"), Some(vec![5]));
+    assert_eq!(snapshot.get(b"b"), Some(vec![9]));
+    assert_eq!(snapshot.get(b"c"), Some(vec![7]));
+}
+
+#[test]
+fn snapshot_get_cached() {
+    let kv = Quadrille::<Treap>::new();
+    let mut tx = kv.transaction();
+    tx.insert(b"k".to_vec(), b"value".to_vec()).unwrap();
+    tx.commit().unwrap();
+
+    let snapshot = kv.snapshot();
+    let first = snapshot.get_cached(b"k").unwrap();
+    let second = snapshot.get_cached(b"k").unwrap();
+    assert_eq!(&*first, b"value");
+    assert_eq!(first, second);
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(snapshot.get_cached(b"missing"), None);
+
+    // the cache reflects the snapshot, not later commits
+    let mut tx = kv.transaction();
+    tx.insert(b"k".to_vec(), b"other".to_vec()).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(&*snapshot.get_cached(b"k").unwrap(), b"value");
+}
+
+#[test]
+fn max_entries_rejects_new_keys() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(LastWriteWins)
+        .max_entries(2)
+        .build();
+    let mut tx = kv.transaction();
+    tx.insert(b"a".to_vec(), vec![1]).unwrap();
+    tx.insert(b"b".to_vec(), vec![2]).unwrap();
+    assert!(matches!(
+        tx.insert(b"c".to_vec(), vec![3]),
+        Err(QuadrilleError::StoreFull)
+    ));
+    assert!(tx.insert(b"a".to_vec(), vec![10]).unwrap());
+    assert!(matches!(
+        tx.insert_many([(b"b".to_vec(), vec![20]), (b"d".to_vec(), vec![4])]),
+        Err(QuadrilleError::StoreFull)
+    ));
+    tx.commit().unwrap();
+    let snapshot = kv.snapshot();
+    assert_eq!(snapshot.get(b"a"), Some(vec![10]));
+    assert_eq!(snapshot.get(b"b"), Some(vec![2]));
+    assert_eq!(snapshot.get(b"c"), None);
+
+    // resolving two transactions that each fit cannot overfill the store
+    let mut tx = kv.transaction();
+    tx.remove(b"b").unwrap();
+    tx.commit().unwrap();
+    let mut tx_a = kv.transaction();
+    let mut tx_b = kv.transaction();
+    tx_a.insert(b"x".to_vec(), vec![1]).unwrap();
+    tx_b.insert(b"y".to_vec(), vec![1]).unwrap();
+    tx_a.commit().unwrap();
+    assert!(matches!(tx_b.commit(), Err(QuadrilleError::StoreFull)));
+    assert_eq!(kv.len(), 2);
+
+    // the count follows single writes, and a rename at the bound fits
+    let mut tx = kv.transaction();
+    assert!(tx.rename_key(b"a", b"r").unwrap());
+    tx.remove(b"x").unwrap();
+    tx.insert(b"z".to_vec(), vec![1]).unwrap();
+    assert!(matches!(
+        tx.insert(b"w".to_vec(), vec![1]),
+        Err(QuadrilleError::StoreFull)
+    ));
+    tx.commit().unwrap();
+    let keys = kv.snapshot().range_keys(Bound::Unbounded, Bound::Unbounded);
+    assert_eq!(keys, [b"r".to_vec(), b"z".to_vec()]);
+}
+
+#[test]
+fn iteration_ignores_concurrent_commits() {
+    let kv = Quadrille::<Treap>::new();
+    let mut tx = kv.transaction();
+    tx.extend((0..500u32).map(|i| (i.to_be_bytes().to_vec(), vec![0])));
+    tx.commit().unwrap();
+    let expected = kv.snapshot().range(Bound::Unbounded, Bound::Unbounded);
+
+    let snapshot = kv.snapshot();
+    let tx = kv.transaction();
+    std::thread::scope(|s| {
+        let writer = s.spawn(|| {
+            for round in 1..200u32 {
+                let mut tx = kv.transaction();
+                tx.insert((round * 7 % 500).to_be_bytes().to_vec(), vec![1])
+                    .unwrap();
+                tx.remove(&(round * 13 % 500).to_be_bytes()).unwrap();
+                tx.insert((1000 + round).to_be_bytes().to_vec(), vec![2])
+                    .unwrap();
+                tx.commit().unwrap();
+            }
+        });
+        let mut seen = Vec::new();
+        for (i, entry) in snapshot.iter_ref().enumerate() {
+            if i % 50 == 0 {
+                std::thread::yield_now();
+            }
+            seen.push((entry.0.to_vec(), entry.1.to_vec()));
+        }
+        writer.join().unwrap();
+        assert_eq!(seen, expected);
+        assert_eq!((&snapshot).into_iter().collect::<Vec<_>>(), expected);
+        assert_eq!(tx.range(Bound::Unbounded, Bound::Unbounded), expected);
+    });
+    assert_ne!(
+        kv.snapshot().range(Bound::Unbounded, Bound::Unbounded),
+        expected
+    );
+}
+
+#[test]
+fn changes_since() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .retain_versions(3)
+        .build();
+    for i in 0..3u8 {
+        let mut tx = kv.transaction();
+        tx.insert(vec![i], vec![i]).unwrap();
+        tx.commit().unwrap();
+    }
+    // version 3 holds keys 0..3
+    let mut tx = kv.transaction();
+    tx.insert(vec![0], vec![10]).unwrap();
+    tx.remove(&[1]).unwrap();
+    tx.commit().unwrap();
+    let mut tx = kv.transaction();
+    tx.insert(vec![3], vec![3]).unwrap();
+    tx.insert(vec![2], vec![20]).unwrap();
+    tx.insert(vec![2], vec![2]).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(kv.version(), 5);
+
+    assert_eq!(
+        kv.changes_since(3).unwrap(),
+        [
+            (vec![0], Change::Set(vec![10])),
+            (vec![1], Change::Delete),
+            (vec![3], Change::Set(vec![3])),
+        ]
+    );
+    assert_eq!(kv.changes_since(5).unwrap(), []);
+    // applying the delta to the old state reproduces the current one
+    let mut tx = kv.transaction_from_snapshot(&kv.snapshot_at_version(3).unwrap());
+    tx.apply_patch(&kv.changes_since(3).unwrap()).unwrap();
+    assert_eq!(
+        tx.range(Bound::Unbounded, Bound::Unbounded),
+        kv.snapshot().range(Bound::Unbounded, Bound::Unbounded)
+    );
+    assert!(kv.changes_since(2).is_none());
+    assert!(kv.changes_since(6).is_none());
+}
+
+#[test]
+fn commit_async_is_send() {
+    fn assert_send<F: Send>(f: F) -> F {
+        f
+    }
+    let kv = Quadrille::<Treap>::new();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(4)
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let tasks: Vec<_> = (0..16u8)
+            .map(|i| {
+                let kv = kv.clone();
+                tokio::spawn(assert_send(async move {
+                    // declared, so commits to other keys never fail it
+                    let mut tx = kv.transaction_for(&[&[i]]);
+                    tx.insert(vec![i], vec![i]).unwrap();
+                    tx.commit_async().await
+                }))
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+    });
+    assert_eq!(kv.len(), 16);
+}
+
+#[test]
+fn estimate_conflict_probability() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(LastWriteWins)
+        .build();
+    let mut tx = kv.transaction();
+    tx.extend((0..100u8).map(|i| (vec![i], vec![0])));
+    tx.commit().unwrap();
+    let mut hot = kv.transaction();
+    for i in 0..50u8 {
+        hot.insert(vec![i], vec![1]).unwrap();
+    }
+    // no conflicts seen yet
+    assert_eq!(hot.estimate_conflict_probability(), 0.0);
+
+    for _ in 0..5 {
+        let mut tx_a = kv.transaction();
+        let mut tx_b = kv.transaction();
+        tx_a.insert(vec![0], vec![2]).unwrap();
+        tx_b.insert(vec![0], vec![3]).unwrap();
+        tx_a.commit().unwrap();
+        tx_b.commit().unwrap();
+    }
+    assert!(kv.metrics().conflicts > 0);
+
+    let mut cold = kv.transaction();
+    cold.insert(vec![99], vec![1]).unwrap();
+    let (hot, cold) = (
+        hot.estimate_conflict_probability(),
+        cold.estimate_conflict_probability(),
+    );
+    assert!(hot > cold && cold > 0.0, "{hot} vs {cold}");
+    assert!(hot <= 1.0);
+    assert_eq!(kv.transaction().estimate_conflict_probability(), 0.0);
+}
+
+#[test]
+fn multi_get_is_consistent() {
+    let kv = Quadrille::<Treap>::new();
+    let mut tx = kv.transaction();
+    tx.insert(b"a".to_vec(), vec![0]).unwrap();
+    tx.insert(b"b".to_vec(), vec![0]).unwrap();
+    tx.commit().unwrap();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for i in 1..=255u8 {
+                let mut tx = kv.transaction();
+                tx.insert(b"a".to_vec(), vec![i]).unwrap();
+                tx.insert(b"b".to_vec(), vec![i]).unwrap();
+                tx.commit().unwrap();
+            }
+        });
+        loop {
+            let vals = kv.multi_get(&[b"a", b"b", b"missing"]);
+            assert_eq!(vals[0], vals[1]);
+            assert_eq!(vals[2], None);
+            if vals[0] == Some(vec![255]) {
+                break;
+            }
+        }
+    });
+}
+
+#[test]
+fn store_builder_matches_insert() {
+    fn check<T: KVStore>() {
+        let entries: Vec<_> = (0..200u32)
+            .map(|i| {
+                (
+                    (i * 37 % 101).to_be_bytes().to_vec(),
+                    i.to_be_bytes().to_vec(),
+                )
+            })
+            .collect();
+        let mut builder = T::builder();
+        let mut replaced = 0;
+        for (key, val) in entries.iter().cloned() {
+            replaced += usize::from(builder.push(key, val));
+        }
+        let built = builder.finish();
+        let inserted = entries
+            .iter()
+            .cloned()
+            .fold(T::default(), |s, (k, v)| s.insert(k, v).0);
+        assert_eq!(replaced, 99);
+        assert_eq!(built.len(), 101);
+        assert!(built.validate_invariants().is_ok());
+        assert_eq!(
+            built.range(Bound::Unbounded, Bound::Unbounded),
+            inserted.range(Bound::Unbounded, Bound::Unbounded)
+        );
+    }
+    for_each_store!(check);
+
+    let kv: Quadrille<NaiveBTree> = (0..10u8).map(|i| (vec![i], vec![i])).collect();
+    assert_eq!(kv.len(), 10);
+    assert_eq!(kv.get(&[3]), Some(vec![3]));
+}
+
+#[test]
+fn cancelled_commit_releases_state() {
+    use std::future::Future;
+    use std::task::{Context, Waker};
+
+    let kv = Quadrille::<Treap>::new();
+    let mut tx = kv.transaction();
+    tx.insert(vec![0], vec![0]).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(vec![1], vec![1]).unwrap();
+    rival.commit().unwrap();
+    let ours = Arc::downgrade(&tx.current);
+    let basis = Arc::downgrade(&tx.basis);
+
+    // the first attempt conflicts and yields before resolving
+    let mut commit = Box::pin(tx.commit_async());
+    let mut cx = Context::from_waker(Waker::noop());
+    assert!(commit.as_mut().poll(&mut cx).is_pending());
+    drop(commit);
+
+    assert!(ours.upgrade().is_none());
+    assert!(basis.upgrade().is_none());
+    assert_eq!(kv.get(&[0]), None);
+    // only the root itself and the handle below hold the current state
+    let root = kv.inner.get();
+    assert_eq!(Arc::strong_count(&root), 2);
+    drop(root);
+
+    // a failed exchange hands the state back with no count lost to the root
+    let (marker, basis) = kv.inner.basis();
+    let next = Arc::new(basis.insert(vec![2], vec![2]).0);
+    kv.shared.replace(&kv.inner, Arc::new(Treap::default()));
+    let Err(next) = kv.inner.compare_swap(marker, next) else {
+        panic!("swapped against a replaced root");
+    };
+    assert_eq!(Arc::strong_count(&next), 1);
+}
+
+/// Entries read through [`Pulled`] iterators so far.
+static PULLED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+/// A [`NaiveBTree`] counting how many entries are read through `iter_ref`.
+#[derive(Default)]
+struct Pulled(NaiveBTree);
+
+impl KVStore for Pulled {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key)
+    }
+
+    fn insert(&self, key: Vec<u8>, val: Vec<u8>) -> (Self, bool) {
+        let (new, found) = self.0.insert(key, val);
+        (Pulled(new), found)
+    }
+
+    fn remove(&self, key: &[u8]) -> (Self, bool) {
+        let (new, found) = self.0.remove(key);
+        (Pulled(new), found)
+    }
+
+    fn resolve(_basis: Arc<Self>, _prev: Arc<Self>) -> Result<Arc<Self>, QuadrilleError> {
+        Err(QuadrilleError::KeyConflict)
+    }
+
+    fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.0.range(start, end)
+    }
+
+    fn iter_ref(&self) -> EntryIter<'_> {
+        Box::new(self.0.iter_ref().inspect(|_| {
+            PULLED.fetch_add(1, Relaxed);
+        }))
+    }
+}
+
+#[test]
+fn diff_stream() {
+    let kv = Quadrille::<Pulled>::builder().retain_versions(2).build();
+    let mut tx = kv.transaction();
+    tx.extend((0..1000u32).map(|i| (i.to_be_bytes().to_vec(), vec![0])));
+    tx.commit().unwrap();
+    let base = kv.snapshot();
+    let version = kv.version();
+    let mut tx = kv.transaction();
+    for i in (0..1000u32).step_by(3) {
+        tx.insert(i.to_be_bytes().to_vec(), vec![1]).unwrap();
+    }
+    for i in (1..1000u32).step_by(7) {
+        tx.remove(&i.to_be_bytes()).unwrap();
+    }
+    tx.insert(b"new".to_vec(), vec![2]).unwrap();
+    tx.commit().unwrap();
+
+    let pulled = PULLED.load(Relaxed);
+    let mut stream = kv.diff_stream(&base);
+    assert_eq!(
+        stream.next(),
+        Some((0u32.to_be_bytes().to_vec(), Change::Set(vec![1])))
+    );
+    // only the first entry of each state has been read
+    assert!(PULLED.load(Relaxed) - pulled <= 4);
+    let mut streamed = vec![(0u32.to_be_bytes().to_vec(), Change::Set(vec![1]))];
+    streamed.extend(stream);
+    assert_eq!(streamed, kv.changes_since(version).unwrap());
+    assert_eq!(kv.diff_stream(&kv.snapshot()).count(), 0);
+
+    // reverse-ordered stores diff in their own order
+    let kv = Quadrille::<OrderedBTree<ReverseOrder>>::builder()
+        .retain_versions(4)
+        .build();
+    let mut tx = kv.transaction();
+    tx.extend((0..5u8).map(|i| (vec![i], vec![i])));
+    tx.commit().unwrap();
+    let base = kv.snapshot();
+    let version = kv.version();
+    let mut tx = kv.transaction();
+    tx.remove(&[1]).unwrap();
+    tx.insert(vec![3], vec![30]).unwrap();
+    tx.insert(vec![9], vec![9]).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(
+        kv.diff_stream(&base).collect::<Vec<_>>(),
+        [
+            (vec![9], Change::Set(vec![9])),
+            (vec![3], Change::Set(vec![30])),
+            (vec![1], Change::Delete),
+        ]
+    );
+    assert_eq!(
+        kv.changes_since(version).unwrap(),
+        kv.diff_stream(&base).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn set_all_replaces_contents() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(LastWriteWins)
+        .build();
+    let mut tx = kv.transaction();
+    tx.extend([(b"a".to_vec(), vec![1]), (b"b".to_vec(), vec![1])]);
+    tx.insert(b"c".to_vec(), vec![1]).unwrap();
+    tx.commit().unwrap();
+
+    let desired = [(b"b".to_vec(), vec![2]), (b"d".to_vec(), vec![4])];
+    let mut tx = kv.transaction();
+    tx.insert(b"e".to_vec(), vec![5]).unwrap();
+    tx.set_all(desired.clone()).unwrap();
+    assert_eq!(tx.get(b"e"), None);
+    tx.commit().unwrap();
+    assert_eq!(
+        kv.snapshot().range(Bound::Unbounded, Bound::Unbounded),
+        desired
+    );
+
+    // even a commit to an untouched key conflicts
+    let mut tx = kv.transaction();
+    tx.set_all([(b"x".to_vec(), vec![0])]).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(b"y".to_vec(), vec![0]).unwrap();
+    rival.commit().unwrap();
+    assert_eq!(tx.commit().err(), Some(QuadrilleError::KeyConflict));
+
+    // rebasing keeps the replacement exact
+    let mut tx = kv.transaction();
+    tx.set_all([(b"x".to_vec(), vec![0])]).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(b"z".to_vec(), vec![0]).unwrap();
+    rival.commit().unwrap();
+    let Err((tx, QuadrilleError::KeyConflict)) = tx.try_commit() else {
+        panic!("expected a conflict");
+    };
+    tx.try_commit().ok().unwrap();
+    assert_eq!(
+        kv.snapshot().range(Bound::Unbounded, Bound::Unbounded),
+        [(b"x".to_vec(), vec![0])]
+    );
+}
+
+#[test]
+fn namespaces_are_separate() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let alice = kv.namespace(b"alice/".to_vec());
+    let bob = kv.namespace(b"bob/".to_vec());
+    let mut tx = alice.transaction();
+    tx.insert(b"k1".to_vec(), b"a1".to_vec()).unwrap();
+    tx.insert(b"k2".to_vec(), b"a2".to_vec()).unwrap();
+    assert_eq!(tx.get(b"k1"), Some(b"a1".to_vec()));
+    tx.commit().unwrap();
+    let mut tx = bob.transaction();
+    tx.insert(b"k1".to_vec(), b"b1".to_vec()).unwrap();
+    assert!(!tx.contains_key(b"k2"));
+    tx.commit().unwrap();
+    let mut tx = kv.transaction();
+    tx.insert(b"alice".to_vec(), vec![]).unwrap();
+    tx.insert(b"carol/k1".to_vec(), vec![]).unwrap();
+    tx.commit().unwrap();
+
+    assert_eq!(alice.get(b"k1"), Some(b"a1".to_vec()));
+    assert_eq!(bob.get(b"k1"), Some(b"b1".to_vec()));
+    assert_eq!(bob.get(b"k2"), None);
+    assert_eq!(kv.get(b"bob/k1"), Some(b"b1".to_vec()));
+    assert_eq!(
+        alice.range(Bound::Unbounded, Bound::Unbounded),
+        [
+            (b"k1".to_vec(), b"a1".to_vec()),
+            (b"k2".to_vec(), b"a2".to_vec())
+        ]
+    );
+    assert_eq!(
+        alice.range(Bound::Excluded(b"k1"), Bound::Unbounded),
+        [(b"k2".to_vec(), b"a2".to_vec())]
+    );
+    assert_eq!(bob.scan_prefix(b"k"), [(b"k1".to_vec(), b"b1".to_vec())]);
+
+    let mut tx = bob.transaction();
+    tx.remove(b"k1").unwrap();
+    assert_eq!(tx.range(Bound::Unbounded, Bound::Unbounded), []);
+    tx.commit().unwrap();
+    assert_eq!(alice.get(b"k1"), Some(b"a1".to_vec()));
+}
+
+#[test]
+fn versioned_values() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    assert_eq!(tx.get_versioned(b"k"), Ok(None));
+    assert_eq!(
+        tx.insert_versioned(b"k".to_vec(), b"v1".to_vec(), 3),
+        Err(QuadrilleError::VersionMismatch {
+            expected: 3,
+            found: 0
+        })
+    );
+    tx.insert_versioned(b"k".to_vec(), b"v1".to_vec(), 0)
+        .unwrap();
+    assert_eq!(tx.get_versioned(b"k"), Ok(Some((b"v1".to_vec(), 1))));
+    tx.commit().unwrap();
+
+    let mut stale = kv.transaction();
+    assert_eq!(stale.get_versioned(b"k"), Ok(Some((b"v1".to_vec(), 1))));
+    let mut rival = kv.transaction();
+    rival
+        .insert_versioned(b"k".to_vec(), b"v2".to_vec(), 1)
+        .unwrap();
+    rival.commit().unwrap();
+    // the stale view still sees version 1, but the commit notices the bump
+    stale
+        .insert_versioned(b"k".to_vec(), b"v3".to_vec(), 1)
+        .unwrap();
+    assert_eq!(
+        stale.commit().err(),
+        Some(QuadrilleError::VersionMismatch {
+            expected: 1,
+            found: 2
+        })
+    );
+
+    // even where reads are not checked
+    let mut stale = kv.transaction_with_isolation(Isolation::ReadCommitted);
+    stale
+        .insert_versioned(b"other".to_vec(), b"o1".to_vec(), 0)
+        .unwrap();
+    let mut rival = kv.transaction();
+    rival
+        .insert_versioned(b"other".to_vec(), b"o1".to_vec(), 0)
+        .unwrap();
+    rival.commit().unwrap();
+    assert_eq!(
+        stale.commit().err(),
+        Some(QuadrilleError::VersionMismatch {
+            expected: 0,
+            found: 1
+        })
+    );
+
+    let mut tx = kv.transaction();
+    assert_eq!(
+        tx.insert_versioned(b"k".to_vec(), b"v3".to_vec(), 1),
+        Err(QuadrilleError::VersionMismatch {
+            expected: 1,
+            found: 2
+        })
+    );
+    tx.insert_versioned(b"k".to_vec(), b"v3".to_vec(), 2)
+        .unwrap();
+    tx.commit().unwrap();
+    assert_eq!(
+        kv.transaction().get_versioned(b"k"),
+        Ok(Some((b"v3".to_vec(), 3)))
+    );
+
+    // try_commit checks them too, and keeps failing once they are stale
+    let mut tx = kv.transaction();
+    tx.insert_versioned(b"k".to_vec(), b"v4".to_vec(), 3)
+        .unwrap();
+    let mut rival = kv.transaction();
+    rival
+        .insert_versioned(b"k".to_vec(), b"r4".to_vec(), 3)
+        .unwrap();
+    rival.commit().unwrap();
+    let mismatch = QuadrilleError::VersionMismatch {
+        expected: 3,
+        found: 4,
+    };
+    let (tx, err) = tx.try_commit().err().unwrap();
+    assert_eq!(err, mismatch);
+    let (_, err) = tx.try_commit().err().unwrap();
+    assert_eq!(err, mismatch);
+    assert_eq!(
+        kv.transaction().get_versioned(b"k"),
+        Ok(Some((b"r4".to_vec(), 4)))
+    );
+
+    // a plain write buffered first does not drop the expectation
+    let mut tx = kv.transaction();
+    let stored = kv.get(b"k").unwrap();
+    tx.insert(b"k".to_vec(), stored).unwrap();
+    tx.insert_versioned(b"k".to_vec(), b"v5".to_vec(), 4)
+        .unwrap();
+    let mut rival = kv.transaction();
+    rival
+        .insert_versioned(b"k".to_vec(), b"r5".to_vec(), 4)
+        .unwrap();
+    rival.commit().unwrap();
+    assert_eq!(
+        tx.commit().err(),
+        Some(QuadrilleError::VersionMismatch {
+            expected: 4,
+            found: 5
+        })
+    );
+
+    let mut tx = kv.transaction();
+    tx.insert(b"plain".to_vec(), vec![1]).unwrap();
+    assert!(matches!(
+        tx.get_versioned(b"plain"),
+        Err(QuadrilleError::InvalidData(_))
+    ));
+}
+
+#[test]
+fn clone_into_backend() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.extend((0..300u32).map(|i| (i.to_be_bytes().to_vec(), (i * 3).to_be_bytes().to_vec())));
+    tx.commit().unwrap();
+    let all = kv.snapshot().range(Bound::Unbounded, Bound::Unbounded);
+
+    let sorted = kv.clone_into_backend::<SortedVecStore>();
+    assert_eq!(
+        sorted.snapshot().range(Bound::Unbounded, Bound::Unbounded),
+        all
+    );
+    let treap = kv.clone_into_backend::<Treap>();
+    assert_eq!(
+        treap.snapshot().range(Bound::Unbounded, Bound::Unbounded),
+        all
+    );
+    let hashed = kv.clone_into_backend::<NaiveHashMap>();
+    assert_eq!(
+        hashed.snapshot().range(Bound::Unbounded, Bound::Unbounded),
+        all
+    );
+    // the copies are independent of the source
+    let mut tx = treap.transaction();
+    tx.remove(&0u32.to_be_bytes()).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(kv.len(), 300);
+}
+
+#[test]
+fn lock_keys_serializes() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    const ROUNDS: u64 = 200;
+    std::thread::scope(|s| {
+        for _ in 0..2 {
+            s.spawn(|| {
+                for _ in 0..ROUNDS {
+                    let mut guard = kv.lock_keys(&[b"counter"]);
+                    let n = guard
+                        .get(b"counter")
+                        .map_or(0, |v| u64::from_be_bytes(v.try_into().unwrap()));
+                    std::thread::yield_now();
+                    guard
+                        .insert(b"counter".to_vec(), (n + 1).to_be_bytes().to_vec())
+                        .unwrap();
+                    guard.commit().unwrap();
+                }
+            });
+        }
+    });
+    assert_eq!(
+        kv.get(b"counter"),
+        Some((2 * ROUNDS).to_be_bytes().to_vec())
+    );
+    // waiting in line meant no commit ever had to retry
+    assert_eq!(kv.metrics().conflicts, 0);
+
+    // overlapping sets in opposite orders cannot deadlock
+    std::thread::scope(|s| {
+        for keys in [[&b"a"[..], b"b", b"c"], [b"c", b"b", b"a"]] {
+            let kv = &kv;
+            s.spawn(move || {
+                for i in 0..ROUNDS {
+                    let mut guard = kv.lock_keys(&keys);
+                    std::thread::yield_now();
+                    guard
+                        .insert(keys[0].to_vec(), i.to_be_bytes().to_vec())
+                        .unwrap();
+                    guard.commit().unwrap();
+                }
+            });
+        }
+    });
+
+    // a dropped guard rolls back and frees its keys
+    let mut guard = kv.lock_keys(&[b"x"]);
+    guard.insert(b"x".to_vec(), vec![]).unwrap();
+    assert!(guard.insert(b"y".to_vec(), vec![]).is_err());
+    drop(guard);
+    assert_eq!(kv.get(b"x"), None);
+    kv.lock_keys(&[b"x"]).commit().unwrap();
+}
+
+#[test]
+fn pending_changes() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(b"a".to_vec(), b"1".to_vec()).unwrap();
+    tx.insert(b"b".to_vec(), b"1".to_vec()).unwrap();
+    tx.insert(b"c".to_vec(), b"1".to_vec()).unwrap();
+    tx.commit().unwrap();
+
+    let mut tx = kv.transaction();
+    assert!(tx.pending_changes().is_empty());
+    tx.insert(b"a".to_vec(), b"2".to_vec()).unwrap();
+    tx.remove(b"b").unwrap();
+    tx.insert(b"d".to_vec(), b"1".to_vec()).unwrap();
+    // written back unchanged, so not pending
+    tx.insert(b"c".to_vec(), b"1".to_vec()).unwrap();
+    assert_eq!(
+        tx.pending_changes(),
+        vec![
+            (b"a".to_vec(), Change::Set(b"2".to_vec())),
+            (b"b".to_vec(), Change::Delete),
+            (b"d".to_vec(), Change::Set(b"1".to_vec())),
+        ]
+    );
+}
+
+#[test]
+fn reset_transaction() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction_with_isolation(Isolation::Snapshot);
+    tx.insert_with_ttl(b"a".to_vec(), b"1".to_vec(), Duration::from_secs(60))
+        .unwrap();
+    tx.get(b"b");
+    let mut other = kv.transaction();
+    other.insert(b"b".to_vec(), b"1".to_vec()).unwrap();
+    other.commit().unwrap();
+    let basis = Arc::downgrade(&tx.basis);
+
+    tx.reset();
+    assert!(basis.upgrade().is_none());
+    assert!(!tx.touched());
+    assert!(tx.pending_changes().is_empty());
+    assert!(tx.ttls.is_empty());
+    assert_eq!(tx.get(b"a"), None);
+    assert_eq!(tx.get(b"b"), Some(b"1".to_vec()));
+    assert_eq!(tx.isolation, Isolation::Snapshot);
+
+    tx.insert(b"c".to_vec(), b"1".to_vec()).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(kv.get(b"a"), None);
+    assert_eq!(kv.get(b"c"), Some(b"1".to_vec()));
+}
+
+#[test]
+fn equal_writes_do_not_conflict() {
+    fn race(
+        kv: &Quadrille<NaiveBTree>,
+        declared: bool,
+        ours: &[u8],
+        theirs: &[u8],
+    ) -> Result<(), QuadrilleError> {
+        let mut tx = match declared {
+            true => kv.transaction_for(&[b"k", b"mine"]),
+            false => kv.transaction(),
+        };
+        tx.insert(b"k".to_vec(), ours.to_vec()).unwrap();
+        tx.insert(b"mine".to_vec(), ours.to_vec()).unwrap();
+        let mut other = kv.transaction();
+        other.insert(b"k".to_vec(), theirs.to_vec()).unwrap();
+        other.insert(b"theirs".to_vec(), theirs.to_vec()).unwrap();
+        other.commit().unwrap();
+        tx.commit().map(drop)
+    }
+
+    /// Reapplies our writes unless the concurrent commits changed one.
+    struct RefuseClashes;
+    impl ConflictResolver<NaiveBTree> for RefuseClashes {
+        fn resolve(
+            &self,
+            _base: &NaiveBTree,
+            _theirs: &NaiveBTree,
+            _ours: &NaiveBTree,
+        ) -> Result<NaiveBTree, QuadrilleError> {
+            unreachable!("commits resolve with their changes")
+        }
+
+        fn resolve_changes(
+            &self,
+            base: &NaiveBTree,
+            theirs: &NaiveBTree,
+            ours: &NaiveBTree,
+            changes: &ChangeSet,
+        ) -> Result<NaiveBTree, QuadrilleError> {
+            if changes
+                .iter()
+                .any(|(key, _)| base.get(key) != theirs.get(key))
+            {
+                return Err(QuadrilleError::KeyConflict);
+            }
+            LastWriteWins.resolve_changes(base, theirs, ours, changes)
+        }
+    }
+
+    // a Serializable write the concurrent commit agrees with is no clash,
+    // so writes that merge elsewhere still merge
+    let kv = Quadrille::<NaiveBTree>::new();
+    race(&kv, true, b"v", b"v").unwrap();
+    assert_eq!(
+        race(&kv, true, b"v", b"w"),
+        Err(QuadrilleError::KeyConflict)
+    );
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(RefuseClashes)
+        .build();
+    race(&kv, false, b"v", b"v").unwrap();
+    assert_eq!(kv.get(b"mine"), Some(b"v".to_vec()));
+    assert_eq!(kv.get(b"theirs"), Some(b"v".to_vec()));
+    assert_eq!(
+        race(&kv, false, b"v", b"w"),
+        Err(QuadrilleError::KeyConflict)
+    );
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(b"k".to_vec(), b"v".to_vec()).unwrap();
+    let mut other = kv.transaction();
+    other.insert(b"k".to_vec(), b"v".to_vec()).unwrap();
+    other.insert(b"theirs".to_vec(), b"v".to_vec()).unwrap();
+    other.commit().unwrap();
+    tx.commit().unwrap();
+    assert_eq!(kv.get(b"theirs"), Some(b"v".to_vec()));
+
+    let snapshot = Quadrille::<NaiveBTree>::builder().build();
+    let race_snapshot = |ours: &[u8], theirs: &[u8]| {
+        let mut tx = snapshot.transaction_with_isolation(Isolation::Snapshot);
+        tx.insert(b"k".to_vec(), ours.to_vec()).unwrap();
+        tx.insert(b"mine".to_vec(), ours.to_vec()).unwrap();
+        let mut other = snapshot.transaction();
+        other.insert(b"k".to_vec(), theirs.to_vec()).unwrap();
+        other.commit().unwrap();
+        tx.commit().map(drop)
+    };
+    race_snapshot(b"v", b"v").unwrap();
+    assert_eq!(snapshot.get(b"mine"), Some(b"v".to_vec()));
+    assert_eq!(race_snapshot(b"v", b"w"), Err(QuadrilleError::KeyConflict));
+    assert_eq!(race_snapshot(b"v", b"V"), Err(QuadrilleError::KeyConflict));
+
+    let loose = Quadrille::<NaiveBTree>::builder()
+        .value_eq(|a, b| a.eq_ignore_ascii_case(b))
+        .build();
+    let mut tx = loose.transaction_with_isolation(Isolation::Snapshot);
+    tx.insert(b"k".to_vec(), b"v".to_vec()).unwrap();
+    let mut other = loose.transaction();
+    other.insert(b"k".to_vec(), b"V".to_vec()).unwrap();
+    other.commit().unwrap();
+    tx.commit().unwrap();
+    let mut tx = loose.transaction();
+    tx.insert(b"k".to_vec(), b"x".to_vec()).unwrap();
+    let mut other = loose.transaction();
+    other.insert(b"k".to_vec(), b"y".to_vec()).unwrap();
+    other.commit().unwrap();
+    assert_eq!(tx.commit().map(drop), Err(QuadrilleError::KeyConflict));
+
+    // an agreeing write the resolver never sees still counts as kept
+    let loose = Quadrille::<NaiveBTree>::builder()
+        .value_eq(|a, b| a.eq_ignore_ascii_case(b))
+        .resolver(LastWriteWins)
+        .build();
+    let mut tx = loose.transaction();
+    tx.insert(b"k".to_vec(), b"v".to_vec()).unwrap();
+    tx.insert(b"mine".to_vec(), b"v".to_vec()).unwrap();
+    let mut other = loose.transaction();
+    other.insert(b"k".to_vec(), b"V".to_vec()).unwrap();
+    other.insert(b"theirs".to_vec(), b"V".to_vec()).unwrap();
+    other.commit().unwrap();
+    tx.commit().unwrap();
+    assert_eq!(loose.get(b"k"), Some(b"V".to_vec()));
+    assert_eq!(loose.get(b"mine"), Some(b"v".to_vec()));
+
+    // a TTL on an agreeing write is still committed
+    let clock = TestClock::new();
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .clock(clock.clone())
+        .build();
+    let ttl = Duration::from_secs(60);
+    let mut tx = kv.transaction();
+    tx.insert_with_ttl(b"k".to_vec(), b"v".to_vec(), ttl)
+        .unwrap();
+    let mut other = kv.transaction();
+    other.insert(b"k".to_vec(), b"v".to_vec()).unwrap();
+    other.commit().unwrap();
+    tx.commit().unwrap();
+    assert_eq!(kv.get(b"k"), Some(b"v".to_vec()));
+    clock.advance(ttl);
+    assert_eq!(kv.get(b"k"), None);
+}
+
+#[test]
+fn in_place_writes_match_copies() {
+    fn run<T: KVStore>(copy: bool) -> std::collections::BTreeMap<Vec<u8>, Vec<u8>> {
+        let kv = Quadrille::<T>::new();
+        let mut tx = kv.transaction();
+        let mut held = Vec::new();
+        for i in 0..300u32 {
+            let key = (i * 37 % 101).to_be_bytes().to_vec();
+            if i % 3 == 2 {
+                tx.remove(&key).unwrap();
+            } else {
+                tx.insert(key, i.to_be_bytes().to_vec()).unwrap();
+            }
+            if copy {
+                // a second owner forces the next write to copy
+                held.push(tx.current.clone());
+            }
+        }
+        let unique = Arc::strong_count(&tx.current) == 1;
+        assert_eq!(unique, !copy);
+        tx.current.validate_invariants().unwrap();
+        tx.commit().unwrap();
+        // versions held along the way are left as they were
+        if let Some(first) = held.first() {
+            assert_eq!(first.len(), 1);
+        }
+        kv.snapshot()
+            .range(Bound::Unbounded, Bound::Unbounded)
+            .into_iter()
+            .collect()
+    }
+    assert_eq!(run::<NaiveBTree>(false), run::<NaiveBTree>(true));
+    assert_eq!(run::<NaiveHashMap>(false), run::<NaiveBTree>(true));
+    assert_eq!(run::<SortedVecStore>(false), run::<NaiveBTree>(true));
+    assert_eq!(run::<Treap>(false), run::<NaiveBTree>(true));
+}
+
+#[test]
+fn observe_conflicts() {
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .resolver(LastWriteWins)
+        .build();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    kv.observe_conflicts(move |info| sink.lock().unwrap().push(info.clone()));
+
+    let mut tx = kv.transaction_with_isolation(Isolation::Snapshot);
+    tx.get(b"read");
+    tx.insert(b"hot".to_vec(), b"ours".to_vec()).unwrap();
+    tx.insert(b"cold".to_vec(), b"ours".to_vec()).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(b"hot".to_vec(), b"theirs".to_vec()).unwrap();
+    rival.insert(b"read".to_vec(), b"theirs".to_vec()).unwrap();
+    rival.insert(b"other".to_vec(), b"theirs".to_vec()).unwrap();
+    rival.commit().unwrap();
+    tx.commit().unwrap();
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [ConflictInfo {
+            keys: vec![b"hot".to_vec(), b"read".to_vec()],
+            attempt: 1,
+        }]
+    );
+    assert_eq!(kv.get(b"hot"), Some(b"ours".to_vec()));
+}
+
+#[test]
+fn frozen_reads_live_commits() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let frozen = kv.freeze();
+    assert!(frozen.is_empty());
+    let mut tx = kv.transaction();
+    tx.insert(b"a".to_vec(), b"1".to_vec()).unwrap();
+    tx.insert(b"b".to_vec(), b"2".to_vec()).unwrap();
+    tx.commit().unwrap();
+
+    assert_eq!(frozen.get(b"a"), Some(b"1".to_vec()));
+    assert_eq!(frozen.len(), 2);
+    assert_eq!(frozen.version(), kv.version());
+    assert_eq!(frozen.snapshot().get(b"b"), Some(b"2".to_vec()));
+    let mut keys = Vec::new();
+    let _ = frozen.clone().for_each(|k, _| {
+        keys.push(k.to_vec());
+        ControlFlow::Continue(())
+    });
+    assert_eq!(keys, [b"a".to_vec(), b"b".to_vec()]);
+}
+
+#[test]
+fn wal_replay_verifies_checksums() {
+    use crate::wal::{encode_record, Recovery};
+
+    let set = |k: &[u8], v: &[u8]| (k.to_vec(), Change::Set(v.to_vec()));
+    let records = [
+        vec![set(b"a", b"1"), set(b"b", b"1")],
+        vec![set(b"a", b"2"), (b"b".to_vec(), Change::Delete)],
+        vec![set(b"c", b"3")],
+    ];
+    let encoded: Vec<_> = records.iter().map(|r| encode_record(r)).collect();
+    let log = encoded.concat();
+    let tail = log.len() - encoded[2].len();
+
+    let kv = Quadrille::<NaiveBTree>::new();
+    assert_eq!(kv.replay_log(&log, Recovery::Strict), Ok(log.len()));
+    assert_eq!(
+        kv.snapshot().range(Bound::Unbounded, Bound::Unbounded),
+        [
+            (b"a".to_vec(), b"2".to_vec()),
+            (b"c".to_vec(), b"3".to_vec()),
+        ]
+    );
+
+    // a flipped bit in the final record, or a final record cut short
+    let mut flipped = log.clone();
+    *flipped.last_mut().unwrap() ^= 1;
+    for torn in [&flipped[..], &log[..log.len() - 1], &log[..tail + 3]] {
+        let kv = Quadrille::<NaiveBTree>::new();
+        assert_eq!(
+            kv.replay_log(torn, Recovery::Strict),
+            Err(QuadrilleError::CorruptLog { offset: tail })
+        );
+        assert!(kv.is_empty());
+        assert_eq!(kv.replay_log(torn, Recovery::TruncateTornTail), Ok(tail));
+        assert_eq!(kv.get(b"a"), Some(b"2".to_vec()));
+        assert_eq!(kv.get(b"c"), None);
+    }
+
+    // damage with good records after it is not a torn write, whether to a
+    // payload or to the length claiming the record runs past the end
+    let second = encoded[0].len();
+    for (at, bit) in [(second + 13, 1), (second, 0x80)] {
+        let mut middle = log.clone();
+        middle[at] ^= bit;
+        let kv = Quadrille::<NaiveBTree>::new();
+        assert_eq!(
+            kv.replay_log(&middle, Recovery::TruncateTornTail),
+            Err(QuadrilleError::CorruptLog { offset: second })
+        );
+        assert!(kv.is_empty());
+    }
+}
+
+#[test]
+fn basis_get_ignores_own_writes() {
+    let kv = Quadrille::<NaiveBTree>::new();
+    let mut tx = kv.transaction();
+    tx.insert(b"a".to_vec(), b"1".to_vec()).unwrap();
+    tx.insert(b"b".to_vec(), b"1".to_vec()).unwrap();
+    tx.commit().unwrap();
+
+    let mut tx = kv.transaction();
+    tx.insert(b"a".to_vec(), b"2".to_vec()).unwrap();
+    tx.remove(b"b").unwrap();
+    tx.insert(b"c".to_vec(), b"2".to_vec()).unwrap();
+    assert_eq!(tx.get(b"a"), Some(b"2".to_vec()));
+    assert_eq!(tx.basis_get(b"a"), Some(b"1".to_vec()));
+    assert_eq!(tx.get(b"b"), None);
+    assert_eq!(tx.basis_get(b"b"), Some(b"1".to_vec()));
+    assert_eq!(tx.get(b"c"), Some(b"2".to_vec()));
+    assert_eq!(tx.basis_get(b"c"), None);
+
+    drop(tx);
+
+    // reading the basis still counts as a read
+    let mut tx = kv.transaction_for(&[b"c"]);
+    tx.basis_get(b"a");
+    tx.insert(b"c".to_vec(), b"2".to_vec()).unwrap();
+    let mut rival = kv.transaction();
+    rival.insert(b"a".to_vec(), b"3".to_vec()).unwrap();
+    rival.commit().unwrap();
+    assert_eq!(tx.commit().err(), Some(QuadrilleError::KeyConflict));
+}
+
+#[test]
+fn compact_history() {
+    let kv = Quadrille::<Treap>::builder().retain_versions(8).build();
+    for i in 0..5u8 {
+        let mut tx = kv.transaction();
+        tx.insert(vec![i], vec![i]).unwrap();
+        tx.commit().unwrap();
+    }
+    let old = Arc::downgrade(&kv.snapshot_at_version(2).unwrap().root);
+    assert_eq!(kv.compact_history(1), 5);
+    assert!(old.upgrade().is_none());
+    assert!(kv.snapshot_at_version(4).is_none());
+    assert_eq!(kv.snapshot_at_version(5).unwrap().root.len(), 5);
+    assert_eq!(kv.get(&[4]), Some(vec![4]));
+    assert_eq!(kv.compact_history(1), 0);
+
+    let mut tx = kv.transaction();
+    tx.insert(vec![5], vec![5]).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(kv.changes_since(5).unwrap().len(), 1);
+    assert_eq!(Quadrille::<Treap>::new().compact_history(0), 0);
+}
+
+#[test]
+fn approx_distinct_values() {
+    let kv = Quadrille::<CardinalityTracked<NaiveBTree>>::new();
+    assert_eq!(kv.approx_distinct_values(), 0);
+    for chunk in (0..20_000u32).collect::<Vec<_>>().chunks(1000) {
+        let mut tx = kv.transaction();
+        for &i in chunk {
+            // 20k keys over 5k distinct values
+            tx.insert(i.to_be_bytes().to_vec(), (i % 5000).to_be_bytes().to_vec())
+                .unwrap();
+        }
+        tx.commit().unwrap();
+    }
+    let estimate = kv.approx_distinct_values() as f64;
+    // within three standard errors
+    assert!(
+        (estimate / 5000.0 - 1.0).abs() < 0.1,
+        "estimated {estimate}"
+    );
+
+    // values already seen do not move the estimate
+    let before = kv.approx_distinct_values();
+    let mut tx = kv.transaction();
+    tx.insert(b"again".to_vec(), 7u32.to_be_bytes().to_vec())
+        .unwrap();
+    tx.remove(&0u32.to_be_bytes()).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(kv.approx_distinct_values(), before);
+
+    // few values are counted almost exactly
+    let small = Quadrille::builder()
+        .initial(CardinalityTracked::new(NaiveBTree::default()))
+        .build();
+    let mut tx = small.transaction();
+    for i in 0..1000u32 {
+        tx.insert(i.to_be_bytes().to_vec(), vec![(i % 50) as u8])
+            .unwrap();
+    }
+    tx.commit().unwrap();
+    assert!(small.approx_distinct_values().abs_diff(50) <= 2);
+    fuzz_invariants::<CardinalityTracked<Treap>>(2, 300).unwrap();
+}
+
+#[test]
+fn fair_commits_prevent_starvation() {
+    use std::sync::mpsc::channel;
+
+    // Commits a low priority write whose every attempt has a rival thread
+    // commit to the same key in between, for as long as the rival is let.
+    fn contested_commit(kv: Quadrille<NaiveBTree>) -> Result<(), QuadrilleError> {
+        let (ask, asked) = channel::<()>();
+        let (done, rival_done) = channel();
+        std::thread::scope(|s| {
+            let rival = s.spawn(|| {
+                for () in asked {
+                    let mut tx = kv.transaction();
+                    tx.insert(b"hot".to_vec(), b"rival".to_vec()).unwrap();
+                    tx.commit().unwrap();
+                    let _ = done.send(());
+                }
+            });
+            let mut tx = kv.transaction_with_priority(Priority::Low);
+            tx.insert(b"hot".to_vec(), b"slow".to_vec()).unwrap();
+            let result = tx.commit_if(|_| {
+                ask.send(()).unwrap();
+                // a rival held off by our turn does not commit in time
+                let _ = rival_done.recv_timeout(Duration::from_millis(50));
+                true
+            });
+            drop(ask);
+            rival.join().unwrap();
+            result.map(drop)
+        })
+    }
+
+    let starved = Quadrille::builder().resolver(LastWriteWins).build();
+    assert_eq!(contested_commit(starved), Err(QuadrilleError::RetryLimit));
+
+    let fair = Quadrille::builder()
+        .resolver(LastWriteWins)
+        .fair_commits(3)
+        .build();
+    // well within the 8 attempts a low priority commit gets
+    contested_commit(fair.clone()).unwrap();
+
+    // a commit from inside a starving one's predicate, on the thread holding
+    // the turn, goes ahead rather than waiting on itself
+    let mut tx = fair.transaction_with_priority(Priority::Low);
+    tx.insert(b"hot".to_vec(), b"slow".to_vec()).unwrap();
+    let result = tx.commit_if(|_| {
+        let mut rival = fair.transaction();
+        rival.insert(b"hot".to_vec(), b"rival".to_vec()).unwrap();
+        rival.commit().unwrap();
+        true
+    });
+    assert_eq!(result.err(), Some(QuadrilleError::RetryLimit));
+}
+
+#[test]
+fn cas_commits_like_a_transaction() {
+    let clock = TestClock::new();
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .clock(clock.clone())
+        .max_entries(1)
+        .validator(|_, changes| match changes.get(b"k") {
+            Some(Change::Set(val)) if val == b"vetoed" => {
+                Err(QuadrilleError::Rejected("no".into()))
+            }
+            _ => Ok(()),
+        })
+        .build();
+    let ttl = Duration::from_secs(60);
+    let mut tx = kv.transaction();
+    tx.insert_with_ttl(b"k".to_vec(), b"old".to_vec(), ttl)
+        .unwrap();
+    tx.commit().unwrap();
+
+    assert_eq!(
+        kv.cas(b"other", None, Some(vec![1])),
+        Err(QuadrilleError::StoreFull)
+    );
+    assert_eq!(
+        kv.cas(b"k", Some(b"old"), Some(b"vetoed".to_vec())),
+        Err(QuadrilleError::Rejected("no".into()))
+    );
+    // the new value no longer carries the old one's TTL
+    assert_eq!(kv.cas(b"k", Some(b"old"), Some(b"new".to_vec())), Ok(true));
+    clock.advance(ttl);
+    assert_eq!(kv.transaction().get(b"k"), Some(b"new".to_vec()));
+
+    let mut tx = kv.transaction();
+    tx.insert_with_ttl(b"k".to_vec(), b"brief".to_vec(), ttl)
+        .unwrap();
+    tx.commit().unwrap();
+    clock.advance(ttl);
+    // an expired value is absent
+    assert_eq!(
+        kv.cas(b"k", Some(b"brief"), Some(b"late".to_vec())),
+        Ok(false)
+    );
+    assert_eq!(kv.cas(b"k", None, Some(b"fresh".to_vec())), Ok(true));
+    assert_eq!(kv.get(b"k"), Some(b"fresh".to_vec()));
+}
+
+#[test]
+fn ttls_publish_with_the_root() {
+    let clock = TestClock::new();
+    let kv = Quadrille::<NaiveBTree>::builder()
+        .clock(clock.clone())
+        .build();
+    let ttl = Duration::from_secs(60);
+    let committed = |kv: &Quadrille<NaiveBTree>| -> Vec<Vec<u8>> {
+        let expiries = kv.shared.expiries.read().unwrap();
+        expiries.keys().cloned().collect()
+    };
+    let mut tx = kv.transaction();
+    for key in [b"a1", b"a2", b"b1", b"c1"] {
+        tx.insert_with_ttl(key.to_vec(), b"v".to_vec(), ttl)
+            .unwrap();
+    }
+    tx.insert(b"d1".to_vec(), b"v".to_vec()).unwrap();
+    tx.commit().unwrap();
+    let snapshot = kv.snapshot();
+    assert_eq!(snapshot.get(b"a1"), Some(b"v".to_vec()));
+
+    // every read outside a transaction honours expiry too
+    clock.advance(ttl);
+    assert_eq!(kv.get(b"a1"), None);
+    assert_eq!(kv.multi_get(&[b"a1", b"d1"]), [None, Some(b"v".to_vec())]);
+    assert_eq!(snapshot.get(b"a1"), None);
+    assert_eq!(snapshot.get_cached(b"a1"), None);
+    assert!(!snapshot.contains_key(b"b1"));
+    assert!(snapshot.contains_key(b"d1"));
+    assert_eq!(kv.transaction_from_snapshot(&snapshot).get(b"c1"), None);
+
+    // and every removal drops the removed keys' TTLs
+    let mut tx = kv.transaction();
+    tx.remove_prefix(b"a").unwrap();
+    tx.retain(|key, _| key != b"b1").unwrap();
+    tx.commit().unwrap();
+    assert_eq!(committed(&kv), [b"c1".to_vec()]);
+    let upper = kv.split_off(b"c");
+    assert!(committed(&kv).is_empty());
+    assert_eq!(committed(&upper), [b"c1".to_vec()]);
+    assert_eq!(upper.get(b"c1"), None);
+    upper.drain();
+    assert!(committed(&upper).is_empty());
+}
+
+#[test]
+fn set_all_keeps_store_config() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = log.clone();
+    let store = Logged::new(NaiveBTree::default(), move |e| sink.lock().unwrap().push(e));
+    let kv = Quadrille::builder().initial(store).build();
+    let mut tx = kv.transaction();
+    tx.set_all([(b"a".to_vec(), b"1".to_vec())]).unwrap();
+    tx.commit().unwrap();
+    let mut tx = kv.transaction();
+    tx.retain(|_, _| false).unwrap();
+    tx.commit().unwrap();
+
+    // the replaced and retained states still report to the sink
+    log.lock().unwrap().clear();
+    assert_eq!(kv.get(b"a"), None);
+    assert_eq!(
+        *log.lock().unwrap(),
+        [LogEvent::Get {
+            key: b"a".to_vec(),
+            found: false
+        }]
+    );
+}
@@ -0,0 +1,34 @@
+//! Internal `tracing` hooks. Without the `tracing` feature both macros expand
+//! to nothing, so their arguments are never evaluated.
+
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($($arg:tt)*) => {
+        tracing::debug!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($($arg:tt)*) => {};
+}
+
+/// Enters a span for the rest of the enclosing scope; bind the result.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($($arg:tt)*) => {
+        tracing::debug_span!($($arg)*).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($($arg:tt)*) => {
+        $crate::trace::NoSpan
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
+
+pub(crate) use {event, span};
@@ -0,0 +1,14 @@
+/// Constraints on the encoded form of a key or value type, so a store can
+/// refuse bytes that do not decode to a valid one. Enable checking with
+/// [`QuadrilleBuilder::validate_keys`] or
+/// [`QuadrilleBuilder::validate_values`]; by default nothing is checked.
+///
+/// [`QuadrilleBuilder::validate_keys`]: crate::QuadrilleBuilder::validate_keys
+/// [`QuadrilleBuilder::validate_values`]: crate::QuadrilleBuilder::validate_values
+pub trait Validate {
+    /// Checks `bytes`, describing what is wrong with them if invalid.
+    fn validate(bytes: &[u8]) -> Result<(), String>;
+}
+
+/// A type-erased [`Validate::validate`].
+pub(crate) type Check = fn(&[u8]) -> Result<(), String>;
@@ -0,0 +1,72 @@
+use crate::{KVStore, QuadrilleError, Transation};
+
+/// Bytes of the version stamp trailing every versioned value.
+const STAMP: usize = 8;
+
+fn split_stamp(stored: Vec<u8>) -> Result<(Vec<u8>, u64), QuadrilleError> {
+    let Some(at) = stored.len().checked_sub(STAMP) else {
+        return Err(QuadrilleError::InvalidData(
+            "value has no version stamp".into(),
+        ));
+    };
+    let mut val = stored;
+    let stamp = val.split_off(at);
+    let version = u64::from_be_bytes(stamp.try_into().expect("stamp is 8 bytes"));
+    Ok((val, version))
+}
+
+impl<T: KVStore> Transation<T> {
+    /// The value at `key` with its version stamp, for a key written with
+    /// [`insert_versioned`](Self::insert_versioned). Fails with
+    /// [`QuadrilleError::InvalidData`] if the stored value is too short to
+    /// carry a stamp.
+    pub fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, QuadrilleError> {
+        self.get(key).map(split_stamp).transpose()
+    }
+
+    /// Per-key compare-and-set: writes `val` at `key` if the key is still at
+    /// `expected` (0 meaning absent), bumping its version by one; otherwise
+    /// fails with [`QuadrilleError::VersionMismatch`], writing nothing.
+    ///
+    /// The version is stored as a big-endian `u64` after the value, so a
+    /// plain [`get`](Self::get) returns the value with the stamp appended.
+    /// The expected version is checked against the transaction's view of
+    /// the key, then again at commit against any concurrent commit, so if
+    /// one bumps the version first the commit fails with
+    /// [`QuadrilleError::VersionMismatch`] at any isolation level.
+    pub fn insert_versioned(
+        &mut self,
+        key: Vec<u8>,
+        val: Vec<u8>,
+        expected: u64,
+    ) -> Result<(), QuadrilleError> {
+        let found = self.get_versioned(&key)?.map_or(0, |(_, version)| version);
+        if found != expected {
+            return Err(QuadrilleError::VersionMismatch { expected, found });
+        }
+        // later writes in this transaction build on its own versions
+        self.versions.entry(key.clone()).or_insert(expected);
+        let mut stored = val;
+        stored.extend_from_slice(&(found + 1).to_be_bytes());
+        self.insert(key, stored)?;
+        Ok(())
+    }
+
+    /// Fails with [`QuadrilleError::VersionMismatch`] if a concurrent commit
+    /// moved a key [`insert_versioned`](Self::insert_versioned) expected at
+    /// some version.
+    pub(crate) fn check_versions(&self) -> Result<(), QuadrilleError> {
+        for (key, &expected) in &self.versions {
+            let expired = self
+                .committed_deadline(key)
+                .is_some_and(|d| self.shared.now() >= d);
+            let stored = self.basis.get(key).filter(|_| !expired);
+            let found = stored.map(split_stamp).transpose()?;
+            let found = found.map_or(0, |(_, version)| version);
+            if found != expected {
+                return Err(QuadrilleError::VersionMismatch { expected, found });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! A checksummed log format for committed changes, so a store can be
+//! rebuilt by replaying them.
+//!
+//! Each record holds the changes of one commit, in the form
+//! [`Transation::pending_changes`] and [`Quadrille::changes_since`] return
+//! them, framed as a header, then the payload. The header is the payload's
+//! big-endian `u32` length and CRC-32 (IEEE), followed by the CRC-32 of
+//! those eight bytes, so a damaged length is caught before it is trusted.
+//! In the payload every change is a tag byte, 1 for a set and 0 for a
+//! delete, then the key and, for a set, the value, each prefixed with its
+//! `u32` length. Appending records, e.g. to a file after each commit, is up
+//! to the caller.
+//!
+//! A crash while appending leaves the last record cut short, or with a
+//! payload whose checksum no longer matches. [`Recovery`] says whether
+//! replay should drop such a torn tail or refuse the log. A header that
+//! fails its own checksum is never taken for a torn write.
+//!
+//! [`Transation::pending_changes`]: crate::Transation::pending_changes
+
+use crate::{Change, KVStore, Quadrille, QuadrilleError};
+
+/// What replay does with a bad record at the very end of the log. A bad
+/// record with anything after it is always an error, since a crash cannot
+/// explain it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Recovery {
+    /// Any bad record fails replay with [`QuadrilleError::CorruptLog`].
+    #[default]
+    Strict,
+    /// A bad final record is taken to be a write torn by a crash and ignored,
+    /// along with the partial data after the last good record.
+    TruncateTornTail,
+}
+
+/// The changes of one commit.
+pub type Record = Vec<(Vec<u8>, Change)>;
+
+/// Length, payload checksum and header checksum, ahead of each payload.
+const HEADER: usize = 12;
+
+/// Frames `changes` as one log record.
+pub fn encode_record(changes: &[(Vec<u8>, Change)]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for (key, change) in changes {
+        match change {
+            Change::Set(val) => {
+                payload.push(1);
+                push_bytes(&mut payload, key);
+                push_bytes(&mut payload, val);
+            }
+            Change::Delete => {
+                payload.push(0);
+                push_bytes(&mut payload, key);
+            }
+        }
+    }
+    let mut record = Vec::with_capacity(HEADER + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    record.extend_from_slice(&crc32(&payload).to_be_bytes());
+    let header_crc = crc32(&record);
+    record.extend_from_slice(&header_crc.to_be_bytes());
+    record.extend_from_slice(&payload);
+    record
+}
+
+fn push_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Decodes every record in `log`, verifying each checksum. Returns the
+/// records along with the length of the log they take up, which is less
+/// than `log.len()` only if [`Recovery::TruncateTornTail`] dropped a torn
+/// final record; truncating the log to it makes it safe to append to again.
+pub fn read_records(
+    log: &[u8],
+    recovery: Recovery,
+) -> Result<(Vec<Record>, usize), QuadrilleError> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    let torn_ok = recovery == Recovery::TruncateTornTail;
+    while offset < log.len() {
+        let corrupt = Err(QuadrilleError::CorruptLog { offset });
+        let rest = &log[offset..];
+        let Some(header) = rest.get(..HEADER) else {
+            // cut short partway through the header
+            if torn_ok {
+                break;
+            }
+            return corrupt;
+        };
+        let word = |i: usize| u32::from_be_bytes(header[i..i + 4].try_into().unwrap());
+        if crc32(&header[..8]) != word(8) {
+            return corrupt;
+        }
+        let end = HEADER + word(0) as usize;
+        let Some(payload) = rest.get(HEADER..end) else {
+            // cut short partway through the payload
+            if torn_ok {
+                break;
+            }
+            return corrupt;
+        };
+        match Some(payload)
+            .filter(|p| crc32(p) == word(4))
+            .and_then(decode)
+        {
+            Some(changes) => {
+                records.push(changes);
+                offset += end;
+            }
+            // the final record, written in full but not as it should be
+            None if torn_ok && end == rest.len() => break,
+            None => return corrupt,
+        }
+    }
+    Ok((records, offset))
+}
+
+fn decode(mut payload: &[u8]) -> Option<Record> {
+    let mut changes = Vec::new();
+    while let Some((&tag, rest)) = payload.split_first() {
+        payload = rest;
+        let key = take_bytes(&mut payload)?;
+        let change = match tag {
+            0 => Change::Delete,
+            1 => Change::Set(take_bytes(&mut payload)?),
+            _ => return None,
+        };
+        changes.push((key, change));
+    }
+    Some(changes)
+}
+
+fn take_bytes(input: &mut &[u8]) -> Option<Vec<u8>> {
+    let len = u32::from_be_bytes(input.get(..4)?.try_into().unwrap()) as usize;
+    let bytes = input.get(4..4 + len)?.to_vec();
+    *input = &input[4 + len..];
+    Some(bytes)
+}
+
+impl<T: KVStore> Quadrille<T> {
+    /// Commits every record of `log` in order, one transaction per record,
+    /// returning the length of the log replayed as [`read_records`] does.
+    /// The whole log is verified before anything is committed, so a corrupt
+    /// log leaves the store unchanged.
+    pub fn replay_log(&self, log: &[u8], recovery: Recovery) -> Result<usize, QuadrilleError> {
+        let (records, len) = read_records(log, recovery)?;
+        for changes in records {
+            let mut tx = self.transaction();
+            tx.apply_patch(&changes)?;
+            tx.commit()?;
+        }
+        Ok(len)
+    }
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &b| {
+        CRC_TABLE[((crc ^ u32::from(b)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}